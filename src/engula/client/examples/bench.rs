@@ -0,0 +1,74 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Measures the throughput of concurrent transactions.
+//!
+//! Each task updates its own objects, so the throughput should scale with
+//! the number of tasks until the server runs out of cores.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use engula_client::{Collection, Universe, I64};
+
+const NUM_OPS: usize = 10000;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = "http://localhost:21716";
+    let uv = Universe::connect(url).await?;
+    let db = uv.create_database("bench").await?;
+    let co = db.create_collection::<I64>("bench").await?;
+
+    for num_tasks in [1, 2, 4, 8, 16, 32] {
+        let ops = run(co.clone(), num_tasks, false).await?;
+        println!("add: {} tasks, {:.0} ops/s", num_tasks, ops);
+    }
+    for num_tasks in [1, 2, 4, 8, 16, 32] {
+        let ops = run(co.clone(), num_tasks, true).await?;
+        println!("transfer: {} tasks, {:.0} ops/s", num_tasks, ops);
+    }
+
+    Ok(())
+}
+
+// Runs `NUM_OPS` operations over `num_tasks` tasks and returns the number of
+// operations per second. A transfer updates two objects in one transaction.
+async fn run(co: Collection<I64>, num_tasks: usize, transfer: bool) -> Result<f64> {
+    let start = Instant::now();
+    let mut handles = Vec::new();
+    for i in 0..num_tasks {
+        let co = co.clone();
+        let handle = tokio::spawn(async move {
+            let a = format!("{}-a", i);
+            let b = format!("{}-b", i);
+            for _ in 0..NUM_OPS / num_tasks {
+                if transfer {
+                    let mut txn = co.begin();
+                    txn.object(a.clone()).sub(1);
+                    txn.object(b.clone()).add(1);
+                    txn.commit().await?;
+                } else {
+                    co.object(a.clone()).add(1).await?;
+                }
+            }
+            Ok::<_, engula_client::Error>(())
+        });
+        handles.push(handle);
+    }
+    for handle in handles {
+        handle.await??;
+    }
+    Ok(NUM_OPS as f64 / start.elapsed().as_secs_f64())
+}
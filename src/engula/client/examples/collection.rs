@@ -14,6 +14,7 @@
 
 use anyhow::Result;
 use engula_client::{Any, Universe};
+use futures::TryStreamExt;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -37,5 +38,10 @@ async fn main() -> Result<()> {
     println!("a = {:?}", co.get("a").await?);
     println!("b = {:?}", co.get("b").await?);
 
+    let objects: Vec<_> = co.scan(..).try_collect().await?;
+    println!("objects = {:?}", objects);
+    let ids: Vec<_> = co.scan_ids(b"b".to_vec()..).try_collect().await?;
+    println!("ids = {:?}", ids);
+
     Ok(())
 }
@@ -42,6 +42,11 @@ async fn main() -> Result<()> {
         c.object("o").set(k3.clone(), 3).await?;
         c.object("o").delete(k2.clone()).await?;
         println!("o = {:?}", c.object("o").load().await?);
+        println!(
+            "o[k1..k3] = {:?}",
+            c.object("o").range(k1.clone()..k3.clone()).await?
+        );
+        println!("o[k3..] = {:?}", c.object("o").prefix(k3.clone()).await?);
     }
 
     {
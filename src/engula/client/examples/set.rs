@@ -0,0 +1,51 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use engula_client::{Blob, Set, Universe, I64};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = "http://localhost:21716";
+    let uv = Universe::connect(url).await?;
+    let db = uv.create_database("set").await?;
+
+    {
+        let c = db.create_collection::<Set<I64>>("set<i64>").await?;
+        println!("{}", c.name());
+        c.set("a", [1, 2, 3]).await?;
+        c.set("b", [2, 3, 4]).await?;
+        println!("a = {:?}", c.get("a").await?);
+        c.object("a").add(5).await?;
+        c.object("a").remove(1).await?;
+        println!("a = {:?}", c.object("a").load().await?);
+        println!("a.len = {:?}", c.object("a").len().await?);
+        println!("a.contains(5) = {:?}", c.object("a").contains(5).await?);
+        c.object("c").union(["a", "b"]).await?;
+        println!("a | b = {:?}", c.get("c").await?);
+        c.object("c").intersection(["a", "b"]).await?;
+        println!("a & b = {:?}", c.get("c").await?);
+    }
+
+    {
+        let c = db.create_collection::<Set<Blob>>("set<blob>").await?;
+        println!("{}", c.name());
+        let mut txn = c.object("o").begin();
+        txn.add(vec![1, 2]).add(vec![3, 4]).remove(vec![1, 2]);
+        txn.commit().await?;
+        println!("o = {:?}", c.object("o").load().await?);
+    }
+
+    Ok(())
+}
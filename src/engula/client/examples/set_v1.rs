@@ -0,0 +1,52 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use anyhow::Result;
+use engula_client::v1::{Set, Universe};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = "http://localhost:21716";
+    let uv = Universe::connect(url).await?;
+    let db = uv.create_database("set").await?;
+    let co = db.create_collection("set").await?;
+
+    co.set("a", Set::new([1, 2, 3])).await?;
+    co.set("b", Set::new([2, 3, 4])).await?;
+    let a: HashSet<i64> = co.get("a").await?;
+    println!("a = {:?}", a);
+
+    co.mutate("a", Set::add(5)).await?;
+    co.mutate("a", Set::remove(1)).await?;
+    let a: HashSet<i64> = co.get("a").await?;
+    println!("a = {:?}", a);
+
+    let len: i64 = co.select("a", Set::len()).await?;
+    println!("a.len() = {:?}", len);
+    let contains: bool = co.select("a", Set::contains(5)).await?;
+    println!("a.contains(5) = {:?}", contains);
+
+    co.mutate("c", Set::union([b"a".to_vec(), b"b".to_vec()]))
+        .await?;
+    let c: HashSet<i64> = co.get("c").await?;
+    println!("a | b = {:?}", c);
+    co.mutate("c", Set::intersection([b"a".to_vec(), b"b".to_vec()]))
+        .await?;
+    let c: HashSet<i64> = co.get("c").await?;
+    println!("a & b = {:?}", c);
+
+    Ok(())
+}
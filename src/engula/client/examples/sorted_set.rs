@@ -0,0 +1,43 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use anyhow::Result;
+use engula_client::{SortedSet, Universe};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = "http://localhost:21716";
+    let uv = Universe::connect(url).await?;
+    let db = uv.create_database("sorted_set").await?;
+    let co = db.create_collection::<SortedSet>("sorted_set").await?;
+
+    let mut txn = co.object("board").begin();
+    txn.add("a", 3.0).add("b", 1.0).add("c", 2.0);
+    txn.commit().await?;
+
+    co.object("board").incr("b", 5.0).await?;
+    println!(
+        "board.score(b) = {:?}",
+        co.object("board").score("b").await?
+    );
+    println!("board.len() = {:?}", co.object("board").len().await?);
+    let top = co.object("board").range_by_rank(-2, i64::MAX).await?;
+    println!("board.range_by_rank(-2, MAX) = {:?}", top);
+    let low = co.object("board").range_by_score(0.0, 2.5).await?;
+    println!("board.range_by_score(0.0, 2.5) = {:?}", low);
+
+    co.object("board").pop_min(1).await?;
+    println!("board = {:?}", co.get("board").await?);
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use anyhow::Result;
+use engula_client::v1::{SortedSet, Universe};
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let url = "http://localhost:21716";
+    let uv = Universe::connect(url).await?;
+    let db = uv.create_database("sorted_set").await?;
+    let co = db.create_collection("sorted_set").await?;
+
+    let mut txn = co.begin();
+    txn.mutate("board", SortedSet::add("a", 3.0));
+    txn.mutate("board", SortedSet::add("b", 1.0));
+    txn.mutate("board", SortedSet::add("c", 2.0));
+    txn.commit().await?;
+
+    co.mutate("board", SortedSet::incr("b", 5.0)).await?;
+    let score: f64 = co.select("board", SortedSet::score("b")).await?;
+    println!("board.score(b) = {:?}", score);
+
+    let len: i64 = co.select("board", SortedSet::len()).await?;
+    println!("board.len() = {:?}", len);
+    let top: Vec<(Vec<u8>, f64)> = co.select("board", SortedSet::range_by_rank(-2..)).await?;
+    println!("board.range_by_rank(-2..) = {:?}", top);
+    let low: Vec<(Vec<u8>, f64)> = co
+        .select("board", SortedSet::range_by_score(0.0..=2.5))
+        .await?;
+    println!("board.range_by_score(0.0..=2.5) = {:?}", low);
+
+    co.mutate("board", SortedSet::pop_min(1)).await?;
+    let board: Vec<(Vec<u8>, f64)> = co.get("board").await?;
+    println!("board = {:?}", board);
+
+    Ok(())
+}
@@ -76,5 +76,20 @@ async fn main() -> Result<()> {
         println!("c4[b] = {:?}", c4.get("b").await?);
     }
 
+    // Reads and writes in one transaction.
+    let txn = db.begin();
+    let (a, len) = {
+        let mut t = c1.begin_with(txn.clone());
+        let a = t.object("a").add(1).load();
+        t.commit().await?;
+        let mut t = c3.begin_with(txn.clone());
+        let len = t.object("a").push_back(3).len();
+        t.commit().await?;
+        (a, len)
+    };
+    txn.commit().await?;
+    println!("c1[a] = {:?}", a.take()?);
+    println!("c3[a].len = {:?}", len.take()?);
+
     Ok(())
 }
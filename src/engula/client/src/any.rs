@@ -12,13 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{ops::RangeBounds, time::Duration};
+
 use engula_apis::*;
+use futures::Stream;
 
-use crate::{expr::call, Client, ObjectValue, Result, Txn};
+use crate::{
+    collection::{id_range, successor},
+    expr::{call, path_expr},
+    watch_events, Client, Error, ObjectValue, Result, Txn, WatchEvent,
+};
 
 pub struct Any {
     id: Vec<u8>,
-    index: Option<Value>,
+    path: Vec<Value>,
+    ts: u64,
     dbname: String,
     coname: String,
     client: Client,
@@ -28,20 +36,59 @@ impl Any {
     pub(crate) fn new(id: Vec<u8>, dbname: String, coname: String, client: Client) -> Self {
         Self {
             id,
-            index: None,
+            path: Vec::new(),
+            ts: 0,
             dbname,
             coname,
             client,
         }
     }
 
-    pub(crate) fn index(mut self, index: impl Into<Value>) -> Self {
-        self.index = Some(index.into());
+    /// Returns the member at `index` of this object.
+    ///
+    /// Indexes can be chained to reach members of members. Missing
+    /// intermediate containers are created when a member is mutated.
+    pub fn index(mut self, index: impl Into<Value>) -> Self {
+        self.path.push(index.into());
+        self
+    }
+
+    /// Reads this object from the snapshot at `ts`.
+    pub(crate) fn at(mut self, ts: u64) -> Self {
+        self.ts = ts;
         self
     }
 
     pub fn begin(self) -> Txn {
-        Txn::new(self.id, self.dbname, self.coname, self.client)
+        Txn::new(self.id, self.dbname, self.coname, self.client).with_path(self.path)
+    }
+
+    pub async fn watch(self) -> Result<impl Stream<Item = Result<WatchEvent<Value>>> + Unpin> {
+        self.watch_as().await
+    }
+
+    pub(crate) async fn watch_as<V: ObjectValue>(
+        self,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<V>>> + Unpin> {
+        self.watch_with(V::cast_from).await
+    }
+
+    pub(crate) async fn watch_with<V>(
+        self,
+        cast: fn(Value) -> Result<V>,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<V>>> + Unpin> {
+        if !self.path.is_empty() {
+            return Err(Error::invalid_argument("watch on members is not supported"));
+        }
+        let end = successor(&self.id);
+        let req = WatchRequest {
+            dbname: self.dbname,
+            coname: self.coname,
+            start: self.id,
+            end,
+            ..Default::default()
+        };
+        watch_events(self.client, req, cast).await
     }
 
     pub async fn load(self) -> Result<Option<Value>> {
@@ -53,6 +100,11 @@ impl Any {
         Ok(())
     }
 
+    pub async fn store_with_ttl(self, value: impl Into<Value>, ttl: Duration) -> Result<()> {
+        self.call(call::store_with_ttl(value, ttl)).await?;
+        Ok(())
+    }
+
     pub async fn reset(self) -> Result<()> {
         self.call(call::reset()).await?;
         Ok(())
@@ -68,6 +120,21 @@ impl Any {
         Ok(())
     }
 
+    pub async fn mul(self, value: impl Into<Value>) -> Result<()> {
+        self.call(call::mul(value)).await?;
+        Ok(())
+    }
+
+    pub async fn min(self, value: impl Into<Value>) -> Result<()> {
+        self.call(call::min(value)).await?;
+        Ok(())
+    }
+
+    pub async fn max(self, value: impl Into<Value>) -> Result<()> {
+        self.call(call::max(value)).await?;
+        Ok(())
+    }
+
     pub async fn len(self) -> Result<Option<i64>> {
         let value = self.call(call::len()).await?;
         i64::cast_from_option(value)
@@ -88,24 +155,95 @@ impl Any {
         Ok(())
     }
 
+    pub(crate) async fn insert(self, value: impl Into<Value>) -> Result<()> {
+        self.call(call::insert(value)).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove(self, value: impl Into<Value>) -> Result<()> {
+        self.call(call::remove(value)).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn contains(self, value: impl Into<Value>) -> Result<bool> {
+        let value = self.call(call::contains(value)).await?;
+        let value = i64::cast_from_option(value)?;
+        Ok(value.unwrap_or_default() != 0)
+    }
+
+    pub(crate) async fn union(self, ids: Vec<Vec<u8>>) -> Result<()> {
+        self.call(call::union(ids)).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn intersection(self, ids: Vec<Vec<u8>>) -> Result<()> {
+        self.call(call::intersection(ids)).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn range(self, range: impl RangeBounds<Vec<u8>>) -> Result<Option<Value>> {
+        // An empty range has no fields, whatever the object is.
+        match id_range(range) {
+            Some((start, end)) => self.call(call::range(start, end)).await,
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) async fn prefix(self, prefix: Vec<u8>) -> Result<Option<Value>> {
+        self.call(call::prefix(prefix)).await
+    }
+
+    pub(crate) async fn range_by_rank(self, start: i64, end: i64) -> Result<Option<Value>> {
+        self.call(call::range_by_rank(start, end)).await
+    }
+
+    pub(crate) async fn range_by_score(self, min: f64, max: f64) -> Result<Option<Value>> {
+        self.call(call::range_by_score(min, max)).await
+    }
+
+    pub(crate) async fn pop_min(self, count: i64) -> Result<Option<Value>> {
+        self.call(call::pop_min(count)).await
+    }
+
+    pub(crate) async fn pop_max(self, count: i64) -> Result<Option<Value>> {
+        self.call(call::pop_max(count)).await
+    }
+
+    /// Loads the members at `indexes` in one request, in order.
+    pub(crate) async fn load_members(self, indexes: Vec<Value>) -> Result<Vec<Option<Value>>> {
+        let subexprs = indexes
+            .into_iter()
+            .map(|index| {
+                let mut path = self.path.clone();
+                path.push(index);
+                path_expr(path, call::load())
+            })
+            .collect();
+        let expr = Expr {
+            from: Some(expr::From::Id(self.id)),
+            subexprs,
+            ..Default::default()
+        };
+        let result = self
+            .client
+            .collection_expr_at(self.dbname, self.coname, expr, self.ts)
+            .await?;
+        Ok(result.values.into_iter().map(|v| v.into()).collect())
+    }
+
     async fn call(self, call: CallExpr) -> Result<Option<Value>> {
         let mut expr = Expr {
             from: Some(expr::From::Id(self.id)),
             ..Default::default()
         };
-        if let Some(index) = self.index {
-            let subexpr = Expr {
-                from: Some(expr::From::Index(index.into())),
-                call: Some(call),
-                ..Default::default()
-            };
-            expr.subexprs.push(subexpr);
-        } else {
+        if self.path.is_empty() {
             expr.call = Some(call);
+        } else {
+            expr.subexprs.push(path_expr(self.path, call));
         }
         let mut result = self
             .client
-            .collection_expr(self.dbname, self.coname, expr)
+            .collection_expr_at(self.dbname, self.coname, expr, self.ts)
             .await?;
         Ok(result.values.pop().and_then(|v| v.into()))
     }
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use engula_apis::*;
-use tonic::transport::Channel;
+use tonic::{transport::Channel, Streaming};
 
 use crate::{Error, Result};
 
@@ -50,10 +50,22 @@ impl Client {
         &self,
         dbname: String,
         req: CollectionTxnRequest,
+    ) -> Result<CollectionTxnResponse> {
+        self.collection_txn_at(dbname, req, 0).await
+    }
+
+    /// Executes a request on the snapshot at `ts`, or on the latest state if
+    /// `ts` is zero.
+    pub async fn collection_txn_at(
+        &self,
+        dbname: String,
+        req: CollectionTxnRequest,
+        ts: u64,
     ) -> Result<CollectionTxnResponse> {
         let req = DatabaseTxnRequest {
             name: dbname,
             requests: vec![req],
+            ts,
         };
         let mut res = self.database_txn(req).await?;
         res.responses
@@ -66,17 +78,68 @@ impl Client {
         dbname: String,
         coname: String,
         expr: Expr,
+    ) -> Result<ExprResult> {
+        self.collection_expr_at(dbname, coname, expr, 0).await
+    }
+
+    pub async fn collection_expr_at(
+        &self,
+        dbname: String,
+        coname: String,
+        expr: Expr,
+        ts: u64,
     ) -> Result<ExprResult> {
         let req = CollectionTxnRequest {
             name: coname,
             exprs: vec![expr],
+            ..Default::default()
         };
-        let mut res = self.collection_txn(dbname, req).await?;
+        let mut res = self.collection_txn_at(dbname, req, ts).await?;
         res.results
             .pop()
             .ok_or_else(|| Error::internal("missing expression result"))
     }
 
+    pub async fn collection_scan(
+        &self,
+        dbname: String,
+        coname: String,
+        scan: ScanExpr,
+        ts: u64,
+    ) -> Result<ScanResult> {
+        let req = CollectionTxnRequest {
+            name: coname,
+            scans: vec![scan],
+            ..Default::default()
+        };
+        let mut res = self.collection_txn_at(dbname, req, ts).await?;
+        res.scans
+            .pop()
+            .ok_or_else(|| Error::internal("missing scan result"))
+    }
+
+    pub async fn collection_lookup(
+        &self,
+        dbname: String,
+        coname: String,
+        lookup: LookupExpr,
+    ) -> Result<LookupResult> {
+        let req = CollectionTxnRequest {
+            name: coname,
+            lookups: vec![lookup],
+            ..Default::default()
+        };
+        let mut res = self.collection_txn_at(dbname, req, 0).await?;
+        res.lookups
+            .pop()
+            .ok_or_else(|| Error::internal("missing lookup result"))
+    }
+
+    pub async fn watch(&self, req: WatchRequest) -> Result<Streaming<WatchResponse>> {
+        let res = self.client.clone().watch(req).await?;
+        Ok(res.into_inner())
+    }
+
     pub async fn database(&self, req: DatabaseRequest) -> Result<DatabaseResponse> {
         let res = self.client.clone().database(req).await?;
         Ok(res.into_inner())
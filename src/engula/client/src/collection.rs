@@ -12,11 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{marker::PhantomData, sync::Arc};
+use std::{
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    sync::Arc,
+    time::Duration,
+};
 
 use engula_apis::*;
+use futures::{
+    stream::{self, BoxStream},
+    Stream, StreamExt, TryStreamExt,
+};
 
-use crate::{Any, Client, CollectionTxn, DatabaseTxn, Error, Object, ObjectValue, Result};
+use crate::{
+    expr::call, watch_events, Any, Client, CollectionTxn, DatabaseTxn, Error, ObjectCodec, Result,
+    WatchEvent,
+};
 
 #[derive(Clone)]
 pub struct Collection<T> {
@@ -24,7 +36,7 @@ pub struct Collection<T> {
     _marker: PhantomData<T>,
 }
 
-impl<T: Object> Collection<T> {
+impl<T: ObjectCodec> Collection<T> {
     pub(crate) fn new(coname: String, dbname: String, client: Client) -> Self {
         let inner = CollectionInner {
             dbname,
@@ -41,6 +53,10 @@ impl<T: Object> Collection<T> {
         &self.inner.coname
     }
 
+    pub(crate) fn dbname(&self) -> &str {
+        &self.inner.dbname
+    }
+
     pub async fn desc(&self) -> Result<CollectionDesc> {
         let req = DescribeCollectionRequest {
             name: self.inner.coname.clone(),
@@ -69,23 +85,196 @@ impl<T: Object> Collection<T> {
 }
 
 // Provides common interfaces for convenience.
-impl<T: Object> Collection<T> {
+impl<T: ObjectCodec> Collection<T> {
     fn any(&self, id: impl Into<Vec<u8>>) -> Any {
         self.inner.new_object(id.into())
     }
 
     pub async fn get(&self, id: impl Into<Vec<u8>>) -> Result<Option<T::Value>> {
         let value = self.any(id).load().await?;
-        T::Value::cast_from_option(value)
+        value.map(T::decode_value).transpose()
+    }
+
+    /// Returns the values of objects in the order of `ids` in one request.
+    /// Missing objects are returned as `None`.
+    pub async fn get_many<I>(&self, ids: I) -> Result<Vec<Option<T::Value>>>
+    where
+        I: IntoIterator,
+        I::Item: Into<Vec<u8>>,
+    {
+        let exprs: Vec<_> = ids
+            .into_iter()
+            .map(|id| Expr {
+                from: Some(expr::From::Id(id.into())),
+                call: Some(call::load()),
+                ..Default::default()
+            })
+            .collect();
+        let num_ids = exprs.len();
+        let req = CollectionTxnRequest {
+            name: self.inner.coname.clone(),
+            exprs,
+            ..Default::default()
+        };
+        let res = self
+            .inner
+            .client
+            .collection_txn(self.inner.dbname.clone(), req)
+            .await?;
+        if res.results.len() != num_ids {
+            return Err(Error::internal(format!(
+                "expect {} results, got {}",
+                num_ids,
+                res.results.len()
+            )));
+        }
+        res.results
+            .into_iter()
+            .map(|mut result| {
+                let value = result.values.pop().and_then(|v| v.into());
+                value.map(T::decode_value).transpose()
+            })
+            .collect()
+    }
+
+    /// Returns the value of an object as of `ts`.
+    ///
+    /// `ts` must be a timestamp within the snapshot retention window of the
+    /// database, e.g. from [`Database::snapshot`](crate::Database::snapshot).
+    pub async fn get_at(&self, id: impl Into<Vec<u8>>, ts: u64) -> Result<Option<T::Value>> {
+        let value = self.any(id).at(ts).load().await?;
+        value.map(T::decode_value).transpose()
     }
 
     pub async fn set(&self, id: impl Into<Vec<u8>>, value: impl Into<T::Value>) -> Result<()> {
-        self.any(id).store(value.into()).await
+        let value = T::encode_value(value.into())?;
+        self.any(id).store(value).await
+    }
+
+    /// Sets an object that expires after `ttl`.
+    pub async fn set_with_ttl(
+        &self,
+        id: impl Into<Vec<u8>>,
+        value: impl Into<T::Value>,
+        ttl: Duration,
+    ) -> Result<()> {
+        let value = T::encode_value(value.into())?;
+        self.any(id).store_with_ttl(value, ttl).await
     }
 
     pub async fn delete(&self, id: impl Into<Vec<u8>>) -> Result<()> {
         self.any(id).reset().await
     }
+
+    /// Returns the objects whose keys in `index` equal `key`, in id order.
+    pub async fn lookup(
+        &self,
+        index: &str,
+        key: impl Into<Value>,
+    ) -> Result<Vec<(Vec<u8>, T::Value)>> {
+        let key: Value = key.into();
+        let lookup = LookupExpr {
+            index: index.to_owned(),
+            key: Some(key.into()),
+        };
+        let res = self
+            .inner
+            .client
+            .collection_lookup(self.inner.dbname.clone(), self.inner.coname.clone(), lookup)
+            .await?;
+        res.ids
+            .into_iter()
+            .zip(res.values)
+            .map(|(id, v)| {
+                let value = v
+                    .value
+                    .ok_or_else(|| Error::internal("missing object value"))?;
+                Ok((id, T::decode_value(value)?))
+            })
+            .collect()
+    }
+
+    /// Returns a stream of objects with ids in the range, in id order.
+    pub fn scan(
+        &self,
+        range: impl RangeBounds<Vec<u8>>,
+    ) -> impl Stream<Item = Result<(Vec<u8>, T::Value)>> + Unpin {
+        self.scan_at(range, 0)
+    }
+
+    /// Returns a stream of objects with ids in the range as of `ts`, in id
+    /// order. Every page is read from the same snapshot.
+    pub fn scan_at(
+        &self,
+        range: impl RangeBounds<Vec<u8>>,
+        ts: u64,
+    ) -> impl Stream<Item = Result<(Vec<u8>, T::Value)>> + Unpin {
+        self.inner
+            .scan_pages(range, false, ts)
+            .map_ok(|res| {
+                let objects = res.ids.into_iter().zip(res.values).map(
+                    |(id, v)| -> Result<(Vec<u8>, T::Value)> {
+                        let value = v
+                            .value
+                            .ok_or_else(|| Error::internal("missing object value"))?;
+                        Ok((id, T::decode_value(value)?))
+                    },
+                );
+                stream::iter(objects)
+            })
+            .try_flatten()
+    }
+
+    /// Returns a stream of object ids in the range, in id order.
+    pub fn scan_ids(
+        &self,
+        range: impl RangeBounds<Vec<u8>>,
+    ) -> impl Stream<Item = Result<Vec<u8>>> + Unpin {
+        self.inner
+            .scan_pages(range, true, 0)
+            .map_ok(|res| stream::iter(res.ids.into_iter().map(Ok::<_, Error>)))
+            .try_flatten()
+    }
+
+    /// Returns a stream of changes to objects with ids in the range.
+    ///
+    /// Changes in the same shard of the collection arrive in commit order,
+    /// while changes in different shards of a sharded collection interleave
+    /// as they are published.
+    pub async fn watch(
+        &self,
+        range: impl RangeBounds<Vec<u8>>,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<T::Value>>> + Unpin> {
+        self.watch_from(range, 0).await
+    }
+
+    /// Returns a stream of changes to objects with ids in the range, starting
+    /// from the change with `sequence`, or from now on if it is zero.
+    ///
+    /// This resumes a watch whose stream fails because it falls behind, from
+    /// the sequence after the last delivered change. It fails with
+    /// `Error::Corrupted` if the changes are no longer retained, and with
+    /// `Error::InvalidArgument` if the range spans several shards.
+    pub async fn watch_from(
+        &self,
+        range: impl RangeBounds<Vec<u8>>,
+        sequence: u64,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<T::Value>>> + Unpin> {
+        let (start, end) = match id_range(range) {
+            Some(range) => range,
+            None => return Ok(stream::pending().right_stream()),
+        };
+        let req = WatchRequest {
+            dbname: self.inner.dbname.clone(),
+            coname: self.inner.coname.clone(),
+            start,
+            end,
+            start_sequence: sequence,
+            ..Default::default()
+        };
+        let events = watch_events(self.inner.client.clone(), req, T::decode_value).await?;
+        Ok(events.left_stream())
+    }
 }
 
 pub struct CollectionInner {
@@ -95,7 +284,7 @@ pub struct CollectionInner {
 }
 
 impl CollectionInner {
-    fn new_txn<T: Object>(&self) -> CollectionTxn<T> {
+    fn new_txn<T: ObjectCodec>(&self) -> CollectionTxn<T> {
         CollectionTxn::new(
             self.dbname.clone(),
             self.coname.clone(),
@@ -103,7 +292,7 @@ impl CollectionInner {
         )
     }
 
-    fn new_object<T: Object>(&self, id: Vec<u8>) -> T {
+    fn new_object<T: ObjectCodec>(&self, id: Vec<u8>) -> T {
         Any::new(
             id,
             self.dbname.clone(),
@@ -119,4 +308,77 @@ impl CollectionInner {
     ) -> Result<collection_response_union::Response> {
         self.client.collection_union(self.dbname.clone(), req).await
     }
+
+    fn scan_pages(
+        &self,
+        range: impl RangeBounds<Vec<u8>>,
+        ids_only: bool,
+        ts: u64,
+    ) -> BoxStream<'static, Result<ScanResult>> {
+        let scan = id_range(range).map(|(start, end)| ScanExpr {
+            start,
+            end,
+            limit: SCAN_PAGE_SIZE,
+            ids_only,
+        });
+        let dbname = self.dbname.clone();
+        let coname = self.coname.clone();
+        let client = self.client.clone();
+        stream::try_unfold(scan, move |scan| {
+            scan_page(client.clone(), dbname.clone(), coname.clone(), scan, ts)
+        })
+        .boxed()
+    }
+}
+
+async fn scan_page(
+    client: Client,
+    dbname: String,
+    coname: String,
+    scan: Option<ScanExpr>,
+    ts: u64,
+) -> Result<Option<(ScanResult, Option<ScanExpr>)>> {
+    let scan = match scan {
+        Some(scan) => scan,
+        None => return Ok(None),
+    };
+    let res = client
+        .collection_scan(dbname, coname, scan.clone(), ts)
+        .await?;
+    // A short page means that there are no more objects in the range.
+    let next = match res.ids.last() {
+        Some(last) if res.ids.len() as u64 == scan.limit => Some(ScanExpr {
+            start: successor(last),
+            ..scan
+        }),
+        _ => None,
+    };
+    Ok(Some((res, next)))
+}
+
+const SCAN_PAGE_SIZE: u64 = 128;
+
+// Converts the range to a pair of start (inclusive) and end (exclusive) ids,
+// or none if the range is empty. An empty end means that the range is
+// unbounded, so a range that ends before the empty id has no ids at all.
+pub(crate) fn id_range(range: impl RangeBounds<Vec<u8>>) -> Option<(Vec<u8>, Vec<u8>)> {
+    let start = match range.start_bound() {
+        Bound::Included(start) => start.clone(),
+        Bound::Excluded(start) => successor(start),
+        Bound::Unbounded => Vec::new(),
+    };
+    let end = match range.end_bound() {
+        Bound::Included(end) => successor(end),
+        Bound::Excluded(end) if end.is_empty() => return None,
+        Bound::Excluded(end) => end.clone(),
+        Bound::Unbounded => Vec::new(),
+    };
+    Some((start, end))
+}
+
+// Returns the smallest id that is greater than `id`.
+pub(crate) fn successor(id: &[u8]) -> Vec<u8> {
+    let mut next = id.to_owned();
+    next.push(0);
+    next
 }
@@ -12,11 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use engula_apis::*;
 
-use crate::{Client, Collection, DatabaseTxn, Error, Object, Result};
+use crate::{Client, Collection, DatabaseTxn, Error, ObjectCodec, Result, Snapshot};
 
 #[derive(Clone)]
 pub struct Database {
@@ -49,19 +49,86 @@ impl Database {
         self.inner.new_txn()
     }
 
-    pub fn collection<T: Object>(&self, name: &str) -> Collection<T> {
+    /// Returns a snapshot of the database at the current time.
+    ///
+    /// Reads from the snapshot see the transactions committed before it and
+    /// none after it, until it falls out of the retention window.
+    pub async fn snapshot(&self) -> Result<Snapshot> {
+        let req = DatabaseTxnRequest {
+            name: self.inner.name.clone(),
+            ..Default::default()
+        };
+        let res = self.inner.client.database_txn(req).await?;
+        Ok(Snapshot::new(self.inner.name.clone(), res.ts))
+    }
+
+    pub fn collection<T: ObjectCodec>(&self, name: &str) -> Collection<T> {
         self.inner.new_collection(name.to_owned())
     }
 
-    pub async fn create_collection<T: Object>(&self, name: &str) -> Result<Collection<T>> {
+    pub async fn create_collection<T: ObjectCodec>(&self, name: &str) -> Result<Collection<T>> {
+        let desc = CollectionDesc {
+            name: name.to_owned(),
+            ..Default::default()
+        };
+        self.create_collection_with_desc(desc).await
+    }
+
+    /// Creates a collection whose objects expire after `ttl` by default.
+    pub async fn create_collection_with_ttl<T: ObjectCodec>(
+        &self,
+        name: &str,
+        ttl: Duration,
+    ) -> Result<Collection<T>> {
         let desc = CollectionDesc {
             name: name.to_owned(),
+            ttl_ms: ttl.as_millis() as u64,
             ..Default::default()
         };
+        self.create_collection_with_desc(desc).await
+    }
+
+    /// Creates a collection with secondary indexes, which are looked up with
+    /// [`Collection::lookup`].
+    pub async fn create_collection_with_indexes<T: ObjectCodec>(
+        &self,
+        name: &str,
+        indexes: Vec<IndexDesc>,
+    ) -> Result<Collection<T>> {
+        let desc = CollectionDesc {
+            name: name.to_owned(),
+            indexes,
+            ..Default::default()
+        };
+        self.create_collection_with_desc(desc).await
+    }
+
+    async fn create_collection_with_desc<T: ObjectCodec>(
+        &self,
+        desc: CollectionDesc,
+    ) -> Result<Collection<T>> {
+        let name = desc.name.clone();
         let req = CreateCollectionRequest { desc: Some(desc) };
         let req = collection_request_union::Request::CreateCollection(req);
         self.inner.collection_union_call(req).await?;
-        Ok(self.collection(name))
+        Ok(self.collection(&name))
+    }
+
+    pub fn list_collections(&self) -> CollectionList {
+        CollectionList::new(self.inner.name.clone(), self.inner.client.clone())
+    }
+
+    /// Replaces the options of the collection named in `desc`.
+    pub async fn update_collection(&self, desc: CollectionDesc) -> Result<CollectionDesc> {
+        let req = UpdateCollectionRequest { desc: Some(desc) };
+        let req = collection_request_union::Request::UpdateCollection(req);
+        let res = self.inner.collection_union_call(req).await?;
+        let desc = if let collection_response_union::Response::UpdateCollection(res) = res {
+            res.desc
+        } else {
+            None
+        };
+        desc.ok_or_else(|| Error::internal("missing collection description"))
     }
 
     pub async fn delete_collection(&self, name: &str) -> Result<()> {
@@ -84,7 +151,7 @@ impl DatabaseInner {
         DatabaseTxn::new(self.name.clone(), self.client.clone())
     }
 
-    fn new_collection<T: Object>(&self, name: String) -> Collection<T> {
+    fn new_collection<T: ObjectCodec>(&self, name: String) -> Collection<T> {
         Collection::new(name, self.name.clone(), self.client.clone())
     }
 
@@ -102,3 +169,45 @@ impl DatabaseInner {
         self.client.collection_union(self.name.clone(), req).await
     }
 }
+
+pub struct CollectionList {
+    dbname: String,
+    client: Client,
+    next_page_token: String,
+    done: bool,
+}
+
+impl CollectionList {
+    fn new(dbname: String, client: Client) -> Self {
+        Self {
+            dbname,
+            client,
+            next_page_token: String::new(),
+            done: false,
+        }
+    }
+
+    /// Returns the next page of at most `size` collections, or an empty page
+    /// if there are no more collections.
+    pub async fn next_page(&mut self, size: usize) -> Result<Vec<CollectionDesc>> {
+        if self.done {
+            return Ok(Vec::new());
+        }
+        let req = ListCollectionsRequest {
+            page_size: size as u64,
+            page_token: self.next_page_token.clone(),
+        };
+        let req = collection_request_union::Request::ListCollections(req);
+        let res = self
+            .client
+            .collection_union(self.dbname.clone(), req)
+            .await?;
+        if let collection_response_union::Response::ListCollections(mut res) = res {
+            self.next_page_token = std::mem::take(&mut res.next_page_token);
+            self.done = self.next_page_token.is_empty();
+            Ok(res.descs)
+        } else {
+            Err(Error::internal("missing list collections response"))
+        }
+    }
+}
@@ -12,6 +12,8 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use engula_apis::*;
 
 macro_rules! call_expr {
@@ -27,6 +29,12 @@ macro_rules! call_expr {
             args: vec![$arg0.into()],
         }
     };
+    ($func:expr, $arg0:expr, $arg1:expr) => {
+        CallExpr {
+            func: $func as i32,
+            args: vec![$arg0.into(), $arg1.into()],
+        }
+    };
 }
 
 pub fn load() -> CallExpr {
@@ -37,6 +45,10 @@ pub fn store(value: impl Into<Value>) -> CallExpr {
     call_expr!(Function::Store, value.into())
 }
 
+pub fn store_with_ttl(value: impl Into<Value>, ttl: Duration) -> CallExpr {
+    call_expr!(Function::Store, value.into(), ttl.as_millis() as i64)
+}
+
 pub fn reset() -> CallExpr {
     call_expr!(Function::Reset)
 }
@@ -49,6 +61,18 @@ pub fn sub(value: impl Into<Value>) -> CallExpr {
     call_expr!(Function::Sub, value.into())
 }
 
+pub fn mul(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::Mul, value.into())
+}
+
+pub fn min(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::Min, value.into())
+}
+
+pub fn max(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::Max, value.into())
+}
+
 pub fn len() -> CallExpr {
     call_expr!(Function::Len)
 }
@@ -64,3 +88,103 @@ pub fn push_back(value: impl Into<Value>) -> CallExpr {
 pub fn push_front(value: impl Into<Value>) -> CallExpr {
     call_expr!(Function::PushFront, value.into())
 }
+
+pub fn if_exists() -> CallExpr {
+    call_expr!(Function::IfExists)
+}
+
+pub fn if_not_exists() -> CallExpr {
+    call_expr!(Function::IfNotExists)
+}
+
+pub fn if_eq(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::IfEq, value.into())
+}
+
+pub fn if_ne(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::IfNe, value.into())
+}
+
+pub fn if_lt(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::IfLt, value.into())
+}
+
+pub fn if_le(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::IfLe, value.into())
+}
+
+pub fn if_gt(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::IfGt, value.into())
+}
+
+pub fn if_ge(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::IfGe, value.into())
+}
+
+pub fn insert(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::Insert, value.into())
+}
+
+pub fn remove(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::Remove, value.into())
+}
+
+pub fn contains(value: impl Into<Value>) -> CallExpr {
+    call_expr!(Function::Contains, value.into())
+}
+
+pub fn range(start: Vec<u8>, end: Vec<u8>) -> CallExpr {
+    call_expr!(
+        Function::Range,
+        Value::BlobValue(start),
+        Value::BlobValue(end)
+    )
+}
+
+pub fn prefix(prefix: Vec<u8>) -> CallExpr {
+    call_expr!(Function::Prefix, Value::BlobValue(prefix))
+}
+
+pub fn range_by_rank(start: i64, end: i64) -> CallExpr {
+    call_expr!(
+        Function::RangeByRank,
+        Value::I64Value(start),
+        Value::I64Value(end)
+    )
+}
+
+pub fn range_by_score(min: f64, max: f64) -> CallExpr {
+    call_expr!(
+        Function::RangeByScore,
+        Value::F64Value(min),
+        Value::F64Value(max)
+    )
+}
+
+pub fn pop_min(count: i64) -> CallExpr {
+    call_expr!(Function::PopMin, Value::I64Value(count))
+}
+
+pub fn pop_max(count: i64) -> CallExpr {
+    call_expr!(Function::PopMax, Value::I64Value(count))
+}
+
+pub fn union(ids: Vec<Vec<u8>>) -> CallExpr {
+    CallExpr {
+        func: Function::Union as i32,
+        args: ids
+            .into_iter()
+            .map(|id| Value::BlobValue(id).into())
+            .collect(),
+    }
+}
+
+pub fn intersection(ids: Vec<Vec<u8>>) -> CallExpr {
+    CallExpr {
+        func: Function::Intersection as i32,
+        args: ids
+            .into_iter()
+            .map(|id| Value::BlobValue(id).into())
+            .collect(),
+    }
+}
@@ -13,3 +13,28 @@
 // limitations under the License.
 
 pub mod call;
+
+use engula_apis::*;
+
+/// Builds an expression that applies `call` to the member at `path`.
+///
+/// Each index in the path selects a member of the previous one, so the
+/// expression is nested once per index.
+pub fn path_expr(path: Vec<Value>, call: CallExpr) -> Expr {
+    let mut expr = Expr {
+        call: Some(call),
+        ..Default::default()
+    };
+    let mut path = path.into_iter().rev();
+    if let Some(index) = path.next() {
+        expr.from = Some(expr::From::Index(index.into()));
+    }
+    for index in path {
+        expr = Expr {
+            from: Some(expr::From::Index(index.into())),
+            subexprs: vec![expr],
+            ..Default::default()
+        };
+    }
+    expr
+}
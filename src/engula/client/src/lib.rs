@@ -19,22 +19,29 @@ mod database;
 mod error;
 mod expr;
 mod object;
+mod snapshot;
 mod txn;
 mod types;
 mod universe;
 #[allow(dead_code)]
 pub mod v1;
+mod watch;
 
+#[cfg(feature = "serde")]
+pub use self::types::{Bincode, BincodeCodec, Codec, Json, JsonCodec, Serde};
 pub use self::{
     any::Any,
     collection::Collection,
-    database::Database,
+    database::{CollectionList, Database},
     error::{Error, Result},
-    txn::{CollectionTxn, DatabaseTxn, Txn},
-    types::{Blob, List, Map, I64},
-    universe::Universe,
+    snapshot::Snapshot,
+    txn::{CollectionTxn, DatabaseTxn, Txn, TxnValue},
+    types::{Blob, List, Map, Set, SortedSet, F64, I64},
+    universe::{DatabaseList, Universe},
+    watch::WatchEvent,
 };
 pub(crate) use self::{
     client::Client,
-    object::{Object, ObjectValue},
+    object::{Object, ObjectCodec, ObjectValue},
+    watch::watch_events,
 };
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, hash::Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
 use engula_apis::*;
 
@@ -28,6 +31,35 @@ impl Object for Any {
     type Value = Value;
 }
 
+/// Converts the values of objects in a collection to and from [`Value`].
+///
+/// Every [`Object`] converts its value with [`ObjectValue`]. Objects whose
+/// values are not an [`ObjectValue`], like `Serde`, implement this directly.
+pub trait ObjectCodec: From<Any> {
+    type Txn: From<Txn>;
+    type Value;
+
+    /// Converts a value read from the collection to the value of the object.
+    fn decode_value(v: Value) -> Result<Self::Value>;
+
+    /// Converts the value of the object to a value to write to the
+    /// collection.
+    fn encode_value(v: Self::Value) -> Result<Value>;
+}
+
+impl<T: Object> ObjectCodec for T {
+    type Txn = T::Txn;
+    type Value = T::Value;
+
+    fn decode_value(v: Value) -> Result<T::Value> {
+        T::Value::cast_from(v)
+    }
+
+    fn encode_value(v: T::Value) -> Result<Value> {
+        Ok(v.into())
+    }
+}
+
 pub trait ObjectValue: Into<Value> {
     fn cast_from(v: Value) -> Result<Self>;
 
@@ -56,6 +88,16 @@ impl ObjectValue for i64 {
     }
 }
 
+impl ObjectValue for f64 {
+    fn cast_from(v: Value) -> Result<Self> {
+        if let Value::F64Value(v) = v {
+            Ok(v)
+        } else {
+            Err(Error::invalid_argument(format!("{:?} to f64", v)))
+        }
+    }
+}
+
 impl ObjectValue for Vec<u8> {
     fn cast_from(v: Value) -> Result<Self> {
         if let Value::BlobValue(v) = v {
@@ -124,3 +166,23 @@ impl<T: ObjectValue> ObjectValue for Vec<T> {
         }
     }
 }
+
+impl<T> ObjectValue for HashSet<T>
+where
+    T: ObjectValue + Eq + Hash,
+{
+    fn cast_from(v: Value) -> Result<Self> {
+        if let Value::SetValue(v) = v {
+            v.values
+                .into_iter()
+                .map(|x| {
+                    x.value
+                        .ok_or_else(|| Error::invalid_argument("missing value"))
+                        .and_then(T::cast_from)
+                })
+                .collect()
+        } else {
+            Err(Error::invalid_argument(format!("{:?} to Set", v,)))
+        }
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::RangeBounds;
+
+use futures::{stream, Stream, StreamExt};
+
+use crate::{Collection, Error, ObjectCodec, Result};
+
+/// A consistent view of a database at a point in time.
+///
+/// Reads from a snapshot are repeatable and see the effects of either all or
+/// none of the operations in a transaction.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    dbname: String,
+    ts: u64,
+}
+
+impl Snapshot {
+    pub(crate) fn new(dbname: String, ts: u64) -> Self {
+        Self { dbname, ts }
+    }
+
+    /// Returns the timestamp of this snapshot.
+    pub fn ts(&self) -> u64 {
+        self.ts
+    }
+
+    pub async fn get<T: ObjectCodec>(
+        &self,
+        co: &Collection<T>,
+        id: impl Into<Vec<u8>>,
+    ) -> Result<Option<T::Value>> {
+        self.check(co)?;
+        co.get_at(id, self.ts).await
+    }
+
+    pub fn scan<T: ObjectCodec>(
+        &self,
+        co: &Collection<T>,
+        range: impl RangeBounds<Vec<u8>>,
+    ) -> impl Stream<Item = Result<(Vec<u8>, T::Value)>> + Unpin {
+        match self.check(co) {
+            Ok(()) => co.scan_at(range, self.ts).left_stream(),
+            Err(err) => stream::iter(vec![Err(err)]).right_stream(),
+        }
+    }
+
+    fn check<T: ObjectCodec>(&self, co: &Collection<T>) -> Result<()> {
+        if co.dbname() == self.dbname {
+            Ok(())
+        } else {
+            Err(Error::invalid_argument("collection is not in the snapshot"))
+        }
+    }
+}
@@ -15,11 +15,15 @@
 use std::{
     marker::PhantomData,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use engula_apis::*;
 
-use crate::{expr::call, Client, Error, Object, Result};
+use crate::{
+    expr::{call, path_expr},
+    Client, Error, ObjectCodec, ObjectValue, Result,
+};
 
 #[derive(Clone)]
 pub struct DatabaseTxn {
@@ -28,7 +32,9 @@ pub struct DatabaseTxn {
 
 struct DatabaseTxnInner {
     handle: DatabaseTxnHandle,
-    requests: Mutex<Vec<CollectionTxnRequest>>,
+    requests: Mutex<Vec<(CollectionTxnRequest, Vec<ValueSlots>)>>,
+    // The first error in building the transaction, which aborts it on commit.
+    error: Mutex<Option<Error>>,
 }
 
 struct DatabaseTxnHandle {
@@ -41,30 +47,43 @@ impl DatabaseTxn {
         let inner = DatabaseTxnInner {
             handle: DatabaseTxnHandle { dbname, client },
             requests: Mutex::new(Vec::new()),
+            error: Mutex::new(None),
         };
         Self {
             inner: Arc::new(inner),
         }
     }
 
-    pub(crate) fn collection<T: Object>(&self, coname: String) -> CollectionTxn<T> {
+    pub(crate) fn collection<T: ObjectCodec>(&self, coname: String) -> CollectionTxn<T> {
         CollectionTxn::new_with(coname, self.inner.clone())
     }
 
     pub async fn commit(self) -> Result<()> {
         let inner =
             Arc::try_unwrap(self.inner).map_err(|_| Error::aborted("pending transaction"))?;
+        if let Some(err) = inner.error.into_inner().unwrap() {
+            return Err(err);
+        }
         let handle = inner.handle;
+        let (requests, slots): (Vec<_>, Vec<_>) =
+            inner.requests.into_inner().unwrap().into_iter().unzip();
         let req = DatabaseTxnRequest {
             name: handle.dbname,
-            requests: inner.requests.into_inner().unwrap(),
+            requests,
+            ..Default::default()
         };
-        handle.client.database_txn(req).await?;
+        let res = handle.client.database_txn(req).await?;
+        if res.responses.len() != slots.len() {
+            return Err(Error::internal("unmatched collection responses"));
+        }
+        for (slots, res) in slots.into_iter().zip(res.responses) {
+            fill_collection_slots(slots, res)?;
+        }
         Ok(())
     }
 }
 
-pub struct CollectionTxn<T: Object> {
+pub struct CollectionTxn<T: ObjectCodec> {
     inner: Arc<CollectionTxnInner>,
     subtxn: Option<T::Txn>,
     _marker: PhantomData<T>,
@@ -74,7 +93,9 @@ struct CollectionTxnInner {
     coname: String,
     handle: Option<DatabaseTxnHandle>,
     parent: Option<Arc<DatabaseTxnInner>>,
-    exprs: Mutex<Vec<Expr>>,
+    exprs: Mutex<Vec<(Expr, ValueSlots)>>,
+    // The first error in building the transaction, which aborts it on commit.
+    error: Mutex<Option<Error>>,
 }
 
 struct CollectionTxnHandle {
@@ -83,7 +104,7 @@ struct CollectionTxnHandle {
     client: Client,
 }
 
-impl<T: Object> CollectionTxn<T> {
+impl<T: ObjectCodec> CollectionTxn<T> {
     pub(crate) fn new(dbname: String, coname: String, client: Client) -> Self {
         let handle = DatabaseTxnHandle { dbname, client };
         Self::new_inner(coname, Some(handle), None)
@@ -103,6 +124,7 @@ impl<T: Object> CollectionTxn<T> {
             handle,
             parent,
             exprs: Mutex::new(Vec::new()),
+            error: Mutex::new(None),
         };
         Self {
             inner: Arc::new(inner),
@@ -121,39 +143,78 @@ impl<T: Object> CollectionTxn<T> {
         self.subtxn.take();
         let inner =
             Arc::try_unwrap(self.inner).map_err(|_| Error::aborted("pending transaction"))?;
+        let error = inner.error.into_inner().unwrap();
+        let (exprs, slots): (Vec<_>, Vec<_>) =
+            inner.exprs.into_inner().unwrap().into_iter().unzip();
         let req = CollectionTxnRequest {
             name: inner.coname,
-            exprs: inner.exprs.into_inner().unwrap(),
+            exprs,
+            ..Default::default()
         };
+        // An error aborts the parent transaction, if any, when it commits.
+        if let Some(err) = error {
+            if let Some(parent) = inner.parent {
+                parent.error.lock().unwrap().get_or_insert(err);
+                return Ok(());
+            }
+            return Err(err);
+        }
         if let Some(handle) = inner.handle {
-            handle.client.collection_txn(handle.dbname, req).await?;
+            let res = handle.client.collection_txn(handle.dbname, req).await?;
+            fill_collection_slots(slots, res)?;
         } else {
             let parent = inner.parent.unwrap();
-            parent.requests.lock().unwrap().push(req);
+            parent.requests.lock().unwrap().push((req, slots));
         }
         Ok(())
     }
 }
 
 // Provides common interfaces for convenience.
-impl<T: Object> CollectionTxn<T> {
+impl<T: ObjectCodec> CollectionTxn<T> {
     fn txn(&self, id: impl Into<Vec<u8>>) -> Txn {
         Txn::new_with(id.into(), self.inner.clone())
     }
 
+    /// Sets an object. If the value fails to encode, the transaction fails
+    /// to commit.
     pub fn set(&mut self, id: impl Into<Vec<u8>>, value: impl Into<T::Value>) {
-        self.txn(id).store(value.into());
+        let mut txn = self.txn(id);
+        match T::encode_value(value.into()) {
+            Ok(value) => txn.store(value),
+            Err(err) => txn.fail(err),
+        };
+    }
+
+    pub fn set_with_ttl(
+        &mut self,
+        id: impl Into<Vec<u8>>,
+        value: impl Into<T::Value>,
+        ttl: Duration,
+    ) {
+        let mut txn = self.txn(id);
+        match T::encode_value(value.into()) {
+            Ok(value) => txn.store_with_ttl(value, ttl),
+            Err(err) => txn.fail(err),
+        };
     }
 
     pub fn delete(&mut self, id: impl Into<Vec<u8>>) {
         self.txn(id).reset();
     }
+
+    pub fn get(&mut self, id: impl Into<Vec<u8>>) -> TxnValue<T::Value> {
+        self.txn(id).load_with(T::decode_value)
+    }
 }
 
 pub struct Txn {
     handle: Option<CollectionTxnHandle>,
     parent: Option<Arc<CollectionTxnInner>>,
     expr: Expr,
+    slots: ValueSlots,
+    path: Vec<Value>,
+    error: Option<Error>,
 }
 
 impl Txn {
@@ -182,32 +243,91 @@ impl Txn {
                 from: Some(expr::From::Id(id)),
                 ..Default::default()
             },
+            slots: Vec::new(),
+            path: Vec::new(),
+            error: None,
         }
     }
 
+    pub(crate) fn with_path(mut self, path: Vec<Value>) -> Self {
+        self.path = path;
+        self
+    }
+
     fn add_call(&mut self, call: CallExpr) -> &mut Self {
-        let expr = Expr {
-            call: Some(call),
-            ..Default::default()
-        };
+        let expr = path_expr(self.path.clone(), call);
         self.expr.subexprs.push(expr);
         self
     }
 
     fn add_index_call(&mut self, index: impl Into<Value>, call: CallExpr) -> &mut Self {
-        let expr = Expr {
-            from: Some(expr::From::Index(index.into().into())),
-            call: Some(call),
-            ..Default::default()
-        };
+        let mut path = self.path.clone();
+        path.push(index.into());
+        let expr = path_expr(path, call);
         self.expr.subexprs.push(expr);
         self
     }
 
+    fn add_read<V: ObjectValue>(&mut self, call: CallExpr) -> TxnValue<V> {
+        self.add_call(call);
+        self.new_value()
+    }
+
+    fn add_index_read<V: ObjectValue>(
+        &mut self,
+        index: impl Into<Value>,
+        call: CallExpr,
+    ) -> TxnValue<V> {
+        self.add_index_call(index, call);
+        self.new_value()
+    }
+
+    fn new_value<V: ObjectValue>(&mut self) -> TxnValue<V> {
+        self.new_value_with(V::cast_from)
+    }
+
+    fn new_value_with<V>(&mut self, cast: fn(Value) -> Result<V>) -> TxnValue<V> {
+        let value = TxnValue::new(cast);
+        self.slots.push(value.slot.clone());
+        value
+    }
+
+    /// Aborts the transaction with `err` when it commits. Only the first
+    /// error is kept.
+    pub(crate) fn fail(&mut self, err: Error) -> &mut Self {
+        self.error.get_or_insert(err);
+        self
+    }
+
+    /// Moves to the member at `index`, so that the following calls apply to
+    /// that member.
+    pub fn index(&mut self, index: impl Into<Value>) -> &mut Self {
+        self.path.push(index.into());
+        self
+    }
+
+    /// Moves back to the object itself.
+    pub fn root(&mut self) -> &mut Self {
+        self.path.clear();
+        self
+    }
+
+    pub fn load(&mut self) -> TxnValue<Value> {
+        self.load_as()
+    }
+
+    pub fn len(&mut self) -> TxnValue<i64> {
+        self.add_read(call::len())
+    }
+
     pub fn store(&mut self, value: impl Into<Value>) -> &mut Self {
         self.add_call(call::store(value))
     }
 
+    pub fn store_with_ttl(&mut self, value: impl Into<Value>, ttl: Duration) -> &mut Self {
+        self.add_call(call::store_with_ttl(value, ttl))
+    }
+
     pub fn reset(&mut self) -> &mut Self {
         self.add_call(call::reset())
     }
@@ -220,6 +340,50 @@ impl Txn {
         self.add_call(call::sub(value))
     }
 
+    pub fn mul(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::mul(value))
+    }
+
+    pub fn min(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::min(value))
+    }
+
+    pub fn max(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::max(value))
+    }
+
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.add_call(call::if_exists())
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.add_call(call::if_not_exists())
+    }
+
+    pub fn if_eq(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::if_eq(value))
+    }
+
+    pub fn if_ne(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::if_ne(value))
+    }
+
+    pub fn if_lt(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::if_lt(value))
+    }
+
+    pub fn if_le(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::if_le(value))
+    }
+
+    pub fn if_gt(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::if_gt(value))
+    }
+
+    pub fn if_ge(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::if_ge(value))
+    }
+
     pub(crate) fn append(&mut self, value: impl Into<Value>) -> &mut Self {
         self.add_call(call::append(value))
     }
@@ -232,6 +396,35 @@ impl Txn {
         self.add_call(call::push_front(value))
     }
 
+    pub(crate) fn insert(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::insert(value))
+    }
+
+    pub(crate) fn remove(&mut self, value: impl Into<Value>) -> &mut Self {
+        self.add_call(call::remove(value))
+    }
+
+    pub(crate) fn union(&mut self, ids: Vec<Vec<u8>>) -> &mut Self {
+        self.add_call(call::union(ids))
+    }
+
+    pub(crate) fn intersection(&mut self, ids: Vec<Vec<u8>>) -> &mut Self {
+        self.add_call(call::intersection(ids))
+    }
+
+    pub(crate) fn load_as<V: ObjectValue>(&mut self) -> TxnValue<V> {
+        self.add_read(call::load())
+    }
+
+    pub(crate) fn load_with<V>(&mut self, cast: fn(Value) -> Result<V>) -> TxnValue<V> {
+        self.add_call(call::load());
+        self.new_value_with(cast)
+    }
+
+    pub(crate) fn get<V: ObjectValue>(&mut self, index: impl Into<Value>) -> TxnValue<V> {
+        self.add_index_read(index, call::load())
+    }
+
     pub(crate) fn set(&mut self, index: impl Into<Value>, value: impl Into<Value>) -> &mut Self {
         self.add_index_call(index, call::store(value))
     }
@@ -240,13 +433,34 @@ impl Txn {
         self.add_index_call(index, call::reset())
     }
 
+    pub(crate) fn insert_index(
+        &mut self,
+        index: impl Into<Value>,
+        value: impl Into<Value>,
+    ) -> &mut Self {
+        self.add_index_call(index, call::insert(value))
+    }
+
+    pub(crate) fn add_index(
+        &mut self,
+        index: impl Into<Value>,
+        value: impl Into<Value>,
+    ) -> &mut Self {
+        self.add_index_call(index, call::add(value))
+    }
+
     pub async fn commit(mut self) -> Result<()> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
         if let Some(handle) = self.handle.take() {
             let expr = std::mem::take(&mut self.expr);
-            handle
+            let slots = std::mem::take(&mut self.slots);
+            let result = handle
                 .client
                 .collection_expr(handle.dbname, handle.coname, expr)
                 .await?;
+            fill_slots(slots, result)?;
         }
         Ok(())
     }
@@ -255,8 +469,64 @@ impl Txn {
 impl Drop for Txn {
     fn drop(&mut self) {
         if let Some(parent) = self.parent.take() {
+            if let Some(err) = self.error.take() {
+                parent.error.lock().unwrap().get_or_insert(err);
+                return;
+            }
             let expr = std::mem::take(&mut self.expr);
-            parent.exprs.lock().unwrap().push(expr);
+            let slots = std::mem::take(&mut self.slots);
+            parent.exprs.lock().unwrap().push((expr, slots));
+        }
+    }
+}
+
+/// A value read by a transaction.
+///
+/// The value is available after the transaction is committed.
+pub struct TxnValue<V> {
+    slot: ValueSlot,
+    cast: fn(Value) -> Result<V>,
+}
+
+impl<V> TxnValue<V> {
+    fn new(cast: fn(Value) -> Result<V>) -> Self {
+        Self {
+            slot: Arc::new(Mutex::new(None)),
+            cast,
         }
     }
+
+    pub fn take(self) -> Result<Option<V>> {
+        let value = self
+            .slot
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| Error::aborted("uncommitted transaction"))?;
+        value.map(self.cast).transpose()
+    }
+}
+
+type ValueSlot = Arc<Mutex<Option<Option<Value>>>>;
+
+type ValueSlots = Vec<ValueSlot>;
+
+fn fill_slots(slots: ValueSlots, result: ExprResult) -> Result<()> {
+    if result.values.len() != slots.len() {
+        return Err(Error::internal("unmatched expression results"));
+    }
+    for (slot, value) in slots.into_iter().zip(result.values) {
+        *slot.lock().unwrap() = Some(value.value);
+    }
+    Ok(())
+}
+
+fn fill_collection_slots(slots: Vec<ValueSlots>, res: CollectionTxnResponse) -> Result<()> {
+    if res.results.len() != slots.len() {
+        return Err(Error::internal("unmatched expression results"));
+    }
+    for (slots, result) in slots.into_iter().zip(res.results) {
+        fill_slots(slots, result)?;
+    }
+    Ok(())
 }
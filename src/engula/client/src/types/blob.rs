@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{Any, Object, ObjectValue, Result, Txn};
+use futures::Stream;
+
+use crate::{Any, Object, ObjectValue, Result, Txn, TxnValue, WatchEvent};
 
 pub struct Blob(Any);
 
@@ -32,6 +34,10 @@ impl Blob {
         self.0.begin().into()
     }
 
+    pub async fn watch(self) -> Result<impl Stream<Item = Result<WatchEvent<Vec<u8>>>> + Unpin> {
+        self.0.watch_as().await
+    }
+
     pub async fn load(self) -> Result<Option<Vec<u8>>> {
         let value = self.0.load().await?;
         Vec::cast_from_option(value)
@@ -63,6 +69,14 @@ impl From<Txn> for BlobTxn {
 }
 
 impl BlobTxn {
+    pub fn load(&mut self) -> TxnValue<Vec<u8>> {
+        self.0.load_as()
+    }
+
+    pub fn len(&mut self) -> TxnValue<i64> {
+        self.0.len()
+    }
+
     pub fn store(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
         self.0.store(value.into());
         self
@@ -78,6 +92,46 @@ impl BlobTxn {
         self
     }
 
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.0.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.0.if_not_exists();
+        self
+    }
+
+    pub fn if_eq(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.if_eq(value.into());
+        self
+    }
+
+    pub fn if_ne(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.if_ne(value.into());
+        self
+    }
+
+    pub fn if_lt(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.if_lt(value.into());
+        self
+    }
+
+    pub fn if_le(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.if_le(value.into());
+        self
+    }
+
+    pub fn if_gt(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.if_gt(value.into());
+        self
+    }
+
+    pub fn if_ge(&mut self, value: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.if_ge(value.into());
+        self
+    }
+
     pub async fn commit(self) -> Result<()> {
         self.0.commit().await
     }
@@ -0,0 +1,166 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use futures::Stream;
+
+use crate::{Any, Object, ObjectValue, Result, Txn, TxnValue, WatchEvent};
+
+pub struct F64(Any);
+
+impl Object for F64 {
+    type Txn = F64Txn;
+    type Value = f64;
+}
+
+impl From<Any> for F64 {
+    fn from(ob: Any) -> Self {
+        Self(ob)
+    }
+}
+
+impl F64 {
+    pub fn begin(self) -> F64Txn {
+        self.0.begin().into()
+    }
+
+    pub async fn watch(self) -> Result<impl Stream<Item = Result<WatchEvent<f64>>> + Unpin> {
+        self.0.watch_as().await
+    }
+
+    pub async fn load(self) -> Result<Option<f64>> {
+        let value = self.0.load().await?;
+        f64::cast_from_option(value)
+    }
+
+    pub async fn store(self, value: f64) -> Result<()> {
+        self.0.store(value).await
+    }
+
+    pub async fn reset(self) -> Result<()> {
+        self.0.reset().await
+    }
+
+    pub async fn add(self, value: f64) -> Result<()> {
+        self.0.add(value).await
+    }
+
+    pub async fn sub(self, value: f64) -> Result<()> {
+        self.0.sub(value).await
+    }
+
+    pub async fn mul(self, value: f64) -> Result<()> {
+        self.0.mul(value).await
+    }
+
+    pub async fn min(self, value: f64) -> Result<()> {
+        self.0.min(value).await
+    }
+
+    pub async fn max(self, value: f64) -> Result<()> {
+        self.0.max(value).await
+    }
+}
+
+pub struct F64Txn(Txn);
+
+impl From<Txn> for F64Txn {
+    fn from(txn: Txn) -> Self {
+        Self(txn)
+    }
+}
+
+impl F64Txn {
+    pub fn load(&mut self) -> TxnValue<f64> {
+        self.0.load_as()
+    }
+
+    pub fn store(&mut self, value: f64) -> &mut Self {
+        self.0.store(value);
+        self
+    }
+
+    pub fn reset(&mut self) -> &mut Self {
+        self.0.reset();
+        self
+    }
+
+    pub fn add(&mut self, value: f64) -> &mut Self {
+        self.0.add(value);
+        self
+    }
+
+    pub fn sub(&mut self, value: f64) -> &mut Self {
+        self.0.sub(value);
+        self
+    }
+
+    pub fn mul(&mut self, value: f64) -> &mut Self {
+        self.0.mul(value);
+        self
+    }
+
+    pub fn min(&mut self, value: f64) -> &mut Self {
+        self.0.min(value);
+        self
+    }
+
+    pub fn max(&mut self, value: f64) -> &mut Self {
+        self.0.max(value);
+        self
+    }
+
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.0.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.0.if_not_exists();
+        self
+    }
+
+    pub fn if_eq(&mut self, value: f64) -> &mut Self {
+        self.0.if_eq(value);
+        self
+    }
+
+    pub fn if_ne(&mut self, value: f64) -> &mut Self {
+        self.0.if_ne(value);
+        self
+    }
+
+    pub fn if_lt(&mut self, value: f64) -> &mut Self {
+        self.0.if_lt(value);
+        self
+    }
+
+    pub fn if_le(&mut self, value: f64) -> &mut Self {
+        self.0.if_le(value);
+        self
+    }
+
+    pub fn if_gt(&mut self, value: f64) -> &mut Self {
+        self.0.if_gt(value);
+        self
+    }
+
+    pub fn if_ge(&mut self, value: f64) -> &mut Self {
+        self.0.if_ge(value);
+        self
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        self.0.commit().await
+    }
+}
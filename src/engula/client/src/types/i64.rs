@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{Any, Object, ObjectValue, Result, Txn};
+use futures::Stream;
+
+use crate::{Any, Object, ObjectValue, Result, Txn, TxnValue, WatchEvent};
 
 pub struct I64(Any);
 
@@ -32,6 +34,10 @@ impl I64 {
         self.0.begin().into()
     }
 
+    pub async fn watch(self) -> Result<impl Stream<Item = Result<WatchEvent<i64>>> + Unpin> {
+        self.0.watch_as().await
+    }
+
     pub async fn load(self) -> Result<Option<i64>> {
         let value = self.0.load().await?;
         i64::cast_from_option(value)
@@ -52,6 +58,18 @@ impl I64 {
     pub async fn sub(self, value: i64) -> Result<()> {
         self.0.sub(value).await
     }
+
+    pub async fn mul(self, value: i64) -> Result<()> {
+        self.0.mul(value).await
+    }
+
+    pub async fn min(self, value: i64) -> Result<()> {
+        self.0.min(value).await
+    }
+
+    pub async fn max(self, value: i64) -> Result<()> {
+        self.0.max(value).await
+    }
 }
 
 pub struct I64Txn(Txn);
@@ -63,6 +81,10 @@ impl From<Txn> for I64Txn {
 }
 
 impl I64Txn {
+    pub fn load(&mut self) -> TxnValue<i64> {
+        self.0.load_as()
+    }
+
     pub fn store(&mut self, value: i64) -> &mut Self {
         self.0.store(value);
         self
@@ -83,6 +105,61 @@ impl I64Txn {
         self
     }
 
+    pub fn mul(&mut self, value: i64) -> &mut Self {
+        self.0.mul(value);
+        self
+    }
+
+    pub fn min(&mut self, value: i64) -> &mut Self {
+        self.0.min(value);
+        self
+    }
+
+    pub fn max(&mut self, value: i64) -> &mut Self {
+        self.0.max(value);
+        self
+    }
+
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.0.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.0.if_not_exists();
+        self
+    }
+
+    pub fn if_eq(&mut self, value: i64) -> &mut Self {
+        self.0.if_eq(value);
+        self
+    }
+
+    pub fn if_ne(&mut self, value: i64) -> &mut Self {
+        self.0.if_ne(value);
+        self
+    }
+
+    pub fn if_lt(&mut self, value: i64) -> &mut Self {
+        self.0.if_lt(value);
+        self
+    }
+
+    pub fn if_le(&mut self, value: i64) -> &mut Self {
+        self.0.if_le(value);
+        self
+    }
+
+    pub fn if_gt(&mut self, value: i64) -> &mut Self {
+        self.0.if_gt(value);
+        self
+    }
+
+    pub fn if_ge(&mut self, value: i64) -> &mut Self {
+        self.0.if_ge(value);
+        self
+    }
+
     pub async fn commit(self) -> Result<()> {
         self.0.commit().await
     }
@@ -14,7 +14,9 @@
 
 use std::marker::PhantomData;
 
-use crate::{Any, Object, ObjectValue, Result, Txn};
+use futures::Stream;
+
+use crate::{Any, Object, ObjectValue, Result, Txn, TxnValue, WatchEvent};
 
 pub struct List<T> {
     ob: Any,
@@ -48,6 +50,18 @@ where
         self.ob.begin().into()
     }
 
+    /// Returns the element at `index` as an object. Negative indexes count
+    /// from the end.
+    pub fn element(self, index: i64) -> T {
+        self.ob.index(index).into()
+    }
+
+    pub async fn watch(
+        self,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<Vec<T::Value>>>> + Unpin> {
+        self.ob.watch_as().await
+    }
+
     pub async fn load(self) -> Result<Option<Vec<T::Value>>> {
         let value = self.ob.load().await?;
         Vec::cast_from_option(value)
@@ -97,6 +111,14 @@ where
     T: Object,
     Vec<T::Value>: ObjectValue,
 {
+    pub fn load(&mut self) -> TxnValue<Vec<T::Value>> {
+        self.txn.load_as()
+    }
+
+    pub fn len(&mut self) -> TxnValue<i64> {
+        self.txn.len()
+    }
+
     pub fn store(&mut self, value: impl Into<Vec<T::Value>>) -> &mut Self {
         self.txn.store(value.into());
         self
@@ -122,6 +144,16 @@ where
         self
     }
 
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.txn.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.txn.if_not_exists();
+        self
+    }
+
     pub async fn commit(self) -> Result<()> {
         self.txn.commit().await
     }
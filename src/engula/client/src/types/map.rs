@@ -12,9 +12,12 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, marker::PhantomData};
+use std::{collections::HashMap, marker::PhantomData, ops::RangeBounds};
 
-use crate::{Any, Object, ObjectValue, Result, Txn};
+use engula_apis::*;
+use futures::Stream;
+
+use crate::{Any, Error, Object, ObjectValue, Result, Txn, TxnValue, WatchEvent};
 
 pub struct Map<T> {
     ob: Any,
@@ -48,6 +51,18 @@ where
         self.ob.begin().into()
     }
 
+    /// Returns the field at `key` as an object, which creates the map and
+    /// the field on the first mutation.
+    pub fn field(self, key: impl Into<Vec<u8>>) -> T {
+        self.ob.index(key.into()).into()
+    }
+
+    pub async fn watch(
+        self,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<HashMap<Vec<u8>, T::Value>>>> + Unpin> {
+        self.ob.watch_as().await
+    }
+
     pub async fn load(self) -> Result<Option<HashMap<Vec<u8>, T::Value>>> {
         let value = self.ob.load().await?;
         HashMap::cast_from_option(value)
@@ -70,6 +85,21 @@ where
         T::Value::cast_from_option(value)
     }
 
+    /// Returns the values of fields in the order of `keys` in one request.
+    /// Missing fields are returned as `None`.
+    pub async fn get_many<I>(self, keys: I) -> Result<Vec<Option<T::Value>>>
+    where
+        I: IntoIterator,
+        I::Item: Into<Vec<u8>>,
+    {
+        let keys = keys
+            .into_iter()
+            .map(|key| Value::BlobValue(key.into()))
+            .collect();
+        let values = self.ob.load_members(keys).await?;
+        values.into_iter().map(T::Value::cast_from_option).collect()
+    }
+
     pub async fn set(self, key: impl Into<Vec<u8>>, value: impl Into<T::Value>) -> Result<()> {
         self.ob.index(key.into()).store(value.into()).await
     }
@@ -77,6 +107,18 @@ where
     pub async fn delete(self, key: impl Into<Vec<u8>>) -> Result<()> {
         self.ob.index(key.into()).reset().await
     }
+
+    /// Returns fields with keys in the range, ordered by key.
+    pub async fn range(self, range: impl RangeBounds<Vec<u8>>) -> Result<Vec<(Vec<u8>, T::Value)>> {
+        let value = self.ob.range(range).await?;
+        fields(value)
+    }
+
+    /// Returns fields with keys that start with `prefix`, ordered by key.
+    pub async fn prefix(self, prefix: impl Into<Vec<u8>>) -> Result<Vec<(Vec<u8>, T::Value)>> {
+        let value = self.ob.prefix(prefix.into()).await?;
+        fields(value)
+    }
 }
 
 pub struct MapTxn<T> {
@@ -93,7 +135,23 @@ impl<T> From<Txn> for MapTxn<T> {
     }
 }
 
-impl<T: Object> MapTxn<T> {
+impl<T> MapTxn<T>
+where
+    T: Object,
+    HashMap<Vec<u8>, T::Value>: ObjectValue,
+{
+    pub fn load(&mut self) -> TxnValue<HashMap<Vec<u8>, T::Value>> {
+        self.txn.load_as()
+    }
+
+    pub fn len(&mut self) -> TxnValue<i64> {
+        self.txn.len()
+    }
+
+    pub fn get(&mut self, key: impl Into<Vec<u8>>) -> TxnValue<T::Value> {
+        self.txn.get(key.into())
+    }
+
     pub fn store(&mut self, value: impl Into<HashMap<Vec<u8>, T::Value>>) -> &mut Self {
         self.txn.store(value.into());
         self
@@ -114,7 +172,36 @@ impl<T: Object> MapTxn<T> {
         self
     }
 
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.txn.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.txn.if_not_exists();
+        self
+    }
+
     pub async fn commit(self) -> Result<()> {
         self.txn.commit().await
     }
 }
+
+fn fields<V: ObjectValue>(value: Option<Value>) -> Result<Vec<(Vec<u8>, V)>> {
+    let value = match value {
+        Some(Value::MapValue(v)) => v,
+        Some(v) => return Err(Error::invalid_argument(format!("{:?} to Map", v))),
+        None => return Ok(Vec::new()),
+    };
+    value
+        .keys
+        .into_iter()
+        .zip(value.values)
+        .map(|(key, value)| {
+            let key = Vec::<u8>::cast_from_option(key.value)?;
+            let value = V::cast_from_option(value.value)?;
+            key.zip(value)
+                .ok_or_else(|| Error::invalid_argument("missing value"))
+        })
+        .collect()
+}
@@ -13,8 +13,17 @@
 // limitations under the License.
 
 mod blob;
+mod f64;
 mod i64;
 mod list;
 mod map;
+#[cfg(feature = "serde")]
+mod serde;
+mod set;
+mod sorted_set;
 
-pub use self::{blob::Blob, i64::I64, list::List, map::Map};
+#[cfg(feature = "serde")]
+pub use self::serde::{Bincode, BincodeCodec, Codec, Json, JsonCodec, Serde};
+pub use self::{
+    blob::Blob, f64::F64, i64::I64, list::List, map::Map, set::Set, sorted_set::SortedSet,
+};
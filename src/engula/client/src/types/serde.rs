@@ -0,0 +1,184 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::marker::PhantomData;
+
+use engula_apis::*;
+use futures::Stream;
+use serde_crate::{de::DeserializeOwned, Serialize};
+
+use crate::{Any, Error, ObjectCodec, Result, Txn, TxnValue, WatchEvent};
+
+/// Encodes values of Rust types into blobs.
+///
+/// Values that fail to encode are invalid arguments, and blobs that fail to
+/// decode are lost data.
+pub trait Codec {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>>;
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T>;
+}
+
+/// Encodes values as JSON.
+pub struct JsonCodec;
+
+impl Codec for JsonCodec {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        serde_json::to_vec(value).map_err(|err| Error::invalid_argument(err.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        serde_json::from_slice(bytes).map_err(|err| Error::data_loss(err.to_string()))
+    }
+}
+
+/// Encodes values with bincode, which is more compact than JSON but can't
+/// be read without the Rust types.
+pub struct BincodeCodec;
+
+impl Codec for BincodeCodec {
+    fn encode<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+        bincode::serialize(value).map_err(|err| Error::invalid_argument(err.to_string()))
+    }
+
+    fn decode<T: DeserializeOwned>(bytes: &[u8]) -> Result<T> {
+        bincode::deserialize(bytes).map_err(|err| Error::data_loss(err.to_string()))
+    }
+}
+
+/// An object that holds a value of `T` in a blob encoded by `C`.
+///
+/// The codec is part of the type, so that a collection of `Serde<T, C>`
+/// always decodes values with the codec that encoded them.
+pub struct Serde<T, C = JsonCodec> {
+    ob: Any,
+    _marker: PhantomData<(T, C)>,
+}
+
+/// An object that holds a value of `T` encoded as JSON.
+pub type Json<T> = Serde<T, JsonCodec>;
+
+/// An object that holds a value of `T` encoded with bincode.
+pub type Bincode<T> = Serde<T, BincodeCodec>;
+
+impl<T, C> From<Any> for Serde<T, C> {
+    fn from(ob: Any) -> Self {
+        Self {
+            ob,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C> ObjectCodec for Serde<T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    type Txn = SerdeTxn<T, C>;
+    type Value = T;
+
+    fn decode_value(v: Value) -> Result<T> {
+        if let Value::BlobValue(v) = v {
+            C::decode(&v)
+        } else {
+            Err(Error::invalid_argument(format!("{:?} to encoded value", v)))
+        }
+    }
+
+    fn encode_value(v: T) -> Result<Value> {
+        Ok(Value::BlobValue(C::encode(&v)?))
+    }
+}
+
+impl<T, C> Serde<T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    pub fn begin(self) -> SerdeTxn<T, C> {
+        self.ob.begin().into()
+    }
+
+    pub async fn watch(self) -> Result<impl Stream<Item = Result<WatchEvent<T>>> + Unpin> {
+        self.ob.watch_with(Self::decode_value).await
+    }
+
+    pub async fn load(self) -> Result<Option<T>> {
+        let value = self.ob.load().await?;
+        value.map(Self::decode_value).transpose()
+    }
+
+    pub async fn store(self, value: impl Into<T>) -> Result<()> {
+        let value = C::encode(&value.into())?;
+        self.ob.store(value).await
+    }
+
+    pub async fn reset(self) -> Result<()> {
+        self.ob.reset().await
+    }
+}
+
+pub struct SerdeTxn<T, C> {
+    txn: Txn,
+    _marker: PhantomData<(T, C)>,
+}
+
+impl<T, C> From<Txn> for SerdeTxn<T, C> {
+    fn from(txn: Txn) -> Self {
+        Self {
+            txn,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T, C> SerdeTxn<T, C>
+where
+    T: Serialize + DeserializeOwned,
+    C: Codec,
+{
+    pub fn load(&mut self) -> TxnValue<T> {
+        self.txn.load_with(Serde::<T, C>::decode_value)
+    }
+
+    /// Stores a value. If the value fails to encode, the transaction fails
+    /// to commit.
+    pub fn store(&mut self, value: impl Into<T>) -> &mut Self {
+        match C::encode(&value.into()) {
+            Ok(value) => self.txn.store(value),
+            Err(err) => self.txn.fail(err),
+        };
+        self
+    }
+
+    pub fn reset(&mut self) -> &mut Self {
+        self.txn.reset();
+        self
+    }
+
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.txn.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.txn.if_not_exists();
+        self
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        self.txn.commit().await
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashSet, marker::PhantomData};
+
+use futures::Stream;
+
+use crate::{Any, Object, ObjectValue, Result, Txn, TxnValue, WatchEvent};
+
+pub struct Set<T> {
+    ob: Any,
+    _marker: PhantomData<T>,
+}
+
+impl<T> From<Any> for Set<T> {
+    fn from(ob: Any) -> Self {
+        Self {
+            ob,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Object for Set<T>
+where
+    T: Object,
+    HashSet<T::Value>: ObjectValue,
+{
+    type Txn = SetTxn<T>;
+    type Value = HashSet<T::Value>;
+}
+
+impl<T> Set<T>
+where
+    T: Object,
+    HashSet<T::Value>: ObjectValue,
+{
+    pub fn begin(self) -> SetTxn<T> {
+        self.ob.begin().into()
+    }
+
+    pub async fn watch(
+        self,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<HashSet<T::Value>>>> + Unpin> {
+        self.ob.watch_as().await
+    }
+
+    pub async fn load(self) -> Result<Option<HashSet<T::Value>>> {
+        let value = self.ob.load().await?;
+        HashSet::cast_from_option(value)
+    }
+
+    pub async fn store(self, value: impl Into<HashSet<T::Value>>) -> Result<()> {
+        self.ob.store(value.into()).await
+    }
+
+    pub async fn reset(self) -> Result<()> {
+        self.ob.reset().await
+    }
+
+    pub async fn len(self) -> Result<Option<i64>> {
+        self.ob.len().await
+    }
+
+    pub async fn add(self, member: impl Into<T::Value>) -> Result<()> {
+        self.ob.insert(member.into()).await
+    }
+
+    pub async fn remove(self, member: impl Into<T::Value>) -> Result<()> {
+        self.ob.remove(member.into()).await
+    }
+
+    pub async fn contains(self, member: impl Into<T::Value>) -> Result<bool> {
+        self.ob.contains(member.into()).await
+    }
+
+    /// Stores the union of the sets with the given ids into this object.
+    pub async fn union<I>(self, ids: impl IntoIterator<Item = I>) -> Result<()>
+    where
+        I: Into<Vec<u8>>,
+    {
+        self.ob
+            .union(ids.into_iter().map(Into::into).collect())
+            .await
+    }
+
+    /// Stores the intersection of the sets with the given ids into this
+    /// object.
+    pub async fn intersection<I>(self, ids: impl IntoIterator<Item = I>) -> Result<()>
+    where
+        I: Into<Vec<u8>>,
+    {
+        self.ob
+            .intersection(ids.into_iter().map(Into::into).collect())
+            .await
+    }
+}
+
+pub struct SetTxn<T> {
+    txn: Txn,
+    _marker: PhantomData<T>,
+}
+
+impl<T> From<Txn> for SetTxn<T> {
+    fn from(txn: Txn) -> Self {
+        Self {
+            txn,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> SetTxn<T>
+where
+    T: Object,
+    HashSet<T::Value>: ObjectValue,
+{
+    pub fn load(&mut self) -> TxnValue<HashSet<T::Value>> {
+        self.txn.load_as()
+    }
+
+    pub fn len(&mut self) -> TxnValue<i64> {
+        self.txn.len()
+    }
+
+    pub fn store(&mut self, value: impl Into<HashSet<T::Value>>) -> &mut Self {
+        self.txn.store(value.into());
+        self
+    }
+
+    pub fn reset(&mut self) -> &mut Self {
+        self.txn.reset();
+        self
+    }
+
+    pub fn add(&mut self, member: impl Into<T::Value>) -> &mut Self {
+        self.txn.insert(member.into());
+        self
+    }
+
+    pub fn remove(&mut self, member: impl Into<T::Value>) -> &mut Self {
+        self.txn.remove(member.into());
+        self
+    }
+
+    pub fn union<I>(&mut self, ids: impl IntoIterator<Item = I>) -> &mut Self
+    where
+        I: Into<Vec<u8>>,
+    {
+        self.txn.union(ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn intersection<I>(&mut self, ids: impl IntoIterator<Item = I>) -> &mut Self
+    where
+        I: Into<Vec<u8>>,
+    {
+        self.txn
+            .intersection(ids.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.txn.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.txn.if_not_exists();
+        self
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        self.txn.commit().await
+    }
+}
@@ -0,0 +1,187 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use engula_apis::*;
+use futures::Stream;
+
+use crate::{Any, Error, ObjectCodec, ObjectValue, Result, Txn, TxnValue, WatchEvent};
+
+/// A sorted set of blob members, ordered by their scores.
+///
+/// A sorted set is created by the first `add` or `incr` to a missing object.
+pub struct SortedSet(Any);
+
+impl ObjectCodec for SortedSet {
+    type Txn = SortedSetTxn;
+    type Value = Vec<(Vec<u8>, f64)>;
+
+    fn decode_value(v: Value) -> Result<Self::Value> {
+        members(v)
+    }
+
+    fn encode_value(v: Self::Value) -> Result<Value> {
+        let (members, scores) = v.into_iter().unzip();
+        Ok(Value::SortedSetValue(SortedSetValue { members, scores }))
+    }
+}
+
+impl From<Any> for SortedSet {
+    fn from(ob: Any) -> Self {
+        Self(ob)
+    }
+}
+
+impl SortedSet {
+    pub fn begin(self) -> SortedSetTxn {
+        self.0.begin().into()
+    }
+
+    pub async fn watch(
+        self,
+    ) -> Result<impl Stream<Item = Result<WatchEvent<Vec<(Vec<u8>, f64)>>>> + Unpin> {
+        self.0.watch_with(members).await
+    }
+
+    pub async fn load(self) -> Result<Option<Vec<(Vec<u8>, f64)>>> {
+        let value = self.0.load().await?;
+        value.map(members).transpose()
+    }
+
+    pub async fn reset(self) -> Result<()> {
+        self.0.reset().await
+    }
+
+    pub async fn len(self) -> Result<Option<i64>> {
+        self.0.len().await
+    }
+
+    pub async fn score(self, member: impl Into<Vec<u8>>) -> Result<Option<f64>> {
+        let value = self.0.index(member.into()).load().await?;
+        f64::cast_from_option(value)
+    }
+
+    /// Sets the score of a member, which is inserted if it is missing.
+    pub async fn add(self, member: impl Into<Vec<u8>>, score: f64) -> Result<()> {
+        let mut txn = self.begin();
+        txn.add(member, score);
+        txn.commit().await
+    }
+
+    /// Adds `delta` to the score of a member. A missing member starts from
+    /// zero.
+    pub async fn incr(self, member: impl Into<Vec<u8>>, delta: f64) -> Result<()> {
+        let mut txn = self.begin();
+        txn.incr(member, delta);
+        txn.commit().await
+    }
+
+    pub async fn remove(self, member: impl Into<Vec<u8>>) -> Result<()> {
+        self.0.index(member.into()).reset().await
+    }
+
+    /// Returns members with ranks in `[start, end)`. Negative ranks count
+    /// from the end.
+    pub async fn range_by_rank(self, start: i64, end: i64) -> Result<Vec<(Vec<u8>, f64)>> {
+        let value = self.0.range_by_rank(start, end).await?;
+        members_or_empty(value)
+    }
+
+    /// Returns members with scores in `[min, max]`.
+    pub async fn range_by_score(self, min: f64, max: f64) -> Result<Vec<(Vec<u8>, f64)>> {
+        let value = self.0.range_by_score(min, max).await?;
+        members_or_empty(value)
+    }
+
+    /// Removes and returns at most `count` members with the lowest scores.
+    pub async fn pop_min(self, count: i64) -> Result<Vec<(Vec<u8>, f64)>> {
+        let value = self.0.pop_min(count).await?;
+        members_or_empty(value)
+    }
+
+    /// Removes and returns at most `count` members with the highest scores.
+    pub async fn pop_max(self, count: i64) -> Result<Vec<(Vec<u8>, f64)>> {
+        let value = self.0.pop_max(count).await?;
+        members_or_empty(value)
+    }
+}
+
+pub struct SortedSetTxn(Txn);
+
+impl From<Txn> for SortedSetTxn {
+    fn from(txn: Txn) -> Self {
+        Self(txn)
+    }
+}
+
+impl SortedSetTxn {
+    pub fn load(&mut self) -> TxnValue<Vec<(Vec<u8>, f64)>> {
+        self.0.load_with(members)
+    }
+
+    pub fn len(&mut self) -> TxnValue<i64> {
+        self.0.len()
+    }
+
+    pub fn reset(&mut self) -> &mut Self {
+        self.0.reset();
+        self
+    }
+
+    pub fn add(&mut self, member: impl Into<Vec<u8>>, score: f64) -> &mut Self {
+        // Inserting the member creates the sorted set if it is missing.
+        let member = member.into();
+        self.0
+            .insert_index(member.clone(), score)
+            .set(member, score);
+        self
+    }
+
+    pub fn incr(&mut self, member: impl Into<Vec<u8>>, delta: f64) -> &mut Self {
+        let member = member.into();
+        self.0
+            .insert_index(member.clone(), 0.0)
+            .add_index(member, delta);
+        self
+    }
+
+    pub fn remove(&mut self, member: impl Into<Vec<u8>>) -> &mut Self {
+        self.0.delete(member.into());
+        self
+    }
+
+    pub fn if_exists(&mut self) -> &mut Self {
+        self.0.if_exists();
+        self
+    }
+
+    pub fn if_not_exists(&mut self) -> &mut Self {
+        self.0.if_not_exists();
+        self
+    }
+
+    pub async fn commit(self) -> Result<()> {
+        self.0.commit().await
+    }
+}
+
+fn members(v: Value) -> Result<Vec<(Vec<u8>, f64)>> {
+    if let Value::SortedSetValue(v) = v {
+        Ok(v.members.into_iter().zip(v.scores).collect())
+    } else {
+        Err(Error::invalid_argument(format!("{:?} to SortedSet", v)))
+    }
+}
+
+fn members_or_empty(v: Option<Value>) -> Result<Vec<(Vec<u8>, f64)>> {
+    v.map(members).transpose().map(Option::unwrap_or_default)
+}
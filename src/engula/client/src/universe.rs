@@ -12,11 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use engula_apis::*;
 
-use crate::{Client, Database, Result};
+use crate::{Client, Database, Error, Result};
 
 #[derive(Clone)]
 pub struct Universe {
@@ -36,15 +36,52 @@ impl Universe {
         self.inner.new_database(name.to_owned())
     }
 
+    pub fn list_databases(&self) -> DatabaseList {
+        DatabaseList::new(self.inner.client.clone())
+    }
+
     pub async fn create_database(&self, name: &str) -> Result<Database> {
         let desc = DatabaseDesc {
             name: name.to_owned(),
             ..Default::default()
         };
+        self.create_database_with_desc(desc).await
+    }
+
+    /// Creates a database that retains old versions of objects for
+    /// `retention`, which bounds how far back snapshots can read.
+    pub async fn create_database_with_snapshot_retention(
+        &self,
+        name: &str,
+        retention: Duration,
+    ) -> Result<Database> {
+        let desc = DatabaseDesc {
+            name: name.to_owned(),
+            snapshot_retention_ms: retention.as_millis() as u64,
+            ..Default::default()
+        };
+        self.create_database_with_desc(desc).await
+    }
+
+    async fn create_database_with_desc(&self, desc: DatabaseDesc) -> Result<Database> {
+        let name = desc.name.clone();
         let req = CreateDatabaseRequest { desc: Some(desc) };
         let req = database_request_union::Request::CreateDatabase(req);
         self.inner.database_union_call(req).await?;
-        Ok(self.database(name))
+        Ok(self.database(&name))
+    }
+
+    /// Replaces the options of the database named in `desc`.
+    pub async fn update_database(&self, desc: DatabaseDesc) -> Result<DatabaseDesc> {
+        let req = UpdateDatabaseRequest { desc: Some(desc) };
+        let req = database_request_union::Request::UpdateDatabase(req);
+        let res = self.inner.database_union_call(req).await?;
+        let desc = if let database_response_union::Response::UpdateDatabase(res) = res {
+            res.desc
+        } else {
+            None
+        };
+        desc.ok_or_else(|| Error::internal("missing database description"))
     }
 
     pub async fn delete_database(&self, name: &str) -> Result<()> {
@@ -73,3 +110,40 @@ impl UniverseInner {
         self.client.database_union(req).await
     }
 }
+
+pub struct DatabaseList {
+    client: Client,
+    next_page_token: String,
+    done: bool,
+}
+
+impl DatabaseList {
+    fn new(client: Client) -> Self {
+        Self {
+            client,
+            next_page_token: String::new(),
+            done: false,
+        }
+    }
+
+    /// Returns the next page of at most `size` databases, or an empty page if
+    /// there are no more databases.
+    pub async fn next_page(&mut self, size: usize) -> Result<Vec<DatabaseDesc>> {
+        if self.done {
+            return Ok(Vec::new());
+        }
+        let req = ListDatabasesRequest {
+            page_size: size as u64,
+            page_token: self.next_page_token.clone(),
+        };
+        let req = database_request_union::Request::ListDatabases(req);
+        let res = self.client.database_union(req).await?;
+        if let database_response_union::Response::ListDatabases(mut res) = res {
+            self.next_page_token = std::mem::take(&mut res.next_page_token);
+            self.done = self.next_page_token.is_empty();
+            Ok(res.descs)
+        } else {
+            Err(Error::internal("missing list databases response"))
+        }
+    }
+}
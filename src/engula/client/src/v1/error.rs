@@ -23,8 +23,12 @@ pub enum Error {
     #[error("{0}")]
     InvalidArgument(String),
     #[error("{0}")]
+    ConditionFailed(String),
+    #[error("{0}")]
     Aborted(String),
     #[error("{0}")]
+    NotLeader(String),
+    #[error("{0}")]
     DataLoss(String),
     #[error("{0}")]
     Internal(String),
@@ -41,6 +45,10 @@ impl Error {
         Self::invalid_argument("invalid conversion")
     }
 
+    pub fn condition_failed(m: impl Into<String>) -> Self {
+        Self::ConditionFailed(m.into())
+    }
+
     pub fn aborted(m: impl Into<String>) -> Self {
         Self::Aborted(m.into())
     }
@@ -60,7 +68,9 @@ impl From<tonic::Status> for Error {
             tonic::Code::NotFound => Error::NotFound(s.message().into()),
             tonic::Code::AlreadyExists => Error::AlreadyExists(s.message().into()),
             tonic::Code::InvalidArgument => Error::InvalidArgument(s.message().into()),
+            tonic::Code::FailedPrecondition => Error::ConditionFailed(s.message().into()),
             tonic::Code::Aborted => Error::Aborted(s.message().into()),
+            tonic::Code::Unavailable => Error::NotLeader(s.message().into()),
             tonic::Code::DataLoss => Error::DataLoss(s.message().into()),
             tonic::Code::Internal => Error::Internal(s.message().into()),
             _ => Error::Unknown(Box::new(s)),
@@ -74,7 +84,9 @@ impl From<Error> for tonic::Status {
             Error::NotFound(s) => (tonic::Code::NotFound, s),
             Error::AlreadyExists(s) => (tonic::Code::AlreadyExists, s),
             Error::InvalidArgument(s) => (tonic::Code::InvalidArgument, s),
+            Error::ConditionFailed(s) => (tonic::Code::FailedPrecondition, s),
             Error::Aborted(s) => (tonic::Code::Aborted, s),
+            Error::NotLeader(s) => (tonic::Code::Unavailable, s),
             Error::DataLoss(s) => (tonic::Code::DataLoss, s),
             Error::Internal(s) => (tonic::Code::Internal, s),
             Error::Unknown(s) => (tonic::Code::Unknown, s.to_string()),
@@ -27,6 +27,6 @@ pub use self::{
     database::Database,
     error::{Error, Result},
     txn::{CollectionTxn, DatabaseTxn},
-    types::{Any, Blob, List, Map, MutateExpr, SelectExpr, Text, F64, I64},
+    types::{Any, Blob, List, Map, MutateExpr, SelectExpr, Set, SortedSet, Text, F64, I64},
     universe::Universe,
 };
@@ -39,7 +39,10 @@ pub struct AnySelect {
 impl AnySelect {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: AnyExpr { call: Some(call) },
+            expr: AnyExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
@@ -61,10 +64,33 @@ pub struct AnyMutate {
 impl AnyMutate {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: AnyExpr { call: Some(call) },
+            expr: AnyExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn if_eq(mut self, value: impl Into<Value>) -> Self {
+        self.expr.guards.push(call::if_eq(value.into()));
+        self
+    }
+
+    pub fn if_ne(mut self, value: impl Into<Value>) -> Self {
+        self.expr.guards.push(call::if_ne(value.into()));
+        self
+    }
+
     pub fn set(value: impl Into<Value>) -> Self {
         Self::new(call::set(value))
     }
@@ -67,7 +67,10 @@ pub struct BlobSelect {
 impl BlobSelect {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: BlobExpr { call: Some(call) },
+            expr: BlobExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
@@ -93,10 +96,53 @@ pub struct BlobMutate {
 impl BlobMutate {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: BlobExpr { call: Some(call) },
+            expr: BlobExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn if_eq(mut self, value: impl Into<Vec<u8>>) -> Self {
+        self.expr.guards.push(call::if_eq(value.into()));
+        self
+    }
+
+    pub fn if_ne(mut self, value: impl Into<Vec<u8>>) -> Self {
+        self.expr.guards.push(call::if_ne(value.into()));
+        self
+    }
+
+    pub fn if_lt(mut self, value: impl Into<Vec<u8>>) -> Self {
+        self.expr.guards.push(call::if_lt(value.into()));
+        self
+    }
+
+    pub fn if_le(mut self, value: impl Into<Vec<u8>>) -> Self {
+        self.expr.guards.push(call::if_le(value.into()));
+        self
+    }
+
+    pub fn if_gt(mut self, value: impl Into<Vec<u8>>) -> Self {
+        self.expr.guards.push(call::if_gt(value.into()));
+        self
+    }
+
+    pub fn if_ge(mut self, value: impl Into<Vec<u8>>) -> Self {
+        self.expr.guards.push(call::if_ge(value.into()));
+        self
+    }
+
     pub fn trim(range: impl RangeBounds<i64>) -> Self {
         Self::new(call::trim(call::range(range)))
     }
@@ -41,6 +41,13 @@ macro_rules! index_call {
             operand: Some(call_expr::Operand::Index($index.into())),
         }
     };
+    ($func:expr, $index:expr, $arg0:expr) => {
+        CallExpr {
+            func: $func as i32,
+            args: vec![$arg0.into()],
+            operand: Some(call_expr::Operand::Index($index.into())),
+        }
+    };
 }
 
 macro_rules! range_call {
@@ -65,10 +72,18 @@ pub fn get_range(r: impl Into<TypedRange>) -> CallExpr {
     range_call!(Function::Get, r)
 }
 
+pub fn prefix(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Prefix, v)
+}
+
 pub fn set(v: impl Into<TypedValue>) -> CallExpr {
     call!(Function::Set, v)
 }
 
+pub fn set_index(i: impl Into<TypedValue>, v: impl Into<TypedValue>) -> CallExpr {
+    index_call!(Function::Set, i, v)
+}
+
 pub fn delete() -> CallExpr {
     call!(Function::Delete)
 }
@@ -81,10 +96,26 @@ pub fn add(v: impl Into<TypedValue>) -> CallExpr {
     call!(Function::Add, v)
 }
 
+pub fn add_index(i: impl Into<TypedValue>, v: impl Into<TypedValue>) -> CallExpr {
+    index_call!(Function::Add, i, v)
+}
+
 pub fn sub(v: impl Into<TypedValue>) -> CallExpr {
     call!(Function::Sub, v)
 }
 
+pub fn mul(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Mul, v)
+}
+
+pub fn min(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Min, v)
+}
+
+pub fn max(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Max, v)
+}
+
 pub fn trim(r: impl Into<TypedRange>) -> CallExpr {
     range_call!(Function::Trim, r)
 }
@@ -105,6 +136,18 @@ pub fn rpush(v: impl Into<TypedValue>) -> CallExpr {
     call!(Function::Rpush, v)
 }
 
+pub fn pop_min(n: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::PopMin, n)
+}
+
+pub fn pop_max(n: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::PopMax, n)
+}
+
+pub fn range_by_score(r: impl Into<TypedRange>) -> CallExpr {
+    range_call!(Function::RangeByScore, r)
+}
+
 pub fn len() -> CallExpr {
     call!(Function::Len)
 }
@@ -113,6 +156,50 @@ pub fn extend(v: impl Into<TypedValue>) -> CallExpr {
     call!(Function::Extend, v)
 }
 
+pub fn contains(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Contains, v)
+}
+
+pub fn union(ids: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Union, ids)
+}
+
+pub fn intersection(ids: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::Intersection, ids)
+}
+
+pub fn if_exists() -> CallExpr {
+    call!(Function::IfExists)
+}
+
+pub fn if_not_exists() -> CallExpr {
+    call!(Function::IfNotExists)
+}
+
+pub fn if_eq(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::IfEq, v)
+}
+
+pub fn if_ne(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::IfNe, v)
+}
+
+pub fn if_lt(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::IfLt, v)
+}
+
+pub fn if_le(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::IfLe, v)
+}
+
+pub fn if_gt(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::IfGt, v)
+}
+
+pub fn if_ge(v: impl Into<TypedValue>) -> CallExpr {
+    call!(Function::IfGe, v)
+}
+
 pub fn range<T>(r: impl RangeBounds<T>) -> TypedRange
 where
     T: Clone + Into<TypedValue>,
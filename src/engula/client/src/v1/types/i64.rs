@@ -36,6 +36,18 @@ impl I64 {
     pub fn sub(value: i64) -> I64Mutate {
         I64Mutate::sub(value)
     }
+
+    pub fn mul(value: i64) -> I64Mutate {
+        I64Mutate::mul(value)
+    }
+
+    pub fn min(value: i64) -> I64Mutate {
+        I64Mutate::min(value)
+    }
+
+    pub fn max(value: i64) -> I64Mutate {
+        I64Mutate::max(value)
+    }
 }
 
 pub struct I64Mutate {
@@ -45,10 +57,53 @@ pub struct I64Mutate {
 impl I64Mutate {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: I64Expr { call: Some(call) },
+            expr: I64Expr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn if_eq(mut self, value: i64) -> Self {
+        self.expr.guards.push(call::if_eq(value));
+        self
+    }
+
+    pub fn if_ne(mut self, value: i64) -> Self {
+        self.expr.guards.push(call::if_ne(value));
+        self
+    }
+
+    pub fn if_lt(mut self, value: i64) -> Self {
+        self.expr.guards.push(call::if_lt(value));
+        self
+    }
+
+    pub fn if_le(mut self, value: i64) -> Self {
+        self.expr.guards.push(call::if_le(value));
+        self
+    }
+
+    pub fn if_gt(mut self, value: i64) -> Self {
+        self.expr.guards.push(call::if_gt(value));
+        self
+    }
+
+    pub fn if_ge(mut self, value: i64) -> Self {
+        self.expr.guards.push(call::if_ge(value));
+        self
+    }
+
     pub fn add(value: i64) -> Self {
         Self::new(call::add(value))
     }
@@ -56,6 +111,18 @@ impl I64Mutate {
     pub fn sub(value: i64) -> Self {
         Self::new(call::sub(value))
     }
+
+    pub fn mul(value: i64) -> Self {
+        Self::new(call::mul(value))
+    }
+
+    pub fn min(value: i64) -> Self {
+        Self::new(call::min(value))
+    }
+
+    pub fn max(value: i64) -> Self {
+        Self::new(call::max(value))
+    }
 }
 
 impl From<I64Mutate> for MutateExpr {
@@ -71,7 +71,10 @@ pub struct ListSelect {
 impl ListSelect {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: ListExpr { call: Some(call) },
+            expr: ListExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
@@ -101,10 +104,33 @@ pub struct ListMutate {
 impl ListMutate {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: ListExpr { call: Some(call) },
+            expr: ListExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn if_eq(mut self, value: impl Into<ListValue>) -> Self {
+        self.expr.guards.push(call::if_eq(value.into()));
+        self
+    }
+
+    pub fn if_ne(mut self, value: impl Into<ListValue>) -> Self {
+        self.expr.guards.push(call::if_ne(value.into()));
+        self
+    }
+
     pub fn trim(range: impl RangeBounds<i64>) -> Self {
         Self::new(call::trim(call::range(range)))
     }
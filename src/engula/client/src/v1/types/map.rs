@@ -46,6 +46,10 @@ impl Map {
         MapSelect::range(range)
     }
 
+    pub fn prefix(prefix: impl Into<Vec<u8>>) -> MapSelect {
+        MapSelect::prefix(prefix)
+    }
+
     pub fn extend(value: impl Into<MapValue>) -> MapMutate {
         MapMutate::extend(value)
     }
@@ -62,7 +66,10 @@ pub struct MapSelect {
 impl MapSelect {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: MapExpr { call: Some(call) },
+            expr: MapExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
@@ -80,6 +87,10 @@ impl MapSelect {
     {
         Self::new(call::get_range(call::range(range)))
     }
+
+    pub fn prefix(prefix: impl Into<Vec<u8>>) -> Self {
+        Self::new(call::prefix(prefix.into()))
+    }
 }
 
 impl From<MapSelect> for SelectExpr {
@@ -95,10 +106,33 @@ pub struct MapMutate {
 impl MapMutate {
     fn new(call: CallExpr) -> Self {
         Self {
-            expr: MapExpr { call: Some(call) },
+            expr: MapExpr {
+                call: Some(call),
+                ..Default::default()
+            },
         }
     }
 
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn if_eq(mut self, value: impl Into<MapValue>) -> Self {
+        self.expr.guards.push(call::if_eq(value.into()));
+        self
+    }
+
+    pub fn if_ne(mut self, value: impl Into<MapValue>) -> Self {
+        self.expr.guards.push(call::if_ne(value.into()));
+        self
+    }
+
     pub fn extend(value: impl Into<MapValue>) -> Self {
         Self::new(call::extend(value.into()))
     }
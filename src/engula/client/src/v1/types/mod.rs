@@ -20,6 +20,8 @@ mod f64;
 mod i64;
 mod list;
 mod map;
+mod set;
+mod sorted_set;
 mod text;
 
 pub use self::{
@@ -30,5 +32,7 @@ pub use self::{
     i64::I64,
     list::List,
     map::Map,
+    set::Set,
+    sorted_set::SortedSet,
     text::Text,
 };
@@ -0,0 +1,141 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use engula_apis::v1::*;
+
+use super::{call, MutateExpr, SelectExpr};
+
+pub struct Set(SetValue);
+
+impl From<Set> for Value {
+    fn from(v: Set) -> Self {
+        v.0.into()
+    }
+}
+
+impl Set {
+    pub fn new(value: impl Into<SetValue>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn len() -> SetSelect {
+        SetSelect::len()
+    }
+
+    pub fn members() -> SetSelect {
+        SetSelect::members()
+    }
+
+    pub fn contains(member: impl Into<Value>) -> SetSelect {
+        SetSelect::contains(member)
+    }
+
+    pub fn add(member: impl Into<Value>) -> SetMutate {
+        SetMutate::add(member)
+    }
+
+    pub fn remove(member: impl Into<Value>) -> SetMutate {
+        SetMutate::remove(member)
+    }
+
+    pub fn union(ids: impl Into<ListValue>) -> SetMutate {
+        SetMutate::union(ids)
+    }
+
+    pub fn intersection(ids: impl Into<ListValue>) -> SetMutate {
+        SetMutate::intersection(ids)
+    }
+}
+
+pub struct SetSelect {
+    expr: SetExpr,
+}
+
+impl SetSelect {
+    fn new(call: CallExpr) -> Self {
+        Self {
+            expr: SetExpr {
+                call: Some(call),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn len() -> Self {
+        Self::new(call::len())
+    }
+
+    pub fn members() -> Self {
+        Self::new(call::get())
+    }
+
+    pub fn contains(member: impl Into<Value>) -> Self {
+        Self::new(call::contains(member.into()))
+    }
+}
+
+impl From<SetSelect> for SelectExpr {
+    fn from(v: SetSelect) -> Self {
+        Expr::from(v.expr).into()
+    }
+}
+
+pub struct SetMutate {
+    expr: SetExpr,
+}
+
+impl SetMutate {
+    fn new(call: CallExpr) -> Self {
+        Self {
+            expr: SetExpr {
+                call: Some(call),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn add(member: impl Into<Value>) -> Self {
+        Self::new(call::add(member.into()))
+    }
+
+    pub fn remove(member: impl Into<Value>) -> Self {
+        Self::new(call::delete_index(member.into()))
+    }
+
+    /// Stores the union of the sets with the given ids.
+    pub fn union(ids: impl Into<ListValue>) -> Self {
+        Self::new(call::union(ids.into()))
+    }
+
+    /// Stores the intersection of the sets with the given ids.
+    pub fn intersection(ids: impl Into<ListValue>) -> Self {
+        Self::new(call::intersection(ids.into()))
+    }
+}
+
+impl From<SetMutate> for MutateExpr {
+    fn from(v: SetMutate) -> Self {
+        Expr::from(v.expr).into()
+    }
+}
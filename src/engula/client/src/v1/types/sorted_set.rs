@@ -0,0 +1,157 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::RangeBounds;
+
+use engula_apis::v1::*;
+
+use super::{call, MutateExpr, SelectExpr};
+
+pub struct SortedSet(SortedSetValue);
+
+impl From<SortedSet> for Value {
+    fn from(v: SortedSet) -> Self {
+        v.0.into()
+    }
+}
+
+impl SortedSet {
+    pub fn new(value: impl Into<SortedSetValue>) -> Self {
+        Self(value.into())
+    }
+
+    pub fn len() -> SortedSetSelect {
+        SortedSetSelect::len()
+    }
+
+    pub fn score(member: impl Into<Vec<u8>>) -> SortedSetSelect {
+        SortedSetSelect::score(member)
+    }
+
+    pub fn range_by_rank(range: impl RangeBounds<i64>) -> SortedSetSelect {
+        SortedSetSelect::range_by_rank(range)
+    }
+
+    pub fn range_by_score(range: impl RangeBounds<f64>) -> SortedSetSelect {
+        SortedSetSelect::range_by_score(range)
+    }
+
+    pub fn add(member: impl Into<Vec<u8>>, score: f64) -> SortedSetMutate {
+        SortedSetMutate::add(member, score)
+    }
+
+    pub fn incr(member: impl Into<Vec<u8>>, delta: f64) -> SortedSetMutate {
+        SortedSetMutate::incr(member, delta)
+    }
+
+    pub fn remove(member: impl Into<Vec<u8>>) -> SortedSetMutate {
+        SortedSetMutate::remove(member)
+    }
+
+    pub fn pop_min(count: i64) -> SortedSetMutate {
+        SortedSetMutate::pop_min(count)
+    }
+
+    pub fn pop_max(count: i64) -> SortedSetMutate {
+        SortedSetMutate::pop_max(count)
+    }
+}
+
+pub struct SortedSetSelect {
+    expr: SortedSetExpr,
+}
+
+impl SortedSetSelect {
+    fn new(call: CallExpr) -> Self {
+        Self {
+            expr: SortedSetExpr {
+                call: Some(call),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn len() -> Self {
+        Self::new(call::len())
+    }
+
+    pub fn score(member: impl Into<Vec<u8>>) -> Self {
+        Self::new(call::get_index(member.into()))
+    }
+
+    pub fn range_by_rank(range: impl RangeBounds<i64>) -> Self {
+        Self::new(call::get_range(call::range(range)))
+    }
+
+    pub fn range_by_score(range: impl RangeBounds<f64>) -> Self {
+        Self::new(call::range_by_score(call::range(range)))
+    }
+}
+
+impl From<SortedSetSelect> for SelectExpr {
+    fn from(v: SortedSetSelect) -> Self {
+        Expr::from(v.expr).into()
+    }
+}
+
+pub struct SortedSetMutate {
+    expr: SortedSetExpr,
+}
+
+impl SortedSetMutate {
+    fn new(call: CallExpr) -> Self {
+        Self {
+            expr: SortedSetExpr {
+                call: Some(call),
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn if_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_exists());
+        self
+    }
+
+    pub fn if_not_exists(mut self) -> Self {
+        self.expr.guards.push(call::if_not_exists());
+        self
+    }
+
+    pub fn add(member: impl Into<Vec<u8>>, score: f64) -> Self {
+        Self::new(call::set_index(member.into(), score))
+    }
+
+    pub fn incr(member: impl Into<Vec<u8>>, delta: f64) -> Self {
+        Self::new(call::add_index(member.into(), delta))
+    }
+
+    pub fn remove(member: impl Into<Vec<u8>>) -> Self {
+        Self::new(call::delete_index(member.into()))
+    }
+
+    pub fn pop_min(count: i64) -> Self {
+        Self::new(call::pop_min(count))
+    }
+
+    pub fn pop_max(count: i64) -> Self {
+        Self::new(call::pop_max(count))
+    }
+}
+
+impl From<SortedSetMutate> for MutateExpr {
+    fn from(v: SortedSetMutate) -> Self {
+        Expr::from(v.expr).into()
+    }
+}
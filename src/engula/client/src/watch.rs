@@ -0,0 +1,47 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use engula_apis::*;
+use futures::{Stream, StreamExt};
+
+use crate::{Client, Result};
+
+/// A change to an object.
+#[derive(Debug)]
+pub struct WatchEvent<V> {
+    pub id: Vec<u8>,
+    /// The new value of the object, or `None` if the object is deleted.
+    pub value: Option<V>,
+    /// Increases with the commit order of changes in a shard of the
+    /// collection. Sequences of different shards are unrelated.
+    pub sequence: u64,
+}
+
+// Changes are delivered once this returns. Values are converted by `cast`.
+pub(crate) async fn watch_events<V>(
+    client: Client,
+    req: WatchRequest,
+    cast: fn(Value) -> Result<V>,
+) -> Result<impl Stream<Item = Result<WatchEvent<V>>> + Unpin> {
+    let changes = client.watch(req).await?;
+    let events = changes.boxed().map(|res| -> Result<WatchEvent<V>> {
+        let res = res?;
+        Ok(WatchEvent {
+            id: res.id,
+            value: res.value.and_then(|v| v.value).map(cast).transpose()?,
+            sequence: res.sequence,
+        })
+    });
+    Ok(events)
+}
@@ -12,8 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
 use anyhow::Result;
-use engula_client::Blob;
+use engula_apis::{IndexDesc, Value};
+use engula_client::{Any, Blob, Map, Set, SortedSet, F64, I64};
+use futures::{StreamExt, TryStreamExt};
 
 use crate::create_universe;
 
@@ -41,3 +48,595 @@ async fn test_apis() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+#[ignore]
+async fn test_txn_reads() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("txn_reads").await?;
+    let c1 = db.create_collection::<I64>("i64").await?;
+    let c2 = db.create_collection::<Map<Blob>>("map").await?;
+    c1.set("a", 1).await?;
+    c2.object("m").set("x", vec![1]).await?;
+
+    let mut txn = c1.begin();
+    let a = txn.object("a").add(1).load();
+    let missing = txn.get("missing");
+    txn.commit().await?;
+    assert_eq!(a.take()?, Some(2));
+    assert_eq!(missing.take()?, None);
+
+    // Reads of a database transaction are filled when it commits.
+    let txn = db.begin();
+    let mut t = c1.begin_with(txn.clone());
+    let a = t.object("a").sub(2).load();
+    t.commit().await?;
+    let mut t = c2.begin_with(txn.clone());
+    let (x, y, len) = {
+        let o = t.object("m");
+        (o.get("x"), o.get("y"), o.set("z", vec![2]).len())
+    };
+    t.commit().await?;
+    txn.commit().await?;
+    assert_eq!(a.take()?, Some(0));
+    assert_eq!(x.take()?, Some(vec![1]));
+    assert_eq!(y.take()?, None);
+    assert_eq!(len.take()?, Some(2));
+
+    // Nothing is read if the transaction fails.
+    let mut txn = c1.begin();
+    let a = txn.object("a").if_ge(1).add(1).load();
+    txn.commit().await.unwrap_err();
+    a.take().unwrap_err();
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_catalog() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("catalog").await?;
+    for i in 0..5 {
+        db.create_collection::<I64>(&format!("co{}", i)).await?;
+    }
+
+    let mut list = db.list_collections();
+    let mut names = Vec::new();
+    loop {
+        let page = list.next_page(2).await?;
+        if page.is_empty() {
+            break;
+        }
+        assert!(page.len() <= 2);
+        names.extend(page.into_iter().map(|desc| desc.name));
+    }
+    assert_eq!(names, vec!["co0", "co1", "co2", "co3", "co4"]);
+
+    let mut desc = db.collection::<I64>("co0").desc().await?;
+    desc.ttl_ms = 1000;
+    let updated = db.update_collection(desc.clone()).await?;
+    assert_eq!(updated, desc);
+
+    // A re-created collection starts empty.
+    let co = db.collection::<I64>("co1");
+    co.set("a", 1).await?;
+    db.delete_collection("co1").await?;
+    co.get("a").await.unwrap_err();
+    let co = db.create_collection::<I64>("co1").await?;
+    assert_eq!(None, co.get("a").await?);
+
+    uv.delete_database("catalog").await?;
+    db.desc().await.unwrap_err();
+    let mut list = uv.list_databases();
+    loop {
+        let page = list.next_page(10).await?;
+        if page.is_empty() {
+            break;
+        }
+        assert!(page.iter().all(|desc| desc.name != "catalog"));
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_scan() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("scan").await?;
+    let co = db.create_collection::<I64>("scan").await?;
+
+    for i in 0..300u32 {
+        co.set(i.to_be_bytes(), i as i64).await?;
+    }
+
+    let objects: Vec<_> = co.scan(..).try_collect().await?;
+    assert_eq!(objects.len(), 300);
+    for (i, (id, value)) in objects.into_iter().enumerate() {
+        assert_eq!(id, (i as u32).to_be_bytes());
+        assert_eq!(value, i as i64);
+    }
+
+    let start = 100u32.to_be_bytes().to_vec();
+    let end = 200u32.to_be_bytes().to_vec();
+    let ids: Vec<_> = co.scan_ids(start..=end).try_collect().await?;
+    assert_eq!(ids.len(), 101);
+
+    // A range that ends before the empty id is empty, not unbounded.
+    let ids: Vec<_> = co.scan_ids(..vec![]).try_collect().await?;
+    assert!(ids.is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_conditions() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("conditions").await?;
+    let co = db.create_collection::<I64>("conditions").await?;
+
+    let mut txn = co.object("stock").begin();
+    txn.if_not_exists().store(1);
+    txn.commit().await?;
+
+    let mut txn = co.object("stock").begin();
+    txn.if_ge(1).sub(1);
+    txn.commit().await?;
+    assert_eq!(Some(0), co.get("stock").await?);
+
+    let mut txn = co.begin();
+    txn.object("stock").if_ge(1).sub(1);
+    txn.object("other").store(1);
+    let err = txn.commit().await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::FailedPrecondition);
+    assert_eq!(Some(0), co.get("stock").await?);
+    assert_eq!(None, co.get("other").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_numeric() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("numeric").await?;
+    let co = db.create_collection::<I64>("i64").await?;
+
+    co.object("o").max(3).await?;
+    co.object("o").mul(4).await?;
+    co.object("o").min(10).await?;
+    assert_eq!(Some(10), co.get("o").await?);
+
+    co.set("o", i64::MAX).await?;
+    let err = co.object("o").add(1).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(Some(i64::MAX), co.get("o").await?);
+
+    let co = db.create_collection::<F64>("f64").await?;
+    co.object("o").add(1.5).await?;
+    co.object("o").mul(2.0).await?;
+    assert_eq!(Some(3.0), co.get("o").await?);
+
+    co.set("o", f64::INFINITY).await?;
+    let err = co.object("o").mul(0.0).await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(Some(f64::INFINITY), co.get("o").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_set() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("set").await?;
+    let co = db.create_collection::<Set<I64>>("set").await?;
+
+    co.set("a", [1, 2, 3]).await?;
+    co.set("b", [2, 3, 4]).await?;
+    co.object("a").add(5).await?;
+    co.object("a").remove(1).await?;
+    assert_eq!(co.get("a").await?, Some(HashSet::from([2, 3, 5])));
+    assert_eq!(Some(3), co.object("a").len().await?);
+    assert!(co.object("a").contains(5).await?);
+    assert!(!co.object("a").contains(1).await?);
+    assert!(!co.object("missing").contains(1).await?);
+
+    co.object("c").union(["a", "b", "missing"]).await?;
+    assert_eq!(co.get("c").await?, Some(HashSet::from([2, 3, 4, 5])));
+    co.object("c").intersection(["a", "b"]).await?;
+    assert_eq!(co.get("c").await?, Some(HashSet::from([2, 3])));
+    co.object("c").intersection(["a", "missing"]).await?;
+    assert_eq!(co.get("c").await?, Some(HashSet::new()));
+
+    let co = db.create_collection::<Set<Blob>>("blobs").await?;
+    let mut txn = co.object("o").begin();
+    txn.add(vec![1]).add(vec![2]).add(vec![1]).remove(vec![2]);
+    let len = txn.len();
+    txn.commit().await?;
+    assert_eq!(len.take()?, Some(1));
+    assert_eq!(co.get("o").await?, Some(HashSet::from([vec![1]])));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_sorted_set() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("sorted_set").await?;
+    let co = db.create_collection::<SortedSet>("sorted_set").await?;
+    let board = || co.object("board");
+
+    board().incr("a", 1.0).await?;
+    board().add("b", 3.0).await?;
+    board().add("c", 2.0).await?;
+    board().incr("a", 1.5).await?;
+    assert_eq!(Some(2.5), board().score("a").await?);
+    assert_eq!(None, board().score("d").await?);
+    assert_eq!(Some(3), board().len().await?);
+
+    let expect = vec![(b"c".to_vec(), 2.0), (b"a".to_vec(), 2.5)];
+    assert_eq!(board().range_by_rank(0, 2).await?, expect);
+    assert_eq!(board().range_by_score(2.0, 2.5).await?, expect);
+    let top = board().range_by_rank(-1, i64::MAX).await?;
+    assert_eq!(top, vec![(b"b".to_vec(), 3.0)]);
+
+    assert_eq!(board().pop_min(1).await?, vec![(b"c".to_vec(), 2.0)]);
+    assert_eq!(board().pop_max(1).await?, vec![(b"b".to_vec(), 3.0)]);
+    board().remove("a").await?;
+    assert_eq!(Some(Vec::new()), co.get("board").await?);
+    assert!(co.object("missing").pop_min(1).await?.is_empty());
+
+    // Adding to a field of a missing map creates a map, not a sorted set.
+    let co = db.create_collection::<Map<I64>>("counters").await?;
+    co.object("hits").field("home").add(1).await?;
+    co.object("hits").field("home").add(2).await?;
+    co.object("hits").field("about").add(1).await?;
+    assert_eq!(Some(3), co.object("hits").get("home").await?);
+    assert_eq!(Some(2), co.object("hits").len().await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_map_range() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("map_range").await?;
+    let co = db.create_collection::<Map<I64>>("map_range").await?;
+
+    for key in ["a", "ab", "abc", "b", "c"] {
+        co.object("o").set(key, key.len() as i64).await?;
+    }
+    let fields = co.object("o").range(b"ab".to_vec()..b"c".to_vec()).await?;
+    let expect = vec![
+        (b"ab".to_vec(), 2),
+        (b"abc".to_vec(), 3),
+        (b"b".to_vec(), 1),
+    ];
+    assert_eq!(fields, expect);
+    let fields = co.object("o").prefix("ab").await?;
+    let expect = vec![(b"ab".to_vec(), 2), (b"abc".to_vec(), 3)];
+    assert_eq!(fields, expect);
+
+    co.object("o").delete("ab").await?;
+    assert_eq!(Some(4), co.object("o").len().await?);
+    assert_eq!(None, co.object("o").get("ab").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_paths() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("paths").await?;
+    let co = db.create_collection::<Any>("paths").await?;
+    let doc = || co.object("doc");
+
+    doc().index("counters").index("hits").add(1).await?;
+    doc().index("tags").store(vec![1i64, 2i64]).await?;
+    let hits = doc().index("counters").index("hits").load().await?;
+    assert_eq!(hits, Some(1.into()));
+    let tag = doc().index("tags").index(-1).load().await?;
+    assert_eq!(tag, Some(2.into()));
+
+    let mut txn = doc().begin();
+    txn.index("counters").index("hits").if_eq(1).add(2);
+    txn.root().index("tags").index(0).store(0);
+    txn.commit().await?;
+    let hits = doc().index("counters").index("hits").load().await?;
+    assert_eq!(hits, Some(3.into()));
+    let tag = doc().index("tags").index(0).load().await?;
+    assert_eq!(tag, Some(0.into()));
+
+    let co = db.create_collection::<Map<Map<I64>>>("fields").await?;
+    let doc = || co.object("doc");
+    doc().field("counters").field("hits").add(1).await?;
+    let hits = doc().field("counters").field("hits").load().await?;
+    assert_eq!(hits, Some(1));
+
+    // Adding to a nested member of a missing object creates maps on the way.
+    let co = db.collection::<Any>("paths");
+    co.object("nested").index("a").index("b").add(1).await?;
+    co.object("flat").index("a").add(1).await?;
+    let nested = co.object("nested").load().await?;
+    let flat = co.object("flat").load().await?;
+    let expect = |key: &str, value: Value| {
+        let map = HashMap::from([(key.as_bytes().to_vec(), value)]);
+        Value::from(map)
+    };
+    assert_eq!(nested, Some(expect("a", expect("b", 1.into()))));
+    assert_eq!(flat, Some(expect("a", 1.into())));
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_atomic_txn() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("atomic").await?;
+    let c1 = db.create_collection::<I64>("c1").await?;
+    let c2 = db.create_collection::<I64>("c2").await?;
+    c1.set("a", 1).await?;
+    c2.set("b", i64::MAX).await?;
+
+    // The last expression overflows, so nothing is applied.
+    let txn = db.begin();
+    let mut t = c1.begin_with(txn.clone());
+    t.object("a").add(1);
+    t.object("new").store(1);
+    t.commit().await?;
+    let mut t = c2.begin_with(txn.clone());
+    t.object("c").store(1);
+    t.object("b").sub(1).add(2);
+    t.commit().await?;
+    let err = txn.commit().await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::InvalidArgument);
+    assert_eq!(Some(1), c1.get("a").await?);
+    assert_eq!(None, c1.get("new").await?);
+    assert_eq!(None, c2.get("c").await?);
+    assert_eq!(Some(i64::MAX), c2.get("b").await?);
+
+    // The same applies to expressions within a collection.
+    let mut txn = c1.begin();
+    txn.object("a").add(1);
+    txn.object("a").mul(i64::MAX);
+    txn.commit().await.unwrap_err();
+    assert_eq!(Some(1), c1.get("a").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_concurrent_txns() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("concurrent").await?;
+    let co = db.create_collection::<I64>("concurrent").await?;
+
+    // Transfers between overlapping pairs of objects keep the total.
+    let mut handles = Vec::new();
+    for i in 0..8u32 {
+        let co = co.clone();
+        handles.push(tokio::spawn(async move {
+            for j in 0..100u32 {
+                let mut txn = co.begin();
+                txn.object(((i + j) % 10).to_be_bytes()).sub(1);
+                txn.object(((i + j + 1) % 10).to_be_bytes()).add(1);
+                txn.commit().await?;
+            }
+            Ok::<_, engula_client::Error>(())
+        }));
+    }
+    for handle in handles {
+        handle.await??;
+    }
+    let objects: Vec<_> = co.scan(..).try_collect().await?;
+    let total: i64 = objects.iter().map(|(_, v)| v).sum();
+    assert_eq!(total, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_get_many() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("get_many").await?;
+    let co = db.create_collection::<I64>("i64").await?;
+    co.set("a", 1).await?;
+    co.set("c", 3).await?;
+    let values = co.get_many(["a", "b", "c"]).await?;
+    assert_eq!(values, vec![Some(1), None, Some(3)]);
+    assert!(co.get_many(Vec::<Vec<u8>>::new()).await?.is_empty());
+
+    let co = db.create_collection::<Map<Blob>>("map").await?;
+    co.object("m").set("x", vec![1]).await?;
+    let values = co.object("m").get_many(["y", "x"]).await?;
+    assert_eq!(values, vec![None, Some(vec![1])]);
+    let values = co.object("missing").get_many(["x"]).await?;
+    assert_eq!(values, vec![None]);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_snapshot() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv
+        .create_database_with_snapshot_retention("snapshot", Duration::from_secs(1))
+        .await?;
+    let c1 = db.create_collection::<I64>("c1").await?;
+    let c2 = db.create_collection::<I64>("c2").await?;
+    c1.set("a", 1).await?;
+    c2.set("b", 2).await?;
+
+    let snapshot = db.snapshot().await?;
+    c1.set("a", 10).await?;
+    c1.set("c", 30).await?;
+    c2.delete("b").await?;
+    assert_eq!(Some(1), snapshot.get(&c1, "a").await?);
+    assert_eq!(Some(2), c2.get_at("b", snapshot.ts()).await?);
+    assert_eq!(None, snapshot.get(&c1, "c").await?);
+    let objects: Vec<_> = snapshot.scan(&c1, ..).try_collect().await?;
+    assert_eq!(objects, vec![(b"a".to_vec(), 1)]);
+    assert_eq!(Some(10), c1.get("a").await?);
+
+    // Old versions are dropped after the retention window.
+    tokio::time::sleep(Duration::from_secs(2)).await;
+    snapshot.get(&c1, "a").await.unwrap_err();
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_ttl() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("ttl").await?;
+    let co = db
+        .create_collection_with_ttl::<I64>("ttl", Duration::from_millis(200))
+        .await?;
+
+    co.set("a", 1).await?;
+    co.set_with_ttl("b", 2, Duration::from_secs(60)).await?;
+    assert_eq!(Some(1), co.get("a").await?);
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(None, co.get("a").await?);
+    assert_eq!(Some(2), co.get("b").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_watch() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("watch").await?;
+    let co = db.create_collection::<I64>("watch").await?;
+
+    let mut changes = co.watch(b"a".to_vec()..b"b".to_vec()).await?;
+    let mut object_changes = co.object("b").watch().await?;
+
+    co.set("a", 1).await?;
+    co.set("b", 2).await?;
+    co.delete("a").await?;
+
+    let event = changes.next().await.unwrap()?;
+    assert_eq!((event.id, event.value), (b"a".to_vec(), Some(1)));
+    let first = event.sequence;
+    let event = changes.next().await.unwrap()?;
+    assert_eq!((event.id, event.value), (b"a".to_vec(), None));
+    assert!(event.sequence > first);
+
+    let event = object_changes.next().await.unwrap()?;
+    assert_eq!((event.id, event.value), (b"b".to_vec(), Some(2)));
+
+    // Resumes from a retained change.
+    let mut changes = co.watch_from(b"a".to_vec()..b"b".to_vec(), first).await?;
+    let event = changes.next().await.unwrap()?;
+    assert_eq!((event.id, event.sequence), (b"a".to_vec(), first));
+    let event = changes.next().await.unwrap()?;
+    assert_eq!((event.id, event.value), (b"a".to_vec(), None));
+    co.watch_from(.., first + 100).await.unwrap_err();
+
+    Ok(())
+}
+
+#[tokio::test]
+#[ignore]
+async fn test_lookup() -> Result<()> {
+    let uv = create_universe().await?;
+    let db = uv.create_database("lookup").await?;
+    let email = IndexDesc {
+        name: "email".to_owned(),
+        field: b"email".to_vec(),
+    };
+    let co = db
+        .create_collection_with_indexes::<Map<Blob>>("users", vec![email])
+        .await?;
+
+    co.object("u1").set("email", b"a@x".to_vec()).await?;
+    co.object("u2").set("email", b"b@x".to_vec()).await?;
+    let users = co.lookup("email", b"a@x".to_vec()).await?;
+    let ids: Vec<_> = users.into_iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, vec![b"u1".to_vec()]);
+
+    co.object("u2").set("email", b"a@x".to_vec()).await?;
+    let users = co.lookup("email", b"a@x".to_vec()).await?;
+    assert_eq!(users.len(), 2);
+    assert!(co.lookup("email", b"b@x".to_vec()).await?.is_empty());
+
+    co.delete("u1").await?;
+    let users = co.lookup("email", b"a@x".to_vec()).await?;
+    let ids: Vec<_> = users.into_iter().map(|(id, _)| id).collect();
+    assert_eq!(ids, vec![b"u2".to_vec()]);
+    assert!(co.lookup("missing", b"a@x".to_vec()).await.is_err());
+
+    // Expired objects are dropped from indexes.
+    let user: HashMap<_, _> = [(b"email".to_vec(), b"c@x".to_vec())].into_iter().collect();
+    co.set_with_ttl("u3", user, Duration::from_millis(200))
+        .await?;
+    assert_eq!(co.lookup("email", b"c@x".to_vec()).await?.len(), 1);
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert!(co.lookup("email", b"c@x".to_vec()).await?.is_empty());
+
+    // Indexes are fixed when the collection is created.
+    let mut desc = co.desc().await?;
+    desc.indexes.clear();
+    db.update_collection(desc).await.unwrap_err();
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[tokio::test]
+#[ignore]
+async fn test_serde() -> Result<()> {
+    use engula_client::{Bincode, Json};
+    use serde_crate::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
+    #[serde(crate = "serde_crate")]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    let uv = create_universe().await?;
+    let db = uv.create_database("serde").await?;
+    let alice = User {
+        name: "alice".to_owned(),
+        age: 30,
+    };
+
+    let co = db.create_collection::<Json<User>>("json").await?;
+    co.set("a", alice.clone()).await?;
+    assert_eq!(co.get("a").await?, Some(alice.clone()));
+    co.object("b").store(alice.clone()).await?;
+    assert_eq!(co.object("b").load().await?, Some(alice.clone()));
+
+    // Blobs that don't decode are lost data.
+    db.collection::<Blob>("json")
+        .set("c", b"{".to_vec())
+        .await?;
+    let err = co.get("c").await.unwrap_err();
+    assert_eq!(err.code(), tonic::Code::DataLoss);
+
+    let co = db.create_collection::<Bincode<User>>("bincode").await?;
+    let mut txn = co.begin();
+    txn.set("a", alice.clone());
+    txn.object("b").store(alice.clone());
+    txn.commit().await?;
+    assert_eq!(co.get("a").await?, Some(alice.clone()));
+    assert_eq!(co.get("b").await?, Some(alice));
+
+    Ok(())
+}
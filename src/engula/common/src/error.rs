@@ -23,13 +23,17 @@ pub enum Error {
     #[error("{0}")]
     InvalidArgument(String),
     #[error("{0}")]
+    ConditionFailed(String),
+    #[error("{0}")]
+    NotLeader(String),
+    #[error("{0}")]
     Corrupted(String),
     #[error("{0}")]
     Internal(String),
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
-    Unknown(Box<dyn std::error::Error + Send>),
+    Unknown(Box<dyn std::error::Error + Send + Sync + 'static>),
 }
 
 impl Error {
@@ -37,6 +41,14 @@ impl Error {
         Self::InvalidArgument(m.into())
     }
 
+    pub fn condition_failed(m: impl Into<String>) -> Self {
+        Self::ConditionFailed(m.into())
+    }
+
+    pub fn not_leader(m: impl Into<String>) -> Self {
+        Self::NotLeader(m.into())
+    }
+
     pub fn corrupted(m: impl Into<String>) -> Self {
         Self::Corrupted(m.into())
     }
@@ -45,7 +57,7 @@ impl Error {
         Self::Internal(m.into())
     }
 
-    pub fn unknown(err: impl std::error::Error + Send + 'static) -> Self {
+    pub fn unknown(err: impl std::error::Error + Send + Sync + 'static) -> Self {
         Self::Unknown(Box::new(err))
     }
 }
@@ -56,6 +68,8 @@ impl From<tonic::Status> for Error {
             tonic::Code::NotFound => Error::NotFound(s.message().into()),
             tonic::Code::AlreadyExists => Error::AlreadyExists(s.message().into()),
             tonic::Code::InvalidArgument => Error::InvalidArgument(s.message().into()),
+            tonic::Code::FailedPrecondition => Error::ConditionFailed(s.message().into()),
+            tonic::Code::Unavailable => Error::NotLeader(s.message().into()),
             tonic::Code::DataLoss => Error::Corrupted(s.message().into()),
             tonic::Code::Internal => Error::Internal(s.message().into()),
             _ => Error::Unknown(Box::new(s)),
@@ -75,6 +89,8 @@ impl From<Error> for tonic::Status {
             Error::NotFound(s) => (tonic::Code::NotFound, s),
             Error::AlreadyExists(s) => (tonic::Code::AlreadyExists, s),
             Error::InvalidArgument(s) => (tonic::Code::InvalidArgument, s),
+            Error::ConditionFailed(s) => (tonic::Code::FailedPrecondition, s),
+            Error::NotLeader(s) => (tonic::Code::Unavailable, s),
             Error::Corrupted(s) => (tonic::Code::DataLoss, s),
             Error::Internal(s) => (tonic::Code::Internal, s),
             Error::Io(s) => (tonic::Code::Unknown, s.to_string()),
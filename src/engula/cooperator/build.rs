@@ -15,6 +15,12 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
         .extern_path(".engula.v1", "::engula_apis")
-        .compile(&["engula/cooperator/v1/cooperator.proto"], &["."])?;
+        .compile(
+            &[
+                "engula/cooperator/v1/cooperator.proto",
+                "engula/cooperator/v1/journal.proto",
+            ],
+            &["."],
+        )?;
     Ok(())
 }
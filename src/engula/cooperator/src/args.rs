@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::VecDeque;
+use std::{collections::VecDeque, time::Duration};
 
 use engula_apis::*;
 
@@ -39,10 +39,27 @@ impl Args {
         }
     }
 
+    /// Takes an optional ttl in milliseconds.
+    pub fn take_ttl(&mut self) -> Result<Option<Duration>> {
+        match self.0.pop_front().and_then(|v| v.value) {
+            None => Ok(None),
+            Some(Value::I64Value(v)) if v > 0 => Ok(Some(Duration::from_millis(v as u64))),
+            _ => Err(Error::invalid_argument("require positive ttl")),
+        }
+    }
+
+    pub fn take_f64(&mut self) -> Result<f64> {
+        match self.take()? {
+            Value::F64Value(v) => Ok(v),
+            _ => Err(Error::invalid_argument("require f64")),
+        }
+    }
+
     pub fn take_numeric(&mut self) -> Result<Value> {
         let v = self.take()?;
         match v {
             Value::I64Value(_) => Ok(v),
+            Value::F64Value(f) if !f.is_nan() => Ok(v),
             _ => Err(Error::invalid_argument("require numeric")),
         }
     }
@@ -54,6 +71,14 @@ impl Args {
         }
     }
 
+    pub fn take_option_set(&mut self) -> Result<Option<SetValue>> {
+        match self.0.pop_front().and_then(|v| v.value) {
+            None => Ok(None),
+            Some(Value::SetValue(v)) => Ok(Some(v)),
+            _ => Err(Error::invalid_argument("require set")),
+        }
+    }
+
     pub fn take_text(&mut self) -> Result<String> {
         match self.take()? {
             Value::TextValue(v) => Ok(v),
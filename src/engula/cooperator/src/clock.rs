@@ -0,0 +1,54 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    sync::atomic::{AtomicU64, Ordering},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Microseconds since the UNIX epoch.
+pub type Timestamp = u64;
+
+/// Issues strictly increasing timestamps that follow the wall clock.
+///
+/// Timestamps keep increasing even if the wall clock goes backwards, in
+/// which case they run ahead of the wall clock until it catches up.
+#[derive(Default)]
+pub struct Clock {
+    last: AtomicU64,
+}
+
+impl Clock {
+    pub fn now(&self) -> Timestamp {
+        let wall = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_micros() as Timestamp);
+        let mut last = self.last.load(Ordering::Acquire);
+        loop {
+            let ts = wall.max(last + 1);
+            match self
+                .last
+                .compare_exchange_weak(last, ts, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return ts,
+                Err(actual) => last = actual,
+            }
+        }
+    }
+
+    /// Makes the following timestamps larger than `ts`.
+    pub fn advance(&self, ts: Timestamp) {
+        self.last.fetch_max(ts, Ordering::AcqRel);
+    }
+}
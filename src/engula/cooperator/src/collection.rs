@@ -12,66 +12,1269 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet, VecDeque},
+    hash::{Hash, Hasher},
+    ops::Bound,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex as StdMutex, RwLock, Weak,
+    },
+    time::{Duration, Instant},
+};
 
 use engula_apis::*;
-use tokio::sync::Mutex;
+use engula_supervisor::shard_by_id;
+use tokio::sync::{broadcast, Mutex, OwnedMutexGuard};
 
-use crate::{Args, Error, Result};
+use crate::{
+    apis::{CollectionLog, MemberLog, ObjectLog},
+    index, numeric, path, Args, Clock, Error, Map, Object, ReadCache, ReadCacheStats, Result,
+    SortedSet, Timestamp, WriteCache,
+};
 
+/// A collection of objects.
+///
+/// Objects are partitioned into shards by the hash of their ids, and each
+/// shard is locked independently, so that transactions on different objects
+/// can run in parallel.
+///
+/// If a store is given, each shard keeps only the recently used objects in
+/// memory and reads the others from the store.
 #[derive(Clone)]
 pub struct Collection {
-    inner: Arc<Mutex<Inner>>,
+    shards: Arc<Vec<Arc<Mutex<Inner>>>>,
+    shared: Arc<Shared>,
+}
+
+const NUM_SHARDS: usize = 16;
+const RECLAIM_INTERVAL: Duration = Duration::from_secs(1);
+const WATCH_CHANNEL_SIZE: usize = 1024;
+// The number of recent changes retained for watchers that resume.
+const WATCH_HISTORY_SIZE: usize = 4096;
+
+// The state shared by all shards of a collection.
+struct Shared {
+    id: u64,
+    name: String,
+    // The id of the shard that this collection serves, or zero if the
+    // collection is not sharded.
+    shard: u64,
+    // The cooperator that the shard is assigned to, which is empty if the
+    // collection is not sharded or the shard is not assigned yet.
+    owner: RwLock<String>,
+    // Set when the shard is moved away, after which it serves no requests
+    // until it is moved back.
+    fenced: AtomicBool,
+    // The default ttl of objects in milliseconds, or zero if they never
+    // expire.
+    ttl_ms: AtomicU64,
+    indexes: RwLock<Vec<IndexDesc>>,
+    recent: StdMutex<Recent>,
+    changes: broadcast::Sender<WatchResponse>,
+    clock: Arc<Clock>,
+    // How long old versions of objects are retained for snapshot reads.
+    retention: Arc<AtomicU64>,
+    cache: Arc<ReadCacheStats>,
+    store: Option<WriteCache>,
+}
+
+impl Shared {
+    fn ttl(&self) -> Option<Duration> {
+        match self.ttl_ms.load(Ordering::Relaxed) {
+            0 => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    fn indexes(&self) -> Vec<IndexDesc> {
+        self.indexes.read().unwrap().clone()
+    }
 }
 
 impl Collection {
-    pub fn new() -> Self {
-        Self {
-            inner: Arc::new(Mutex::new(Inner::new())),
+    /// Creates a collection that stamps changes with timestamps from `clock`.
+    /// If `shard` is nonzero, it serves only the objects in that shard of the
+    /// collection.
+    pub fn new(
+        desc: CollectionDesc,
+        shard: u64,
+        clock: Arc<Clock>,
+        retention: Arc<AtomicU64>,
+        cache: Arc<ReadCacheStats>,
+        store: Option<WriteCache>,
+    ) -> Self {
+        let owner = shard_owner(&desc, shard);
+        let shared = Arc::new(Shared {
+            id: desc.id,
+            name: desc.name,
+            shard,
+            owner: RwLock::new(owner),
+            fenced: AtomicBool::new(false),
+            ttl_ms: AtomicU64::new(desc.ttl_ms),
+            indexes: RwLock::new(desc.indexes),
+            recent: StdMutex::new(Recent::default()),
+            changes: broadcast::channel(WATCH_CHANNEL_SIZE).0,
+            clock,
+            retention,
+            cache,
+            store,
+        });
+        let shards: Vec<_> = (0..NUM_SHARDS)
+            .map(|_| Arc::new(Mutex::new(Inner::new(shared.clone()))))
+            .collect();
+        let shards = Arc::new(shards);
+        tokio::spawn(reclaim_expired(Arc::downgrade(&shards)));
+        Self { shards, shared }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.shared.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.shared.name
+    }
+
+    pub fn shard(&self) -> u64 {
+        self.shared.shard
+    }
+
+    /// Applies the options and the shard assignment in `desc` to the
+    /// collection.
+    pub fn update(&self, desc: &CollectionDesc) {
+        self.shared.ttl_ms.store(desc.ttl_ms, Ordering::Relaxed);
+        *self.shared.indexes.write().unwrap() = desc.indexes.clone();
+        *self.shared.owner.write().unwrap() = shard_owner(desc, self.shared.shard);
+    }
+
+    /// Returns the cooperator that the shard is assigned to, or an empty
+    /// string if it is not assigned.
+    pub fn owner(&self) -> String {
+        self.shared.owner.read().unwrap().clone()
+    }
+
+    /// Stops serving requests, and waits for the running transactions to
+    /// finish. Transactions check the fence after they lock their shards.
+    pub async fn fence(&self) {
+        self.shared.fenced.store(true, Ordering::Release);
+        for shard in self.shards.iter() {
+            drop(shard.lock().await);
+        }
+    }
+
+    /// Serves requests again after the shard is moved back.
+    pub fn unfence(&self) {
+        self.shared.fenced.store(false, Ordering::Release);
+    }
+
+    pub fn is_fenced(&self) -> bool {
+        self.shared.fenced.load(Ordering::Acquire)
+    }
+
+    /// Returns the shards that `req` reads or writes.
+    ///
+    /// The index entries of an object are in its own shard, so writes to an
+    /// indexed collection lock the same shards as other writes.
+    pub fn shards_of(&self, req: &CollectionTxnRequest) -> BTreeSet<usize> {
+        if !req.scans.is_empty() || !req.lookups.is_empty() {
+            return (0..self.shards.len()).collect();
+        }
+        let mut shards = BTreeSet::new();
+        for expr in &req.exprs {
+            if let Some(expr::From::Id(id)) = &expr.from {
+                shards.insert(shard_index(id));
+            }
+            for id in source_ids(expr) {
+                shards.insert(shard_index(id));
+            }
+        }
+        shards
+    }
+
+    /// Locks the given shards to execute requests that take effect together.
+    ///
+    /// Shards are locked in ascending order. Callers that lock multiple
+    /// collections must lock them in a consistent order too, so that
+    /// transactions never wait for each other in a cycle.
+    pub async fn begin(&self, shards: BTreeSet<usize>) -> Transaction {
+        let mut guards = BTreeMap::new();
+        for index in shards {
+            let guard = self.shards[index].clone().lock_owned().await;
+            guards.insert(index, guard);
+        }
+        Transaction {
+            shards: guards,
+            shared: self.shared.clone(),
+            snapshot: false,
+        }
+    }
+
+    /// Applies the objects in a log record committed at `ts` by another
+    /// member of the group, and publishes them to watchers.
+    pub async fn apply_log(&self, ts: Timestamp, objects: Vec<ObjectLog>) -> Result<()> {
+        for object in objects {
+            let index = shard_index(&object.id);
+            let mut shard = self.shards[index].lock().await;
+            // Changed members apply to the current object, which may have
+            // been evicted.
+            if !object.members.is_empty() && !shard.read_cache.contains_key(&object.id) {
+                if let Some(store) = &self.shared.store {
+                    if let Some(current) = store.get(self.shared.id, &object.id).await? {
+                        shard.load(current)?;
+                    }
+                }
+            }
+            shard.dirty.insert(object.id.clone());
+            shard.apply_log(object, ts)?;
+            shard.publish();
+        }
+        Ok(())
+    }
+
+    /// Subscribes to changes from sequence `start`, or to the changes after
+    /// now if `start` is zero.
+    ///
+    /// Sequences are not durable, so they can only be resumed from the same
+    /// instance of the collection that assigned them.
+    pub fn watch(&self, start: u64) -> Result<Changes> {
+        // Subscribes under the lock so that no change falls between the
+        // retained and the received ones.
+        let recent = self.shared.recent.lock().unwrap();
+        let receiver = self.shared.changes.subscribe();
+        if start == 0 {
+            return Ok(Changes {
+                last_sequence: recent.sequence,
+                retained: Vec::new(),
+                receiver,
+            });
+        }
+        if start <= recent.retained_after || start > recent.sequence + 1 {
+            return Err(Error::corrupted(format!(
+                "changes from sequence {} are not retained",
+                start
+            )));
+        }
+        let retained = recent
+            .changes
+            .iter()
+            .filter(|event| event.sequence >= start)
+            .cloned()
+            .collect();
+        Ok(Changes {
+            last_sequence: start - 1,
+            retained,
+            receiver,
+        })
+    }
+}
+
+/// The changes to a collection that a watcher subscribes to.
+pub struct Changes {
+    /// The sequence of the change before the first one delivered.
+    pub last_sequence: u64,
+    /// The retained changes to deliver before the received ones.
+    pub retained: Vec<WatchResponse>,
+    pub receiver: broadcast::Receiver<WatchResponse>,
+}
+
+// The recent changes of a collection, which watchers can resume from.
+#[derive(Default)]
+struct Recent {
+    // The sequence of the last change.
+    sequence: u64,
+    changes: VecDeque<WatchResponse>,
+    // The sequence of the last change that is no longer retained.
+    retained_after: u64,
+}
+
+/// A set of locked shards that stages changes until they are committed.
+///
+/// Changes that are not committed are rolled back when the transaction is
+/// dropped.
+pub struct Transaction {
+    shards: BTreeMap<usize, OwnedMutexGuard<Inner>>,
+    shared: Arc<Shared>,
+    snapshot: bool,
+}
+
+impl Transaction {
+    /// Loads the objects that `req` reads or writes into memory, reading them
+    /// from the store if they are not cached.
+    ///
+    /// The index entries that lookups read and the objects in them are
+    /// loaded too, and so are the evicted objects in the ranges of scans, so
+    /// that they are in memory before a snapshot is taken.
+    pub async fn load(&mut self, req: &CollectionTxnRequest) -> Result<()> {
+        let mut ids = Vec::new();
+        for expr in &req.exprs {
+            ids.extend(expr_id(expr).map(<[u8]>::to_vec));
+            ids.extend(source_ids(expr).into_iter().map(<[u8]>::to_vec));
+        }
+        self.load_objects(ids).await?;
+        let entries: Vec<_> = req
+            .lookups
+            .iter()
+            .filter_map(|lookup| self.lookup_entries(lookup).ok())
+            .flat_map(|(_, _, entries)| entries)
+            .collect();
+        // Objects that scans would return but have been evicted are read
+        // from the store again.
+        let mut evicted = Vec::new();
+        for scan in &req.scans {
+            for shard in self.shards.values() {
+                evicted.extend(shard.evicted_in(scan));
+            }
         }
+        self.load_objects(evicted).await?;
+        self.load_objects(entries.clone()).await?;
+        let mut ids = Vec::new();
+        for entry in entries {
+            ids.extend(self.shard(&entry)?.entry(&entry));
+        }
+        self.load_objects(ids).await
     }
 
-    pub async fn execute(&self, req: CollectionTxnRequest) -> Result<CollectionTxnResponse> {
-        let mut inner = self.inner.lock().await;
+    async fn load_objects(&mut self, ids: Vec<Vec<u8>>) -> Result<()> {
+        let store = match &self.shared.store {
+            Some(store) => store.clone(),
+            None => return Ok(()),
+        };
+        for id in ids {
+            let id = id.as_slice();
+            let shard = self.shard(id)?;
+            if shard.read_cache.touch(id) {
+                self.shared.cache.record_hit();
+                continue;
+            }
+            self.shared.cache.record_miss();
+            if let Some(object) = store.get(self.shared.id, id).await? {
+                // Objects that have expired are not loaded.
+                let now = self.shared.clock.now();
+                if object.expire_at == 0 || object.expire_at > now {
+                    self.shard(id)?.load(object)?;
+                    continue;
+                }
+            }
+            self.shard(id)?.evicted.remove(id);
+        }
+        Ok(())
+    }
+
+    /// Turns the transaction into a read-only view of the locked shards as
+    /// of `ts`.
+    pub fn read_at(&mut self, ts: Timestamp) {
+        let now = Instant::now();
+        for shard in self.shards.values_mut() {
+            shard.expire(now);
+            shard.read_at(ts);
+        }
+        self.snapshot = true;
+    }
+
+    /// Executes a request, and updates the indexes of the objects it writes.
+    pub async fn execute(&mut self, req: CollectionTxnRequest) -> Result<CollectionTxnResponse> {
+        if self.snapshot {
+            if !req.exprs.iter().all(is_read_only) {
+                return Err(Error::invalid_argument("snapshot is read-only"));
+            }
+        } else {
+            let now = Instant::now();
+            for shard in self.shards.values_mut() {
+                shard.expire(now);
+            }
+        }
+        // Checks all conditions before applying any expression, so a failed
+        // condition aborts the whole request.
+        for expr in &req.exprs {
+            let id = expr_id(expr)?;
+            if index::is_entry(id) {
+                return Err(Error::invalid_argument("object id is reserved"));
+            }
+            self.shard(id)?.check_expr(expr)?;
+        }
+        // A snapshot never writes, so it never updates indexes.
+        let indexes = if self.snapshot {
+            Vec::new()
+        } else {
+            self.shared.indexes()
+        };
+        let mut written = BTreeMap::new();
+        if !indexes.is_empty() {
+            for expr in req.exprs.iter().filter(|expr| !is_read_only(expr)) {
+                let id = expr_id(expr)?;
+                written.insert(id.to_owned(), self.index_keys(&indexes, id)?);
+            }
+        }
         let mut res = CollectionTxnResponse::default();
         for expr in req.exprs {
-            let result = inner.handle_expr(expr)?;
+            let result = self.handle_expr(expr)?;
             res.results.push(result);
         }
+        self.update_indexes(&indexes, written).await?;
+        for scan in req.scans {
+            let result = self.handle_scan(scan)?;
+            res.scans.push(result);
+        }
+        for lookup in req.lookups {
+            let result = self.handle_lookup(lookup)?;
+            res.lookups.push(result);
+        }
         Ok(res)
     }
+
+    /// Returns the objects changed by the transaction as they would be
+    /// committed at `ts`, or none if nothing is changed.
+    pub fn log(&self, ts: Timestamp) -> Option<CollectionLog> {
+        if self.snapshot {
+            return None;
+        }
+        let now = Instant::now();
+        let objects: Vec<_> = self
+            .shards
+            .values()
+            .flat_map(|shard| {
+                shard
+                    .undo_log
+                    .iter()
+                    .filter_map(|(id, undo)| shard.object_log(id, undo, ts, now))
+            })
+            .collect();
+        if objects.is_empty() {
+            return None;
+        }
+        Some(CollectionLog {
+            id: self.shared.id,
+            name: self.shared.name.clone(),
+            objects,
+            shard: self.shared.shard,
+        })
+    }
+
+    /// Commits the changes with the timestamp `ts`.
+    pub fn commit(mut self, ts: Timestamp) {
+        // The view of a snapshot is rolled back on drop.
+        if self.snapshot {
+            return;
+        }
+        for shard in self.shards.values_mut() {
+            shard.commit(ts);
+        }
+    }
+
+    fn shard(&mut self, id: &[u8]) -> Result<&mut Inner> {
+        self.shards
+            .get_mut(&shard_index(id))
+            .map(|guard| &mut **guard)
+            .ok_or_else(|| Error::internal("shard is not locked"))
+    }
+
+    // Returns the keys of an object in each of the indexes.
+    fn index_keys(&mut self, indexes: &[IndexDesc], id: &[u8]) -> Result<Vec<Option<Vec<u8>>>> {
+        let object = self.shard(id)?.read_cache.get(id);
+        let keys = indexes
+            .iter()
+            .map(|desc| object.and_then(|object| index::index_key(desc, object)))
+            .collect();
+        Ok(keys)
+    }
+
+    // Moves the written objects from the entries of their old keys to the
+    // entries of their new keys.
+    async fn update_indexes(
+        &mut self,
+        indexes: &[IndexDesc],
+        written: BTreeMap<Vec<u8>, Vec<Option<Vec<u8>>>>,
+    ) -> Result<()> {
+        let mut changes = Vec::new();
+        for (id, old_keys) in written {
+            let new_keys = self.index_keys(indexes, &id)?;
+            for ((desc, old_key), new_key) in indexes.iter().zip(old_keys).zip(new_keys) {
+                if old_key == new_key {
+                    continue;
+                }
+                let partition = shard_index(&id);
+                if let Some(key) = old_key {
+                    let entry = index::entry_id(self.shared.shard, partition, &desc.name, &key);
+                    changes.push((entry, id.clone(), false));
+                }
+                if let Some(key) = new_key {
+                    let entry = index::entry_id(self.shared.shard, partition, &desc.name, &key);
+                    changes.push((entry, id.clone(), true));
+                }
+            }
+        }
+        let entries = changes.iter().map(|(entry, ..)| entry.clone()).collect();
+        self.load_objects(entries).await?;
+        for (entry, id, insert) in changes {
+            let shard = self.shard(&entry)?;
+            let mut ids = shard.entry(&entry);
+            if insert {
+                ids.insert(id);
+            } else {
+                ids.remove(&id);
+            }
+            shard.set_entry(&entry, ids);
+        }
+        Ok(())
+    }
+
+    // Returns the index, the key and the entries in all shards that `lookup`
+    // reads.
+    fn lookup_entries(&self, lookup: &LookupExpr) -> Result<(IndexDesc, Vec<u8>, Vec<Vec<u8>>)> {
+        let desc = self
+            .shared
+            .indexes()
+            .into_iter()
+            .find(|desc| desc.name == lookup.index)
+            .ok_or_else(|| Error::NotFound(format!("index {}", lookup.index)))?;
+        let key = lookup
+            .key
+            .as_ref()
+            .and_then(|key| key.value.as_ref())
+            .and_then(index::encode_key)
+            .ok_or_else(|| Error::invalid_argument("require blob, text or i64 key"))?;
+        let entries = (0..NUM_SHARDS)
+            .map(|partition| index::entry_id(self.shared.shard, partition, &desc.name, &key))
+            .collect();
+        Ok((desc, key, entries))
+    }
+
+    fn handle_lookup(&mut self, lookup: LookupExpr) -> Result<LookupResult> {
+        let (desc, key, entries) = self.lookup_entries(&lookup)?;
+        let mut ids = BTreeSet::new();
+        for entry in entries {
+            ids.append(&mut self.shard(&entry)?.entry(&entry));
+        }
+        let mut result = LookupResult::default();
+        for id in ids {
+            // Entries that are not cached when their objects expire keep the
+            // ids, so objects are checked against the key again.
+            if let Some(object) = self.shard(&id)?.read_cache.get(&id) {
+                if index::index_key(&desc, object).as_ref() == Some(&key) {
+                    result.values.push(object.to_value().into());
+                    result.ids.push(id);
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn handle_expr(&mut self, expr: Expr) -> Result<ExprResult> {
+        let id = expr_id(&expr)?.to_owned();
+        if !is_read_only(&expr) {
+            self.shard(&id)?.prepare(&id);
+        }
+        let exprs = match expr.call {
+            Some(call) => vec![Expr {
+                call: Some(call),
+                ..Default::default()
+            }],
+            None => expr.subexprs,
+        };
+        let mut result = ExprResult::default();
+        for expr in exprs {
+            let expr = self.resolve_sources(expr)?;
+            let mut values = self
+                .shard(&id)?
+                .handle_object_exprs(&id, vec![expr])?
+                .values;
+            result.values.append(&mut values);
+        }
+        Ok(result)
+    }
+
+    // Replaces the source ids of a set operation with the source sets, since
+    // the sources may belong to other shards.
+    fn resolve_sources(&mut self, mut expr: Expr) -> Result<Expr> {
+        if matches!(expr.from, Some(expr::From::Index(_))) {
+            return Ok(expr);
+        }
+        if let Some(call) = expr.call.as_mut() {
+            if is_set_operation(call) {
+                for arg in &mut call.args {
+                    if let Some(Value::BlobValue(source)) = &arg.value {
+                        let values = match self.shard(source)?.value(source)? {
+                            Some(Value::SetValue(v)) => v.values.clone(),
+                            Some(_) => return Err(Error::invalid_argument("require set object")),
+                            None => Vec::new(),
+                        };
+                        *arg = Value::SetValue(SetValue { values }).into();
+                    }
+                }
+            }
+        }
+        Ok(expr)
+    }
+
+    fn handle_scan(&mut self, scan: ScanExpr) -> Result<ScanResult> {
+        let mut objects = Vec::new();
+        for shard in self.shards.values() {
+            let result = shard.handle_scan(scan.clone())?;
+            let mut values = result.values.into_iter();
+            for id in result.ids {
+                objects.push((id, values.next()));
+            }
+        }
+        objects.sort_by(|a, b| a.0.cmp(&b.0));
+        if scan.limit > 0 {
+            objects.truncate(scan.limit as usize);
+        }
+        let mut result = ScanResult::default();
+        for (id, value) in objects {
+            result.ids.push(id);
+            result.values.extend(value);
+        }
+        Ok(result)
+    }
+}
+
+impl Drop for Transaction {
+    fn drop(&mut self) {
+        for shard in self.shards.values_mut() {
+            shard.rollback();
+            shard.publish();
+            shard.evict();
+        }
+    }
+}
+
+async fn reclaim_expired(shards: Weak<Vec<Arc<Mutex<Inner>>>>) {
+    let mut interval = tokio::time::interval(RECLAIM_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Some(shards) = shards.upgrade() {
+            // Locks one shard at a time, so it never holds a lock that a
+            // transaction waits for while waiting for another one.
+            for shard in shards.iter() {
+                let mut shard = shard.lock().await;
+                shard.expire(Instant::now());
+                shard.publish();
+                shard.prune();
+                shard.evict();
+            }
+        } else {
+            break;
+        }
+    }
+}
+
+// Returns the shard of an object. Index entries are in the shards of the
+// objects they hold.
+fn shard_index(id: &[u8]) -> usize {
+    if let Some(partition) = index::entry_partition(id) {
+        return partition % NUM_SHARDS;
+    }
+    let mut hasher = DefaultHasher::new();
+    id.hash(&mut hasher);
+    (hasher.finish() % NUM_SHARDS as u64) as usize
+}
+
+fn expr_id(expr: &Expr) -> Result<&[u8]> {
+    if let Some(expr::From::Id(id)) = &expr.from {
+        Ok(id)
+    } else {
+        Err(Error::invalid_argument("missing object id"))
+    }
+}
+
+fn shard_owner(desc: &CollectionDesc, shard: u64) -> String {
+    shard_by_id(desc, shard).map_or_else(String::new, |s| s.cooperator.clone())
+}
+
+/// Returns the ids of the objects that `expr` reads besides its own.
+pub fn source_ids(expr: &Expr) -> Vec<&[u8]> {
+    let object_calls = expr
+        .subexprs
+        .iter()
+        .filter(|e| !matches!(e.from, Some(expr::From::Index(_))))
+        .filter_map(|e| e.call.as_ref());
+    expr.call
+        .iter()
+        .chain(object_calls)
+        .filter(|call| is_set_operation(call))
+        .flat_map(|call| call.args.iter())
+        .filter_map(|arg| match &arg.value {
+            Some(Value::BlobValue(id)) => Some(id.as_slice()),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_set_operation(call: &CallExpr) -> bool {
+    matches!(
+        Function::from_i32(call.func),
+        Some(Function::Union | Function::Intersection)
+    )
 }
 
 struct Inner {
-    read_cache: BTreeMap<Vec<u8>, Value>,
-    _write_cache: BTreeMap<Vec<u8>, Vec<Expr>>,
+    read_cache: ReadCache,
+    deadlines: BTreeMap<Vec<u8>, Instant>,
+    expiry_queue: BTreeSet<(Instant, Vec<u8>)>,
+    dirty: BTreeSet<Vec<u8>>,
+    shared: Arc<Shared>,
+    // The ids of objects that are evicted from the read cache, which scans
+    // load from the store.
+    evicted: BTreeSet<Vec<u8>>,
+    undo_log: BTreeMap<Vec<u8>, Undo>,
+    // The replaced states of objects, each tagged with the timestamp at which
+    // it is replaced, in timestamp order.
+    history: BTreeMap<Vec<u8>, VecDeque<(Timestamp, Saved)>>,
+    history_queue: VecDeque<(Timestamp, Vec<u8>)>,
+}
+
+// The state of an object before it is changed by a transaction.
+struct Undo {
+    saved: Saved,
+    deadline: Option<Instant>,
+    dirty: bool,
+}
+
+// The replaced state of an object.
+#[derive(Clone)]
+enum Saved {
+    Object(Option<Object>),
+    // The old values of the changed members of a map or a sorted set, or
+    // none for members that didn't exist.
+    Members(BTreeMap<Vec<u8>, Option<Value>>),
 }
 
 impl Inner {
-    fn new() -> Self {
+    fn new(shared: Arc<Shared>) -> Self {
         Self {
-            read_cache: BTreeMap::new(),
-            _write_cache: BTreeMap::new(),
+            read_cache: ReadCache::new(shared.cache.clone()),
+            deadlines: BTreeMap::new(),
+            expiry_queue: BTreeSet::new(),
+            dirty: BTreeSet::new(),
+            shared,
+            evicted: BTreeSet::new(),
+            undo_log: BTreeMap::new(),
+            history: BTreeMap::new(),
+            history_queue: VecDeque::new(),
         }
     }
 
-    fn handle_expr(&mut self, expr: Expr) -> Result<ExprResult> {
-        let id = if let Some(expr::From::Id(id)) = expr.from {
+    // Saves the state of an object before its first change in a transaction.
+    // Maps and sorted sets save only the members that are changed, so that
+    // changing a few members doesn't copy the whole object.
+    fn prepare(&mut self, id: &[u8]) {
+        if !self.undo_log.contains_key(id) {
+            let saved = match self.read_cache.get(id) {
+                Some(Object::Map(_) | Object::SortedSet(_)) => Saved::Members(BTreeMap::new()),
+                object => Saved::Object(object.cloned()),
+            };
+            let undo = Undo {
+                saved,
+                deadline: self.deadlines.get(id).cloned(),
+                dirty: self.dirty.contains(id),
+            };
+            self.undo_log.insert(id.to_owned(), undo);
+        }
+    }
+
+    // Saves the whole object before it is replaced or changed as a whole.
+    fn save(&mut self, id: &[u8]) {
+        self.prepare(id);
+        if let Some(undo) = self.undo_log.get_mut(id) {
+            if let Saved::Members(members) = &mut undo.saved {
+                let mut object = self.read_cache.get(id).cloned();
+                if let Some(object) = &mut object {
+                    restore_members(object, std::mem::take(members));
+                }
+                undo.saved = Saved::Object(object);
+            }
+        }
+    }
+
+    // Saves a member of a map or a sorted set before its first change in a
+    // transaction.
+    fn save_member(&mut self, id: &[u8], key: &[u8]) {
+        let value = self
+            .read_cache
+            .get(id)
+            .and_then(|object| object.member(key));
+        if let Some(members) = self.saved_members(id) {
+            members.entry(key.to_owned()).or_insert(value);
+        }
+    }
+
+    // Returns the saved members of an object if only its members are
+    // changed in the transaction.
+    fn saved_members(&mut self, id: &[u8]) -> Option<&mut BTreeMap<Vec<u8>, Option<Value>>> {
+        match self.undo_log.get_mut(id) {
+            Some(Undo {
+                saved: Saved::Members(members),
+                ..
+            }) => Some(members),
+            _ => None,
+        }
+    }
+
+    fn commit(&mut self, ts: Timestamp) {
+        for (id, undo) in std::mem::take(&mut self.undo_log) {
+            if matches!(&undo.saved, Saved::Members(members) if members.is_empty()) {
+                continue;
+            }
+            // Objects may have been changed in place.
+            self.read_cache.recharge(&id);
+            self.record(id, undo.saved, ts);
+        }
+    }
+
+    // Evicts objects if the caches are full. Evicted objects are read from
+    // the store again, which also keeps their expiration times.
+    fn evict(&mut self) {
+        for id in self.read_cache.evict() {
+            self.clear_deadline(&id);
+            self.evicted.insert(id);
+        }
+    }
+
+    // Records that the `saved` state of an object is replaced at `ts`.
+    fn record(&mut self, id: Vec<u8>, saved: Saved, ts: Timestamp) {
+        self.history_queue.push_back((ts, id.clone()));
+        self.history.entry(id).or_default().push_back((ts, saved));
+    }
+
+    // Removes versions that are replaced before the retention window.
+    fn prune(&mut self) {
+        let now = self.shared.clock.now();
+        let retention = self.shared.retention.load(Ordering::Relaxed);
+        let cutoff = now.saturating_sub(retention);
+        while let Some((ts, id)) = self.history_queue.front() {
+            if *ts >= cutoff {
+                break;
+            }
+            if let Some(versions) = self.history.get_mut(id) {
+                versions.pop_front();
+                if versions.is_empty() {
+                    self.history.remove(id);
+                }
+            }
+            self.history_queue.pop_front();
+        }
+    }
+
+    // Replaces objects that have changed since `ts` with their versions at
+    // `ts`. The current versions are restored on rollback.
+    fn read_at(&mut self, ts: Timestamp) {
+        let mut changes = Vec::new();
+        for (id, versions) in &self.history {
+            // Changes since `ts` are undone from the first whole object they
+            // replace, or from the current object if only members are
+            // changed.
+            let mut base = None;
+            let mut members = Vec::new();
+            for (_, saved) in versions.iter().filter(|(replaced, _)| *replaced > ts) {
+                match saved {
+                    Saved::Object(object) => {
+                        base = Some(object.clone());
+                        break;
+                    }
+                    Saved::Members(saved) => members.push(saved.clone()),
+                }
+            }
+            if base.is_some() || !members.is_empty() {
+                changes.push((id.clone(), base, members));
+            }
+        }
+        for (id, base, members) in changes {
+            self.save(&id);
+            let mut object = base.unwrap_or_else(|| self.read_cache.get(&id).cloned());
+            if let Some(object) = &mut object {
+                for members in members.into_iter().rev() {
+                    restore_members(object, members);
+                }
+            }
+            match object {
+                Some(object) => self.read_cache.insert(id, object),
+                None => self.read_cache.remove(&id),
+            };
+        }
+    }
+
+    fn rollback(&mut self) {
+        for (id, undo) in std::mem::take(&mut self.undo_log) {
+            if !undo.dirty {
+                self.dirty.remove(&id);
+            }
+            let object = match undo.saved {
+                Saved::Object(object) => object,
+                // Members are changed in place, which keeps the deadline.
+                Saved::Members(members) => {
+                    if let Some(object) = self.read_cache.get_mut(&id) {
+                        restore_members(object, members);
+                    }
+                    self.read_cache.recharge(&id);
+                    continue;
+                }
+            };
+            self.remove(&id);
+            if let Some(object) = object {
+                self.read_cache.insert(id.clone(), object);
+            }
+            if let Some(deadline) = undo.deadline {
+                self.deadlines.insert(id.clone(), deadline);
+                self.expiry_queue.insert((deadline, id));
+            }
+        }
+    }
+
+    /// Publishes the latest values of changed objects to watchers.
+    ///
+    /// Sequences are unique within the collection, or within the shard of it
+    /// that this serves, and increase in commit order.
+    fn publish(&mut self) {
+        let dirty = std::mem::take(&mut self.dirty);
+        let mut recent = self.shared.recent.lock().unwrap();
+        for id in dirty {
+            // Index entries change with the objects they point to.
+            if index::is_entry(&id) {
+                continue;
+            }
+            recent.sequence += 1;
+            let sequence = recent.sequence;
+            // Values are not encoded if nobody watches, and there is nothing
+            // to resume from then.
+            if self.shared.changes.receiver_count() == 0 {
+                recent.changes.clear();
+                recent.retained_after = sequence;
+                continue;
+            }
+            let value = self.read_cache.get(&id).map(|v| v.to_value().into());
+            let event = WatchResponse {
+                id,
+                value,
+                sequence,
+            };
+            if recent.changes.len() == WATCH_HISTORY_SIZE {
+                if let Some(dropped) = recent.changes.pop_front() {
+                    recent.retained_after = dropped.sequence;
+                }
+            }
+            recent.changes.push_back(event.clone());
+            // Sending fails only if there are no watchers.
+            let _ = self.shared.changes.send(event);
+        }
+    }
+
+    /// Inserts an object that expires after `ttl`, or the collection's
+    /// default ttl if not given.
+    fn insert(&mut self, id: &[u8], value: Value, ttl: Option<Duration>) -> Result<()> {
+        let object = Object::try_from(value)?;
+        self.insert_object(id, object, ttl);
+        Ok(())
+    }
+
+    fn insert_object(&mut self, id: &[u8], object: Object, ttl: Option<Duration>) {
+        self.clear_deadline(id);
+        if let Some(ttl) = ttl.or_else(|| self.shared.ttl()) {
+            let deadline = Instant::now() + ttl;
+            self.deadlines.insert(id.to_owned(), deadline);
+            self.expiry_queue.insert((deadline, id.to_owned()));
+        }
+        self.read_cache.insert(id.to_owned(), object);
+    }
+
+    fn remove(&mut self, id: &[u8]) -> Option<Object> {
+        self.clear_deadline(id);
+        self.read_cache.remove(id)
+    }
+
+    // Returns the ids in an index entry.
+    fn entry(&self, id: &[u8]) -> BTreeSet<Vec<u8>> {
+        match self.read_cache.get(id) {
+            Some(Object::Value(Value::SetValue(v))) => v
+                .values
+                .iter()
+                .filter_map(|v| match &v.value {
+                    Some(Value::BlobValue(id)) => Some(id.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => BTreeSet::new(),
+        }
+    }
+
+    // Replaces the ids in an index entry.
+    fn set_entry(&mut self, id: &[u8], ids: BTreeSet<Vec<u8>>) {
+        self.save(id);
+        self.replace_entry(id, ids);
+    }
+
+    // Replaces the ids in an index entry without saving it for rollback.
+    // Entries never expire, and empty ones are removed.
+    fn replace_entry(&mut self, id: &[u8], ids: BTreeSet<Vec<u8>>) {
+        self.remove(id);
+        if !ids.is_empty() {
+            let values = ids
+                .into_iter()
+                .map(|id| Value::BlobValue(id).into())
+                .collect();
+            let object = Object::Value(Value::SetValue(SetValue { values }));
+            self.read_cache.insert(id.to_owned(), object);
+        }
+    }
+
+    // Returns the change of an object as it is committed at `ts`, or none if
+    // it is not changed. If only members of the object are changed, only
+    // they are logged.
+    fn object_log(&self, id: &[u8], undo: &Undo, ts: Timestamp, now: Instant) -> Option<ObjectLog> {
+        let object = self.read_cache.get(id);
+        let (value, members) = match &undo.saved {
+            Saved::Object(_) => (object.map(|v| v.to_value().into()), Vec::new()),
+            Saved::Members(members) if members.is_empty() => return None,
+            Saved::Members(members) => {
+                let members = members
+                    .keys()
+                    .map(|key| MemberLog {
+                        key: key.clone(),
+                        value: object.and_then(|v| v.member(key)).map(Into::into),
+                    })
+                    .collect();
+                (None, members)
+            }
+        };
+        let expire_at = self.deadlines.get(id).map_or(0, |deadline| {
+            ts + deadline.saturating_duration_since(now).as_micros() as Timestamp
+        });
+        Some(ObjectLog {
+            id: id.to_owned(),
+            value,
+            expire_at,
+            members,
+        })
+    }
+
+    // Applies a change logged at `ts`, and records the replaced state for
+    // snapshot reads.
+    fn apply_log(&mut self, object: ObjectLog, ts: Timestamp) -> Result<()> {
+        let id = object.id.clone();
+        let saved = if object.members.is_empty() {
+            Saved::Object(self.load(object)?)
+        } else {
+            let current = self
+                .read_cache
+                .get_mut(&id)
+                .ok_or_else(|| Error::corrupted("missing object of changed members"))?;
+            let members = object
+                .members
+                .iter()
+                .map(|member| (member.key.clone(), current.member(&member.key)))
+                .collect();
+            current.apply_members(object.members)?;
+            self.read_cache.recharge(&id);
+            Saved::Members(members)
+        };
+        self.record(id, saved, ts);
+        Ok(())
+    }
+
+    // Replaces an object with its logged state, and returns the replaced
+    // object.
+    fn load(&mut self, object: ObjectLog) -> Result<Option<Object>> {
+        self.evicted.remove(&object.id);
+        let old = self.remove(&object.id);
+        let value = match object.value.and_then(|v| v.value) {
+            Some(value) => Object::try_from(value)?,
+            None => return Ok(old),
+        };
+        // Objects that have expired are removed by the next expiration.
+        if object.expire_at > 0 {
+            let remaining = object.expire_at.saturating_sub(self.shared.clock.now());
+            let deadline = Instant::now() + Duration::from_micros(remaining);
+            self.deadlines.insert(object.id.clone(), deadline);
+            self.expiry_queue.insert((deadline, object.id.clone()));
+        }
+        self.read_cache.insert(object.id, value);
+        Ok(old)
+    }
+
+    fn value(&self, id: &[u8]) -> Result<Option<&Value>> {
+        match self.read_cache.get(id) {
+            Some(Object::Value(v)) => Ok(Some(v)),
+            Some(_) => Err(Error::invalid_argument("unsupported object function")),
+            None => Ok(None),
+        }
+    }
+
+    fn value_mut(&mut self, id: &[u8]) -> Result<Option<&mut Value>> {
+        match self.read_cache.get_mut(id) {
+            Some(Object::Value(v)) => Ok(Some(v)),
+            Some(_) => Err(Error::invalid_argument("unsupported object function")),
+            None => Ok(None),
+        }
+    }
+
+    fn map(&self, id: &[u8]) -> Result<Option<&Map>> {
+        match self.read_cache.get(id) {
+            Some(Object::Map(v)) => Ok(Some(v)),
+            Some(_) => Err(Error::invalid_argument("require map object")),
+            None => Ok(None),
+        }
+    }
+
+    fn map_mut(&mut self, id: &[u8]) -> Result<Option<&mut Map>> {
+        match self.read_cache.get_mut(id) {
+            Some(Object::Map(v)) => Ok(Some(v)),
+            Some(_) => Err(Error::invalid_argument("require map object")),
+            None => Ok(None),
+        }
+    }
+
+    fn map_or_insert(&mut self, id: &[u8]) -> Result<&mut Map> {
+        if !self.read_cache.contains_key(id) {
+            self.insert_object(id, Map::default().into(), None);
+        }
+        self.map_mut(id)?
+            .ok_or_else(|| Error::internal("missing map"))
+    }
+
+    fn sorted_set(&self, id: &[u8]) -> Result<Option<&SortedSet>> {
+        match self.read_cache.get(id) {
+            Some(Object::SortedSet(v)) => Ok(Some(v)),
+            Some(_) => Err(Error::invalid_argument("require sorted set object")),
+            None => Ok(None),
+        }
+    }
+
+    fn sorted_set_mut(&mut self, id: &[u8]) -> Result<Option<&mut SortedSet>> {
+        match self.read_cache.get_mut(id) {
+            Some(Object::SortedSet(v)) => Ok(Some(v)),
+            Some(_) => Err(Error::invalid_argument("require sorted set object")),
+            None => Ok(None),
+        }
+    }
+
+    fn sorted_set_or_insert(&mut self, id: &[u8]) -> Result<&mut SortedSet> {
+        if !self.read_cache.contains_key(id) {
+            self.insert_object(id, SortedSet::default().into(), None);
+        }
+        self.sorted_set_mut(id)?
+            .ok_or_else(|| Error::internal("missing sorted set"))
+    }
+
+    fn clear_deadline(&mut self, id: &[u8]) {
+        if let Some(deadline) = self.deadlines.remove(id) {
+            self.expiry_queue.remove(&(deadline, id.to_owned()));
+        }
+    }
+
+    /// Removes objects that have expired by `now`.
+    fn expire(&mut self, now: Instant) {
+        while let Some((deadline, id)) = self.expiry_queue.iter().next().cloned() {
+            if deadline > now {
+                break;
+            }
+            let object = self.remove(&id);
+            if let Some(object) = &object {
+                self.unindex(&id, object);
+            }
+            self.record(id.clone(), Saved::Object(object), self.shared.clock.now());
+            self.dirty.insert(id);
+        }
+    }
+
+    // Removes an expired object from its index entries. Like expiration, this
+    // is not logged, since every member expires the object on its own.
+    fn unindex(&mut self, id: &[u8], object: &Object) {
+        for desc in self.shared.indexes() {
+            let key = match index::index_key(&desc, object) {
+                Some(key) => key,
+                None => continue,
+            };
+            let entry = index::entry_id(self.shared.shard, shard_index(id), &desc.name, &key);
+            let mut ids = self.entry(&entry);
+            if ids.remove(id) {
+                let old = self.read_cache.get(&entry).cloned();
+                self.replace_entry(&entry, ids);
+                self.record(entry, Saved::Object(old), self.shared.clock.now());
+            }
+        }
+    }
+
+    fn check_expr(&self, expr: &Expr) -> Result<()> {
+        let id = if let Some(expr::From::Id(id)) = &expr.from {
             id
         } else {
             return Err(Error::invalid_argument("missing object id"));
         };
-        let mut result = ExprResult::default();
-        if let Some(call) = expr.call {
-            self.handle_object_call(&id, call, &mut result)?;
-        } else {
-            let mut res = self.handle_object_exprs(&id, expr.subexprs)?;
-            let value = if res.values.len() <= 1 {
-                res.values.pop().unwrap_or_default()
-            } else {
-                ListValue { values: res.values }.into()
-            };
-            result.values.push(value);
+        if let Some(call) = &expr.call {
+            self.check_object_call(id, call)?;
+        }
+        for subexpr in &expr.subexprs {
+            match &subexpr.from {
+                Some(expr::From::Index(index)) => {
+                    if path::has_conditions(subexpr) {
+                        let member = self.member(id, index)?;
+                        if let Some(call) = &subexpr.call {
+                            path::check_call(member.as_ref(), call)?;
+                        }
+                        path::check_exprs(member.as_ref(), &subexpr.subexprs)?;
+                    }
+                }
+                _ => {
+                    if let Some(call) = &subexpr.call {
+                        self.check_object_call(id, call)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn check_object_call(&self, id: &[u8], call: &CallExpr) -> Result<()> {
+        if let Some(func) = Function::from_i32(call.func) {
+            if !path::is_condition(func) {
+                return Ok(());
+            }
+        }
+        let value = self.read_cache.get(id).map(Object::to_value);
+        // Normalizes the operand so that it compares with the stored
+        // representation, e.g. maps in key order.
+        let mut call = call.clone();
+        if let Some(operand) = call.args.first_mut() {
+            if let Some(v) = operand.value.take() {
+                *operand = Object::try_from(v)?.to_value().into();
+            }
+        }
+        path::check_call(value.as_ref(), &call)
+    }
+
+    // Returns the ids of evicted objects that a scan may return.
+    fn evicted_in(&self, scan: &ScanExpr) -> Vec<Vec<u8>> {
+        match scan_range(scan) {
+            Some(range) => self
+                .evicted
+                .range(range)
+                .filter(|id| !index::is_entry(id))
+                .take(scan_limit(scan))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn handle_scan(&self, scan: ScanExpr) -> Result<ScanResult> {
+        let mut result = ScanResult::default();
+        let range = match scan_range(&scan) {
+            Some(range) => range,
+            None => return Ok(result),
+        };
+        // Only cached objects are scanned, since the object engine can't
+        // iterate over buckets yet. Evicted objects in the range are loaded
+        // before the scan.
+        let objects = self
+            .read_cache
+            .range(range)
+            .filter(|(id, _)| !index::is_entry(id))
+            .take(scan_limit(&scan));
+        for (id, value) in objects {
+            result.ids.push(id.clone());
+            if !scan.ids_only {
+                result.values.push(value.to_value().into());
+            }
         }
         Ok(result)
     }
@@ -79,18 +1282,66 @@ impl Inner {
     fn handle_object_exprs(&mut self, id: &[u8], exprs: Vec<Expr>) -> Result<ExprResult> {
         let mut result = ExprResult::default();
         for expr in exprs {
-            let call = expr
-                .call
-                .ok_or_else(|| Error::invalid_argument("missing call expr"))?;
             if let Some(expr::From::Index(index)) = expr.from {
-                self.handle_member_call(id, call, index, &mut result)?;
+                if let Some(call) = expr.call {
+                    self.handle_member_call(id, call, index, &mut result)?;
+                } else {
+                    self.handle_member_exprs(id, index, expr.subexprs, &mut result)?;
+                }
             } else {
+                let call = expr
+                    .call
+                    .ok_or_else(|| Error::invalid_argument("missing call expr"))?;
                 self.handle_object_call(id, call, &mut result)?;
             }
         }
         Ok(result)
     }
 
+    // Applies `exprs` to a copy of the member, and stores it back if it is
+    // mutated.
+    fn handle_member_exprs(
+        &mut self,
+        id: &[u8],
+        index: ValueUnion,
+        exprs: Vec<Expr>,
+        result: &mut ExprResult,
+    ) -> Result<()> {
+        let mut member = self.member(id, &index)?;
+        if path::handle_exprs(&mut member, exprs, result)? {
+            let call = match member {
+                Some(value) => CallExpr {
+                    func: Function::Store as i32,
+                    args: vec![value.into()],
+                },
+                None => CallExpr {
+                    func: Function::Reset as i32,
+                    args: vec![],
+                },
+            };
+            self.handle_member_call(id, call, index, &mut ExprResult::default())?;
+        }
+        Ok(())
+    }
+
+    fn member(&self, id: &[u8], index: &ValueUnion) -> Result<Option<Value>> {
+        let index = index
+            .value
+            .as_ref()
+            .ok_or_else(|| Error::invalid_argument("missing index"))?;
+        match (self.read_cache.get(id), index) {
+            (Some(Object::Value(v)), _) => path::member(v, index),
+            (Some(Object::Map(v)), Value::BlobValue(key)) => {
+                Ok(v.get(key).and_then(|v| v.value.clone()))
+            }
+            (Some(Object::SortedSet(v)), Value::BlobValue(member)) => {
+                Ok(v.score(member).map(Value::F64Value))
+            }
+            (Some(_), _) => Err(Error::invalid_argument("require blob index")),
+            (None, _) => Ok(None),
+        }
+    }
+
     fn handle_object_call(
         &mut self,
         id: &[u8],
@@ -99,53 +1350,59 @@ impl Inner {
     ) -> Result<()> {
         let func = Function::from_i32(call.func)
             .ok_or_else(|| Error::invalid_argument("invalid function"))?;
+        // Popping saves only the popped members.
+        if is_mutation(func) && !matches!(func, Function::PopMin | Function::PopMax) {
+            self.save(id);
+        }
         let mut args = Args::new(call.args);
         match func {
             Function::Nop => {}
+            // Conditions are checked before any expression is applied.
+            Function::IfExists
+            | Function::IfNotExists
+            | Function::IfEq
+            | Function::IfNe
+            | Function::IfLt
+            | Function::IfLe
+            | Function::IfGt
+            | Function::IfGe => {}
             Function::Load => {
-                let value = self.read_cache.get(id).cloned();
+                let value = self.read_cache.get(id).map(Object::to_value);
                 result.values.push(value.into());
             }
             Function::Store => {
                 let value = args.take()?;
-                self.read_cache.insert(id.to_owned(), value);
+                let ttl = args.take_ttl()?;
+                self.insert(id, value, ttl)?;
             }
             Function::Reset => {
-                self.read_cache.remove(id);
-            }
-            Function::Add | Function::Sub => {
-                if let Some(value) = self.read_cache.get_mut(id) {
-                    if let Value::I64Value(v) = value {
-                        let operand = args.take_i64()?;
-                        if func == Function::Add {
-                            *v += operand;
-                        } else {
-                            *v -= operand;
-                        }
-                    } else {
-                        return Err(Error::invalid_argument("require numeric object"));
-                    }
-                } else {
-                    let value = args.take_numeric()?;
-                    self.read_cache.insert(id.to_owned(), value);
+                self.remove(id);
+            }
+            Function::Add | Function::Sub | Function::Mul | Function::Min | Function::Max => {
+                let operand = args.take_numeric()?;
+                let value = numeric::update(func, self.value(id)?, operand)?;
+                match self.value_mut(id)? {
+                    Some(v) => *v = value,
+                    None => self.insert(id, value, None)?,
                 }
             }
             Function::Len => {
-                let len = if let Some(value) = self.read_cache.get(id) {
-                    match value {
+                let len = match self.read_cache.get(id) {
+                    Some(Object::Value(value)) => match value {
                         Value::BlobValue(v) => v.len(),
                         Value::TextValue(v) => v.len(),
-                        Value::MapValue(v) => v.keys.len(),
                         Value::ListValue(v) => v.values.len(),
+                        Value::SetValue(v) => v.values.len(),
                         _ => return Err(Error::invalid_argument("require container object")),
-                    }
-                } else {
-                    0
+                    },
+                    Some(Object::Map(v)) => v.len(),
+                    Some(Object::SortedSet(v)) => v.len(),
+                    None => 0,
                 };
                 result.values.push(Value::I64Value(len as i64).into());
             }
             Function::Append => {
-                if let Some(value) = self.read_cache.get_mut(id) {
+                if let Some(value) = self.value_mut(id)? {
                     match value {
                         Value::BlobValue(v) => {
                             let mut operand = args.take_blob()?;
@@ -163,12 +1420,12 @@ impl Inner {
                     }
                 } else {
                     let value = args.take_sequence()?;
-                    self.read_cache.insert(id.to_owned(), value);
+                    self.insert(id, value, None)?;
                 }
             }
             Function::PushBack => {
                 let operand = args.take()?;
-                if let Some(value) = self.read_cache.get_mut(id) {
+                if let Some(value) = self.value_mut(id)? {
                     match value {
                         Value::ListValue(v) => {
                             v.values.push(operand.into());
@@ -179,12 +1436,12 @@ impl Inner {
                     let value = ListValue {
                         values: vec![operand.into()],
                     };
-                    self.read_cache.insert(id.to_owned(), value.into());
+                    self.insert(id, value.into(), None)?;
                 }
             }
             Function::PushFront => {
                 let operand = args.take()?;
-                if let Some(value) = self.read_cache.get_mut(id) {
+                if let Some(value) = self.value_mut(id)? {
                     match value {
                         Value::ListValue(v) => {
                             v.values.insert(0, operand.into());
@@ -195,9 +1452,124 @@ impl Inner {
                     let value = ListValue {
                         values: vec![operand.into()],
                     };
-                    self.read_cache.insert(id.to_owned(), value.into());
+                    self.insert(id, value.into(), None)?;
+                }
+            }
+            Function::Insert => {
+                let member = ValueUnion::from(args.take()?);
+                if let Some(value) = self.value_mut(id)? {
+                    match value {
+                        Value::SetValue(v) => {
+                            if !v.values.contains(&member) {
+                                v.values.push(member);
+                            }
+                        }
+                        _ => return Err(Error::invalid_argument("require set object")),
+                    }
+                } else {
+                    let value = SetValue {
+                        values: vec![member],
+                    };
+                    self.insert(id, value.into(), None)?;
                 }
             }
+            Function::Remove => {
+                let member = ValueUnion::from(args.take()?);
+                if let Some(value) = self.value_mut(id)? {
+                    match value {
+                        Value::SetValue(v) => {
+                            v.values.retain(|x| x != &member);
+                        }
+                        _ => return Err(Error::invalid_argument("require set object")),
+                    }
+                }
+            }
+            Function::Contains => {
+                let member = ValueUnion::from(args.take()?);
+                let contains = match self.value(id)? {
+                    Some(Value::SetValue(v)) => v.values.contains(&member),
+                    Some(_) => return Err(Error::invalid_argument("require set object")),
+                    None => false,
+                };
+                result.values.push(Value::I64Value(contains as i64).into());
+            }
+            Function::Union | Function::Intersection => {
+                // The transaction resolves source ids to sets beforehand.
+                let mut members: Option<Vec<ValueUnion>> = None;
+                while let Some(source) = args.take_option_set()? {
+                    let values = source.values;
+                    members = Some(match members {
+                        None => values,
+                        Some(mut members) if func == Function::Union => {
+                            for value in values {
+                                if !members.contains(&value) {
+                                    members.push(value);
+                                }
+                            }
+                            members
+                        }
+                        Some(mut members) => {
+                            members.retain(|x| values.contains(x));
+                            members
+                        }
+                    });
+                }
+                let value = SetValue {
+                    values: members.unwrap_or_default(),
+                };
+                self.insert(id, value.into(), None)?;
+            }
+            Function::Range => {
+                let start = args.take_blob()?;
+                let end = args.take_blob()?;
+                let value = self
+                    .map(id)?
+                    .map(|v| v.range(start, end))
+                    .unwrap_or_default();
+                result.values.push(Value::MapValue(value).into());
+            }
+            Function::Prefix => {
+                let prefix = args.take_blob()?;
+                let value = self.map(id)?.map(|v| v.prefix(&prefix)).unwrap_or_default();
+                result.values.push(Value::MapValue(value).into());
+            }
+            Function::RangeByRank => {
+                let start = args.take_i64()?;
+                let end = args.take_i64()?;
+                let value = self
+                    .sorted_set(id)?
+                    .map(|v| v.range_by_rank(start, end))
+                    .unwrap_or_default();
+                result.values.push(Value::SortedSetValue(value).into());
+            }
+            Function::RangeByScore => {
+                let min = args.take_f64()?;
+                let max = args.take_f64()?;
+                let value = match self.sorted_set(id)? {
+                    Some(v) => v.range_by_score(min, max)?,
+                    None => SortedSetValue::default(),
+                };
+                result.values.push(Value::SortedSetValue(value).into());
+            }
+            Function::PopMin | Function::PopMax => {
+                let count = args.take_i64()?.max(0) as usize;
+                let value = match self.sorted_set_mut(id)? {
+                    Some(v) if func == Function::PopMin => v.pop_min(count),
+                    Some(v) => v.pop_max(count),
+                    None => SortedSetValue::default(),
+                };
+                if let Some(members) = self.saved_members(id) {
+                    for (member, score) in value.members.iter().zip(&value.scores) {
+                        members
+                            .entry(member.clone())
+                            .or_insert(Some(Value::F64Value(*score)));
+                    }
+                }
+                result.values.push(Value::SortedSetValue(value).into());
+            }
+        }
+        if is_mutation(func) {
+            self.dirty.insert(id.to_owned());
         }
         Ok(())
     }
@@ -211,97 +1583,245 @@ impl Inner {
     ) -> Result<()> {
         let func = Function::from_i32(call.func)
             .ok_or_else(|| Error::invalid_argument("invalid function"))?;
+        // Conditions are checked before any expression is applied.
+        if path::is_condition(func) {
+            return Ok(());
+        }
+        // Member functions with a blob index create a missing object. Inserting
+        // a member with an f64 score creates a sorted set, while others create
+        // a map, whose fields may be sets of any values.
+        let blob_index = matches!(index.value, Some(Value::BlobValue(_)));
+        let score = matches!(
+            call.args.first().and_then(|arg| arg.value.as_ref()),
+            Some(Value::F64Value(_))
+        );
         let mut args = Args::new(call.args);
+        match self.read_cache.get(id) {
+            Some(Object::Value(_)) => {}
+            Some(Object::Map(_)) => {
+                return self.handle_map_member_call(id, func, args, index, result);
+            }
+            Some(Object::SortedSet(_)) => {
+                return self.handle_sorted_set_member_call(id, func, args, index, result);
+            }
+            None if blob_index && func == Function::Insert && score => {
+                return self.handle_sorted_set_member_call(id, func, args, index, result);
+            }
+            None if blob_index => {
+                return self.handle_map_member_call(id, func, args, index, result);
+            }
+            None => {}
+        }
         match func {
             Function::Nop => {}
             Function::Load => {
-                if let Some(value) = self.read_cache.get(id) {
-                    match value {
-                        Value::MapValue(v) => {
-                            if let Some(pos) = v.keys.iter().position(|x| x == &index) {
-                                result.values.push(v.values[pos].clone());
-                            }
-                        }
-                        Value::ListValue(v) => {
-                            if let Some(Value::I64Value(mut pos)) = index.value {
-                                // TODO: do more checks here
-                                let len = v.values.len() as i64;
-                                pos += len;
-                                if pos >= 0 && pos < len {
-                                    result.values.push(v.values[pos as usize].clone());
-                                } else {
-                                    return Err(Error::invalid_argument("index out of range"));
-                                }
-                            }
-                        }
-                        _ => return Err(Error::invalid_argument("require container object")),
-                    }
+                let index = index
+                    .value
+                    .ok_or_else(|| Error::invalid_argument("missing index"))?;
+                let value = match self.value(id)? {
+                    Some(value) => path::member(value, &index)?,
+                    None => None,
+                };
+                // Always returns a value so that results match reads in order.
+                result.values.push(value.into());
+            }
+            Function::Store | Function::Reset => {
+                let index = index
+                    .value
+                    .ok_or_else(|| Error::invalid_argument("missing index"))?;
+                let member = if func == Function::Store {
+                    Some(args.take()?)
+                } else {
+                    None
+                };
+                if let Some(value) = self.value_mut(id)? {
+                    path::set_member(value, index, member)?;
+                } else if member.is_some() {
+                    return Err(Error::invalid_argument("require blob index"));
                 }
             }
+            _ => {
+                let index = index
+                    .value
+                    .ok_or_else(|| Error::invalid_argument("missing index"))?;
+                let value = self
+                    .value_mut(id)?
+                    .ok_or_else(|| Error::invalid_argument("require blob index"))?;
+                let mut member = path::member(value, &index)?;
+                if path::handle_call(&mut member, func, args, result)? {
+                    path::set_member(value, index, member)?;
+                    self.dirty.insert(id.to_owned());
+                }
+                return Ok(());
+            }
+        }
+        if is_mutation(func) {
+            self.dirty.insert(id.to_owned());
+        }
+        Ok(())
+    }
+
+    fn handle_map_member_call(
+        &mut self,
+        id: &[u8],
+        func: Function,
+        mut args: Args,
+        index: ValueUnion,
+        result: &mut ExprResult,
+    ) -> Result<()> {
+        let key = match index.value {
+            Some(Value::BlobValue(v)) => v,
+            _ => return Err(Error::invalid_argument("require blob index")),
+        };
+        match func {
+            Function::Nop => {}
+            Function::Load => {
+                let value = self.map(id)?.and_then(|v| v.get(&key)).cloned();
+                result.values.push(value.unwrap_or_default());
+            }
             Function::Store => {
-                let operand = args.take()?;
-                if let Some(value) = self.read_cache.get_mut(id) {
-                    match value {
-                        Value::MapValue(v) => {
-                            if let Some(pos) = v.keys.iter().position(|x| x == &index) {
-                                v.values[pos] = operand.into();
-                            } else {
-                                v.keys.push(index);
-                                v.values.push(operand.into());
-                            }
-                        }
-                        Value::ListValue(v) => {
-                            if let Some(Value::I64Value(mut pos)) = index.value {
-                                let len = v.values.len() as i64;
-                                pos += len;
-                                if pos >= 0 && pos < len {
-                                    v.values[pos as usize] = operand.into();
-                                } else {
-                                    return Err(Error::invalid_argument("index out of range"));
-                                }
-                            }
-                        }
-                        _ => return Err(Error::invalid_argument("require container object")),
-                    }
-                } else {
-                    match index.value {
-                        Some(Value::BlobValue(_)) => {
-                            let value = MapValue {
-                                keys: vec![index],
-                                values: vec![operand.into()],
-                            };
-                            self.read_cache.insert(id.to_owned(), value.into());
+                let value = args.take()?;
+                self.save_member(id, &key);
+                self.map_or_insert(id)?.insert(key, value.into());
+            }
+            Function::Reset => {
+                self.save_member(id, &key);
+                if let Some(v) = self.map_mut(id)? {
+                    v.remove(&key);
+                }
+            }
+            _ => {
+                let mut field = self
+                    .map(id)?
+                    .and_then(|v| v.get(&key))
+                    .and_then(|v| v.value.clone());
+                if path::handle_call(&mut field, func, args, result)? {
+                    self.save_member(id, &key);
+                    let map = self.map_or_insert(id)?;
+                    match field {
+                        Some(value) => map.insert(key, value.into()),
+                        None => {
+                            map.remove(&key);
                         }
-                        _ => return Err(Error::invalid_argument("require blob index")),
                     }
+                    self.dirty.insert(id.to_owned());
+                }
+                return Ok(());
+            }
+        }
+        if is_mutation(func) {
+            self.dirty.insert(id.to_owned());
+        }
+        Ok(())
+    }
+
+    fn handle_sorted_set_member_call(
+        &mut self,
+        id: &[u8],
+        func: Function,
+        mut args: Args,
+        index: ValueUnion,
+        result: &mut ExprResult,
+    ) -> Result<()> {
+        let member = match index.value {
+            Some(Value::BlobValue(v)) => v,
+            _ => return Err(Error::invalid_argument("require blob member")),
+        };
+        match func {
+            Function::Nop => {}
+            Function::Load => {
+                let score = self.sorted_set(id)?.and_then(|v| v.score(&member));
+                result.values.push(score.map(Value::F64Value).into());
+            }
+            Function::Store => {
+                let score = args.take_f64()?;
+                self.save_member(id, &member);
+                self.sorted_set_or_insert(id)?.insert(member, score)?;
+            }
+            Function::Insert => {
+                // Keeps the score of an existing member, like inserting into a
+                // set.
+                let score = args.take_f64()?;
+                self.save_member(id, &member);
+                let set = self.sorted_set_or_insert(id)?;
+                if set.score(&member).is_none() {
+                    set.insert(member, score)?;
                 }
             }
+            Function::Add => {
+                let delta = args.take_f64()?;
+                self.save_member(id, &member);
+                self.sorted_set_or_insert(id)?.incr(member, delta)?;
+            }
             Function::Reset => {
-                if let Some(value) = self.read_cache.get_mut(id) {
-                    match value {
-                        Value::MapValue(v) => {
-                            if let Some(pos) = v.keys.iter().position(|x| x == &index) {
-                                v.keys.remove(pos);
-                                v.values.remove(pos);
-                            }
-                        }
-                        Value::ListValue(v) => {
-                            if let Some(Value::I64Value(mut pos)) = index.value {
-                                let len = v.values.len() as i64;
-                                pos += len;
-                                if pos >= 0 && pos < len {
-                                    v.values.remove(pos as usize);
-                                } else {
-                                    return Err(Error::invalid_argument("index out of range"));
-                                }
-                            }
-                        }
-                        _ => return Err(Error::invalid_argument("require container object")),
-                    }
+                self.save_member(id, &member);
+                if let Some(v) = self.sorted_set_mut(id)? {
+                    v.remove(&member);
                 }
             }
             _ => return Err(Error::invalid_argument("invalid member function")),
         }
+        if is_mutation(func) {
+            self.dirty.insert(id.to_owned());
+        }
         Ok(())
     }
 }
+
+// Restores the old values of members, which were valid when they were
+// replaced.
+fn restore_members(object: &mut Object, members: BTreeMap<Vec<u8>, Option<Value>>) {
+    for (key, value) in members {
+        let _ = object.set_member(key, value);
+    }
+}
+
+// Returns the range of ids that a scan reads, or none if it is empty.
+fn scan_range(scan: &ScanExpr) -> Option<(Bound<Vec<u8>>, Bound<Vec<u8>>)> {
+    let end = if scan.end.is_empty() {
+        Bound::Unbounded
+    } else if scan.start < scan.end {
+        Bound::Excluded(scan.end.clone())
+    } else {
+        return None;
+    };
+    Some((Bound::Included(scan.start.clone()), end))
+}
+
+fn scan_limit(scan: &ScanExpr) -> usize {
+    if scan.limit > 0 {
+        scan.limit as usize
+    } else {
+        usize::MAX
+    }
+}
+
+/// Returns true if the expression does not change any object.
+pub fn is_read_only(expr: &Expr) -> bool {
+    let read_only = expr.call.as_ref().map_or(true, |call| {
+        Function::from_i32(call.func).map_or(false, |func| !is_mutation(func))
+    });
+    read_only && expr.subexprs.iter().all(is_read_only)
+}
+
+fn is_mutation(func: Function) -> bool {
+    matches!(
+        func,
+        Function::Store
+            | Function::Reset
+            | Function::Add
+            | Function::Sub
+            | Function::Mul
+            | Function::Min
+            | Function::Max
+            | Function::Append
+            | Function::PushBack
+            | Function::PushFront
+            | Function::Insert
+            | Function::Remove
+            | Function::Union
+            | Function::Intersection
+            | Function::PopMin
+            | Function::PopMax
+    )
+}
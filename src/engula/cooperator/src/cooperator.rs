@@ -12,27 +12,104 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::Path;
+
 use engula_apis::*;
 use engula_supervisor::Supervisor;
-use tonic::Request;
+use tonic::{transport::Channel, Request};
 
-use crate::{apis::cooperator_server::Cooperator as _, Result, Server};
+use crate::{
+    apis::{cooperator_client::CooperatorClient, cooperator_server::Cooperator as _},
+    Journal, ObjectEngine, ReadCacheOptions, ReadCacheStats, Result, Server, WatchStream,
+};
 
 #[derive(Clone)]
 pub struct Cooperator {
-    server: Server,
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Local(Server),
+    Remote(CooperatorClient<Channel>),
 }
 
 impl Cooperator {
     pub fn new(supervisor: Supervisor) -> Self {
+        Self::local(Server::new(supervisor))
+    }
+
+    /// Creates a cooperator that logs changes to `journal` and recovers from
+    /// it.
+    pub fn with_journal(supervisor: Supervisor, journal: Journal) -> Self {
+        Self::local(Server::with_journal(supervisor, journal))
+    }
+
+    /// Creates a cooperator that logs changes to `journal`, and flushes them
+    /// into `engine` in the background. Objects that don't fit in the read
+    /// cache are read from `engine`.
+    pub fn with_object_engine(
+        supervisor: Supervisor,
+        journal: Journal,
+        engine: ObjectEngine,
+        cache: ReadCacheOptions,
+    ) -> Self {
+        let server = Server::with_object_engine(supervisor, journal, engine, cache);
+        Self::local(server)
+    }
+
+    /// Opens a cooperator that keeps its objects under `path`. See
+    /// [`Server::open`].
+    pub async fn open(
+        supervisor: Supervisor,
+        path: impl AsRef<Path>,
+        journal: Option<Journal>,
+    ) -> Result<Self> {
+        let server = Server::open(supervisor, path, journal).await?;
+        Ok(Self::local(server))
+    }
+
+    /// Connects to a cooperator served at `url`.
+    pub async fn connect(url: impl Into<String>) -> Result<Self> {
+        let client = CooperatorClient::connect(url.into()).await?;
+        Ok(Self {
+            inner: Inner::Remote(client),
+        })
+    }
+
+    fn local(server: Server) -> Self {
         Self {
-            server: Server::new(supervisor),
+            inner: Inner::Local(server),
+        }
+    }
+
+    /// Returns the size and hit/miss counters of the read cache, or `None` if
+    /// the cooperator is remote.
+    pub fn read_cache_stats(&self) -> Option<&ReadCacheStats> {
+        match &self.inner {
+            Inner::Local(server) => Some(server.read_cache_stats()),
+            Inner::Remote(_) => None,
         }
     }
 
     pub async fn txn(&self, req: TxnRequest) -> Result<TxnResponse> {
         let req = Request::new(req);
-        let res = self.server.txn(req).await?;
+        let res = match &self.inner {
+            Inner::Local(server) => server.txn(req).await?,
+            Inner::Remote(client) => client.clone().txn(req).await?,
+        };
         Ok(res.into_inner())
     }
+
+    pub async fn watch(&self, req: WatchRequest) -> Result<WatchStream> {
+        let req = Request::new(req);
+        let stream = match &self.inner {
+            Inner::Local(server) => server.watch(req).await?.into_inner(),
+            Inner::Remote(client) => {
+                let res = client.clone().watch(req).await?;
+                Box::pin(res.into_inner())
+            }
+        };
+        Ok(stream)
+    }
 }
@@ -12,64 +12,782 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock, Weak,
+    },
+    time::{Duration, Instant},
+};
 
 use engula_apis::*;
-use engula_supervisor::Supervisor;
-use tokio::sync::Mutex;
+use engula_supervisor::{shard_by_id, Supervisor};
+use stream_engine_client::Role;
+use tokio::sync::{Mutex, RwLock as AsyncRwLock};
 
-use crate::{Collection, Result};
+use crate::{
+    apis::LogRecord, journal::Tailer, write_cache, Changes, Clock, Collection, Error, Journal, Log,
+    ObjectEngine, ReadCacheStats, Result, Timestamp, WriteBatch, WriteCache, WriteCacheOptions,
+};
+
+// How long a member waits before it retries to follow the log.
+const RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// How long descriptors of collections and the assignment of the database are
+// cached. One that rejects a request is fetched again right away.
+const CACHE_TTL: Duration = Duration::from_secs(1);
+
+// How long old versions are retained if the database does not specify it.
+const DEFAULT_SNAPSHOT_RETENTION: Duration = Duration::from_secs(60);
 
 #[derive(Clone)]
 pub struct Database {
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Inner>,
 }
 
 impl Database {
-    pub fn new(desc: DatabaseDesc, supervisor: Supervisor) -> Self {
-        let inner = Inner::new(desc, supervisor);
-        Self {
-            inner: Arc::new(Mutex::new(inner)),
-        }
+    /// Opens a database, which logs its changes to the journal if given.
+    /// Changes in the journal are flushed into the object engine if both are
+    /// given, and objects are then cached within the capacity of `cache`.
+    ///
+    /// Each shard has its own log, and so do the unsharded collections of the
+    /// database together. A log is opened when it is first used, and it is
+    /// recovered before it serves requests.
+    pub async fn open(
+        desc: DatabaseDesc,
+        supervisor: Supervisor,
+        journal: Option<Journal>,
+        engine: Option<ObjectEngine>,
+        cache: Arc<ReadCacheStats>,
+    ) -> Result<Self> {
+        let write_cache = match (&journal, engine) {
+            (Some(_), Some(engine)) => {
+                let tenant = write_cache::tenant(&engine, desc.id).await?;
+                let options = WriteCacheOptions::default();
+                Some(WriteCache::new(options, tenant))
+            }
+            _ => None,
+        };
+        let inner = Arc::new(Inner::new(desc, supervisor, journal, write_cache, cache));
+        Ok(Self { inner })
     }
 
+    /// Executes a transaction.
+    ///
+    /// The changes to each shard are appended to the log of the shard, so a
+    /// logged transaction must not change objects in more than one shard.
     pub async fn execute(&self, req: DatabaseTxnRequest) -> Result<DatabaseTxnResponse> {
-        let mut inner = self.inner.lock().await;
+        let mut collections = BTreeMap::new();
+        for coreq in &req.requests {
+            let key = (coreq.name.clone(), coreq.shard);
+            let (co, shards) = match collections.entry(key) {
+                Entry::Occupied(ent) => ent.into_mut(),
+                Entry::Vacant(ent) => {
+                    let co = self.inner.collection(&coreq.name, coreq.shard).await?;
+                    ent.insert((co, BTreeSet::new()))
+                }
+            };
+            shards.append(&mut co.shards_of(coreq));
+        }
+        // Checks unsharded collections against the assignment of the
+        // database.
+        if collections.keys().any(|(_, shard)| *shard == 0) {
+            self.inner.locate(false).await?;
+        }
+        // Opens the logs of the shards, which recovers them if this member
+        // leads them. Logs of shards that are assigned to other members are
+        // not opened, since only the owner of a shard should lead its log.
+        let mut replicas = BTreeMap::new();
+        for (co, _) in collections.values() {
+            self.inner.check_assignment(co).await?;
+            if let Some(replica) = self.inner.replica(co.shard()).await? {
+                replicas.insert(co.shard(), replica);
+            }
+        }
+        // Locks all touched shards first, so that the requests are applied
+        // and published together or not at all. Shards are locked in the
+        // order of collection names, collection shards and then shard
+        // indexes, which avoids deadlocks between concurrent transactions.
+        let mut txns = BTreeMap::new();
+        for (key, (co, shards)) in collections {
+            let txn = co.begin(shards).await;
+            // Checks after locking, so that a fence waits for the
+            // transactions that pass the check.
+            self.inner.check_owner(&co)?;
+            txns.insert(key, txn);
+        }
+        // Followers may lag behind the leader, so they serve no requests,
+        // including reads and conditions.
+        for replica in replicas.values() {
+            self.inner.check_leader(replica)?;
+        }
+        for coreq in &req.requests {
+            if let Some(txn) = txns.get_mut(&(coreq.name.clone(), coreq.shard)) {
+                txn.load(coreq).await?;
+            }
+        }
+        // A nonzero timestamp reads the snapshot at that timestamp.
+        let read_ts = if req.ts > 0 { Some(req.ts) } else { None };
+        if let Some(ts) = read_ts {
+            // Checks the timestamp after locking, so that no version it needs
+            // is pruned and no transaction commits before it from now on.
+            let now = self.inner.clock.now();
+            if ts > now {
+                return Err(Error::invalid_argument("snapshot is in the future"));
+            }
+            let retention = self.inner.retention.load(Ordering::Relaxed);
+            if ts < now.saturating_sub(retention) {
+                return Err(Error::invalid_argument("snapshot is too old"));
+            }
+            for txn in txns.values_mut() {
+                txn.read_at(ts);
+            }
+        }
+        // Returning early drops the transactions, which rolls back the
+        // requests that have been applied.
         let mut res = DatabaseTxnResponse::default();
         for coreq in req.requests {
-            let co = inner.collection(&coreq.name).await?;
-            let cores = co.execute(coreq).await?;
-            res.responses.push(cores);
+            let txn = txns
+                .get_mut(&(coreq.name.clone(), coreq.shard))
+                .ok_or_else(|| Error::internal("missing collection"))?;
+            res.responses.push(txn.execute(coreq).await?);
         }
+        // Stamps the changes while the shards are still locked, so that the
+        // order of timestamps matches the order of conflicting transactions.
+        let ts = read_ts.unwrap_or_else(|| self.inner.clock.now());
+        let mut records: BTreeMap<u64, Vec<_>> = BTreeMap::new();
+        for ((_, shard), txn) in &txns {
+            if let Some(colog) = txn.log(ts) {
+                records.entry(*shard).or_default().push(colog);
+            }
+        }
+        // Records in different logs are not applied atomically.
+        if !replicas.is_empty() && records.len() > 1 {
+            return Err(Error::invalid_argument(
+                "transaction changes objects in multiple shards",
+            ));
+        }
+        for (shard, collections) in records {
+            if let Some(replica) = replicas.get(&shard) {
+                let record = LogRecord {
+                    ts,
+                    collections,
+                    ..Default::default()
+                };
+                // Changes take effect only after they are logged.
+                self.inner.append(replica, record).await?;
+            }
+        }
+        for txn in txns.into_values() {
+            txn.commit(ts);
+        }
+        res.ts = ts;
         Ok(res)
     }
+
+    pub fn id(&self) -> u64 {
+        self.inner.desc.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.inner.desc.name
+    }
+
+    /// Applies the options in `desc` to the database.
+    pub fn update(&self, desc: &DatabaseDesc) {
+        let retention = snapshot_retention(desc);
+        self.inner.retention.store(retention, Ordering::Relaxed);
+    }
+
+    /// Drops the state of collections that have been deleted, and closes the
+    /// logs of shards that are deleted or led by this member but assigned to
+    /// others.
+    pub async fn drop_deleted(&self) {
+        self.inner.drop_deleted().await;
+        self.inner.close_stale_logs().await;
+    }
+
+    /// Opens a shard of a collection, or the unsharded collections if
+    /// `coname` is empty and `shard` is zero, and returns the sequence of the
+    /// last record applied from the log of the shard.
+    ///
+    /// If `fence` is set, the shard is moved away, so it stops serving
+    /// requests and the returned sequence covers all the records it has
+    /// appended. The log of the shard is then closed, which hands it over to
+    /// the target of the move once the lease of this member expires.
+    /// Otherwise, it serves requests again if it was fenced.
+    pub async fn catch_up(&self, coname: &str, shard: u64, fence: bool) -> Result<u64> {
+        let cos = if coname.is_empty() && shard == 0 {
+            // Unsharded collections opened later check the fence of the
+            // database, and the ones opened before are fenced one by one.
+            self.inner.fenced.store(fence, Ordering::Release);
+            let collections = self.inner.collections.lock().await;
+            collections
+                .iter()
+                .filter(|((_, shard), _)| *shard == 0)
+                .map(|(_, co)| co.clone())
+                .collect()
+        } else {
+            vec![self.inner.collection(coname, shard).await?]
+        };
+        let replica = self.inner.replica(shard).await?.ok_or_else(|| {
+            Error::condition_failed(format!("database {} is not logged", self.inner.desc.name))
+        })?;
+        if !fence {
+            for co in &cos {
+                co.unfence();
+            }
+            return Ok(replica.last_sequence.load(Ordering::Relaxed));
+        }
+        for co in &cos {
+            co.fence().await;
+        }
+        let sequence = replica.last_sequence.load(Ordering::Relaxed);
+        self.inner.close_replica(shard).await?;
+        Ok(sequence)
+    }
+
+    /// Watches changes to a collection, or to a shard of it if `shard` is
+    /// nonzero, from sequence `start` if it is nonzero.
+    pub async fn watch(&self, coname: &str, shard: u64, start: u64) -> Result<Changes> {
+        let co = self.inner.collection(coname, shard).await?;
+        if shard == 0 {
+            self.inner.locate(false).await?;
+        }
+        self.inner.check_assignment(&co).await?;
+        co.watch(start)
+    }
 }
 
 struct Inner {
     sp: Supervisor,
     desc: DatabaseDesc,
-    collections: BTreeMap<u64, Collection>,
+    journal: Option<Journal>,
+    // Descriptors of collections by their names, and when they are fetched.
+    descs: RwLock<BTreeMap<String, (CollectionDesc, Instant)>>,
+    // Collections by their ids and shards.
+    collections: Mutex<BTreeMap<(u64, u64), Collection>>,
+    clock: Arc<Clock>,
+    // How long old versions of objects are retained for snapshot reads.
+    retention: Arc<AtomicU64>,
+    // The replicas of shards whose logs are open, where shard zero is the
+    // replica of the unsharded collections.
+    replicas: Mutex<BTreeMap<u64, Arc<Replica>>>,
+    // The cooperator that the unsharded collections are assigned to, which
+    // is empty if they are not assigned yet.
+    owner: RwLock<String>,
+    // When the owner is located, or none if it is not yet.
+    located_at: RwLock<Option<Instant>>,
+    // Set when the unsharded collections are moved away.
+    fenced: AtomicBool,
+    write_cache: Option<WriteCache>,
+    cache: Arc<ReadCacheStats>,
+}
+
+// The changes of a shard, or of all unsharded collections of a database,
+// that are kept by a log.
+struct Replica {
+    shard: u64,
+    log: Log,
+    // The epoch in which this member leads the log, or zero if it doesn't.
+    leading_epoch: AtomicU64,
+    // The largest sequence of records that are applied.
+    last_sequence: AtomicU64,
+    // Held by appends, so that the log is closed after they finish.
+    appending: AsyncRwLock<()>,
 }
 
 impl Inner {
-    fn new(desc: DatabaseDesc, supervisor: Supervisor) -> Self {
+    fn new(
+        desc: DatabaseDesc,
+        supervisor: Supervisor,
+        journal: Option<Journal>,
+        write_cache: Option<WriteCache>,
+        cache: Arc<ReadCacheStats>,
+    ) -> Self {
+        let retention = snapshot_retention(&desc);
         Self {
             sp: supervisor,
             desc,
-            collections: BTreeMap::new(),
+            journal,
+            descs: RwLock::new(BTreeMap::new()),
+            collections: Mutex::new(BTreeMap::new()),
+            clock: Arc::new(Clock::default()),
+            retention: Arc::new(AtomicU64::new(retention)),
+            replicas: Mutex::new(BTreeMap::new()),
+            owner: RwLock::new(String::new()),
+            located_at: RwLock::new(None),
+            fenced: AtomicBool::new(false),
+            write_cache,
+            cache,
+        }
+    }
+
+    // Returns the replica of a shard, or of the unsharded collections if
+    // `shard` is zero, or none if the database is not logged.
+    //
+    // The log of the replica is opened if it is not yet, which makes this
+    // member a candidate to lead it. A leader recovers all objects in the
+    // log before it returns, while a follower catches up in the background.
+    async fn replica(self: &Arc<Self>, shard: u64) -> Result<Option<Arc<Replica>>> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Ok(None),
+        };
+        // Holds the lock while a log is opened, so that it is recovered only
+        // once.
+        let mut replicas = self.replicas.lock().await;
+        if let Some(replica) = replicas.get(&shard) {
+            return Ok(Some(replica.clone()));
+        }
+        let log = journal.open_log(self.desc.id, shard).await?;
+        let mut states = log.subscribe();
+        let state = loop {
+            if let Some(state) = states.borrow().clone() {
+                break state;
+            }
+            states
+                .changed()
+                .await
+                .map_err(|_| Error::internal("log is closed"))?;
+        };
+        let replica = Arc::new(Replica {
+            shard,
+            log,
+            leading_epoch: AtomicU64::new(0),
+            last_sequence: AtomicU64::new(0),
+            appending: AsyncRwLock::new(()),
+        });
+        let mut tailer = replica.log.tail(0);
+        if state.role == Role::Leader {
+            self.catch_up(&replica, &mut tailer, state.epoch).await?;
+        }
+        tokio::spawn(follow(Arc::downgrade(self), replica.clone(), tailer));
+        replicas.insert(shard, replica.clone());
+        Ok(Some(replica))
+    }
+
+    // Closes the log of a replica if it is open, after which this member
+    // neither leads nor follows it.
+    async fn close_replica(&self, shard: u64) -> Result<()> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        let mut replicas = self.replicas.lock().await;
+        if let Some(replica) = replicas.get(&shard) {
+            let _appending = replica.appending.write().await;
+            replica.leading_epoch.store(0, Ordering::Release);
+            journal.close_log(self.desc.id, shard).await?;
+            replicas.remove(&shard);
+        }
+        Ok(())
+    }
+
+    // Appends a record to the log of a replica if this member leads it, and
+    // then adds its changes to the write cache.
+    async fn append(&self, replica: &Replica, record: LogRecord) -> Result<()> {
+        let _appending = replica.appending.read().await;
+        self.check_leader(replica)?;
+        let cache = match &self.write_cache {
+            Some(cache) => cache,
+            None => {
+                let sequence = replica.log.append(&record).await?;
+                replica.last_sequence.fetch_max(sequence, Ordering::Relaxed);
+                return Ok(());
+            }
+        };
+        let batches = write_batches(&record);
+        let generation = cache.pin().await;
+        match replica.log.append(&record).await {
+            Ok(sequence) => {
+                cache.add(generation, batches).await;
+                replica.last_sequence.fetch_max(sequence, Ordering::Relaxed);
+                Ok(())
+            }
+            Err(err) => {
+                cache.unpin(generation).await;
+                Err(err)
+            }
+        }
+    }
+
+    // Applies a record appended by another member of the group. The tailer
+    // has moved past the record, so it is retried until it is applied.
+    async fn apply(&self, replica: &Replica, sequence: u64, record: LogRecord) {
+        while self
+            .try_apply(replica, sequence, record.clone())
+            .await
+            .is_err()
+        {
+            tokio::time::sleep(RETRY_INTERVAL).await;
         }
     }
 
-    async fn collection(&mut self, name: &str) -> Result<Collection> {
-        let desc = self
+    async fn try_apply(
+        &self,
+        replica: &Replica,
+        sequence: u64,
+        mut record: LogRecord,
+    ) -> Result<()> {
+        self.clock.advance(record.ts);
+        // Skips the changes of collections that have been deleted.
+        let mut cologs = Vec::new();
+        for colog in std::mem::take(&mut record.collections) {
+            let mut res = self.collection(&colog.name, colog.shard).await;
+            // The cached descriptor may be of a deleted collection with the
+            // same name.
+            if matches!(&res, Ok(co) if co.id() != colog.id) {
+                self.forget(&colog.name);
+                res = self.collection(&colog.name, colog.shard).await;
+            }
+            let co = match res {
+                Ok(co) => co,
+                Err(Error::NotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            if co.id() == colog.id {
+                co.apply_log(record.ts, colog.objects.clone()).await?;
+                cologs.push(colog);
+            }
+        }
+        // Followers flush the records too, so that they can evict objects
+        // from their caches and serve from their own engines once they lead.
+        if let Some(cache) = &self.write_cache {
+            record.collections = cologs;
+            cache.add_logged(write_batches(&record)).await;
+        }
+        replica.last_sequence.fetch_max(sequence, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Applies the records in the log of a replica up to a barrier appended in
+    // `epoch`, and then accepts writes in the epoch.
+    async fn catch_up(&self, replica: &Replica, tailer: &mut Tailer, epoch: u64) -> Result<()> {
+        let (barrier, sequence) = replica.log.append_barrier().await?;
+        loop {
+            let (sequence, record) = tailer.next().await?;
+            if record.barrier == barrier {
+                break;
+            }
+            if record.barrier == 0 {
+                self.apply(replica, sequence, record).await;
+            }
+        }
+        replica.last_sequence.fetch_max(sequence, Ordering::Relaxed);
+        replica.leading_epoch.store(epoch, Ordering::Release);
+        Ok(())
+    }
+
+    // Returns an error if this member doesn't lead the log of a replica.
+    fn check_leader(&self, replica: &Replica) -> Result<()> {
+        let state = replica.log.state();
+        let leading = replica.leading_epoch.load(Ordering::Acquire);
+        match state {
+            Some(state) if state.role == Role::Leader && state.epoch == leading => Ok(()),
+            _ => {
+                let leader = state.and_then(|s| s.leader);
+                let what = if replica.shard == 0 {
+                    format!("database {}", self.desc.name)
+                } else {
+                    format!("shard {} of database {}", replica.shard, self.desc.name)
+                };
+                Err(Error::not_leader(format!(
+                    "{} is led by {}",
+                    what,
+                    leader.as_deref().unwrap_or("unknown member")
+                )))
+            }
+        }
+    }
+
+    // Returns an error if the shard of `co` is assigned to another member or
+    // is moved away. A member without a journal serves all shards.
+    fn check_owner(&self, co: &Collection) -> Result<()> {
+        let (owner, fenced) = if co.shard() == 0 {
+            let owner = self.owner.read().unwrap().clone();
+            (owner, co.is_fenced() || self.fenced.load(Ordering::Acquire))
+        } else {
+            (co.owner(), co.is_fenced())
+        };
+        let assigned_away = match &self.journal {
+            Some(journal) => !owner.is_empty() && owner != journal.id(),
+            None => false,
+        };
+        if assigned_away || fenced {
+            let what = if co.shard() == 0 {
+                format!("database {}", self.desc.name)
+            } else {
+                format!("shard {} of collection {}", co.shard(), co.name())
+            };
+            return Err(Error::not_leader(format!(
+                "{} is not served by this cooperator",
+                what
+            )));
+        }
+        Ok(())
+    }
+
+    // Checks the owner of the shard of `co` like `check_owner`, but fetches
+    // the assignment again if the cached one rejects it, since the shard may
+    // have been moved to this member since.
+    async fn check_assignment(&self, co: &Collection) -> Result<()> {
+        if self.check_owner(co).is_ok() {
+            return Ok(());
+        }
+        if co.shard() == 0 {
+            self.locate(true).await?;
+        } else {
+            // Updates the owner of `co` with the fetched descriptor.
+            self.forget(co.name());
+            self.collection(co.name(), co.shard()).await?;
+        }
+        self.check_owner(co)
+    }
+
+    // Refreshes the cooperator that the unsharded collections are assigned
+    // to, which they are checked against. A cached one is kept unless it is
+    // stale or `refresh` is set.
+    async fn locate(&self, refresh: bool) -> Result<()> {
+        if self.journal.is_none() {
+            return Ok(());
+        }
+        let located_at = *self.located_at.read().unwrap();
+        if !refresh && located_at.map_or(false, |at| at.elapsed() < CACHE_TTL) {
+            return Ok(());
+        }
+        let owner = self.sp.locate_database(self.desc.name.clone()).await?;
+        *self.owner.write().unwrap() = owner;
+        *self.located_at.write().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    // Returns the descriptor of a collection, which is cached for a while.
+    async fn describe(&self, name: &str) -> Result<CollectionDesc> {
+        if let Some((desc, fetched_at)) = self.descs.read().unwrap().get(name) {
+            if fetched_at.elapsed() < CACHE_TTL {
+                return Ok(desc.clone());
+            }
+        }
+        let res = self
             .sp
             .describe_collection(self.desc.name.clone(), name.to_owned())
-            .await?;
+            .await;
+        let mut descs = self.descs.write().unwrap();
+        match res {
+            Ok(desc) => {
+                descs.insert(name.to_owned(), (desc.clone(), Instant::now()));
+                Ok(desc)
+            }
+            Err(err) => {
+                descs.remove(name);
+                Err(err)
+            }
+        }
+    }
+
+    // Drops the cached descriptor of a collection, so that it is fetched
+    // again.
+    fn forget(&self, name: &str) {
+        self.descs.write().unwrap().remove(name);
+    }
+
+    // Returns a shard of a collection. A sharded collection is served by
+    // shards only, and an unsharded one by shard zero only.
+    async fn collection(&self, name: &str, shard: u64) -> Result<Collection> {
+        let mut desc = self.describe(name).await?;
+        let found = if shard == 0 {
+            desc.shards.is_empty()
+        } else {
+            shard_by_id(&desc, shard).is_some()
+        };
+        // The cached descriptor may be older than the shards.
+        if !found {
+            self.forget(name);
+            desc = self.describe(name).await?;
+        }
+        if shard == 0 && !desc.shards.is_empty() {
+            return Err(Error::invalid_argument(format!(
+                "collection {} is sharded",
+                name
+            )));
+        }
+        if shard != 0 && shard_by_id(&desc, shard).is_none() {
+            return Err(Error::NotFound(format!(
+                "shard {} of collection {}",
+                shard, name
+            )));
+        }
         let co = self
             .collections
-            .entry(desc.id)
-            .or_insert_with(Collection::new)
+            .lock()
+            .await
+            .entry((desc.id, shard))
+            .or_insert_with(|| {
+                Collection::new(
+                    desc.clone(),
+                    shard,
+                    self.clock.clone(),
+                    self.retention.clone(),
+                    self.cache.clone(),
+                    self.write_cache.clone(),
+                )
+            })
             .clone();
+        co.update(&desc);
         Ok(co)
     }
+
+    // Drops the state of collections that have been deleted.
+    async fn drop_deleted(&self) {
+        let collections: Vec<_> = self
+            .collections
+            .lock()
+            .await
+            .iter()
+            .map(|(key, co)| (*key, co.name().to_owned()))
+            .collect();
+        for ((id, shard), name) in collections {
+            // Refreshes the cached descriptors too.
+            self.forget(&name);
+            let res = self.describe(&name).await;
+            // A collection with the same name but another id is a new one.
+            let deleted = match res {
+                Ok(desc) => desc.id != id || (shard != 0 && shard_by_id(&desc, shard).is_none()),
+                Err(Error::NotFound(_)) => true,
+                Err(_) => false,
+            };
+            if deleted {
+                self.collections.lock().await.remove(&(id, shard));
+            }
+        }
+    }
+
+    // Closes the logs of shards that have been deleted, and the logs that
+    // this member leads but whose shards or unsharded collections are
+    // assigned to other members, e.g. after a move that fails to cut over,
+    // so that the owners are elected instead.
+    async fn close_stale_logs(&self) {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return,
+        };
+        let replicas: Vec<_> = self.replicas.lock().await.values().cloned().collect();
+        for replica in replicas {
+            let leading = replica.leading_epoch.load(Ordering::Acquire) != 0;
+            if replica.shard == 0 {
+                if leading && self.locate(true).await.is_ok() {
+                    let owner = self.owner.read().unwrap().clone();
+                    if !owner.is_empty() && owner != journal.id() {
+                        let _ = self.close_replica(0).await;
+                    }
+                }
+                continue;
+            }
+            let name = self
+                .collections
+                .lock()
+                .await
+                .iter()
+                .find(|((_, shard), _)| *shard == replica.shard)
+                .map(|(_, co)| co.name().to_owned());
+            let stale = match name {
+                Some(name) => match self.collection(&name, replica.shard).await {
+                    Ok(co) => {
+                        let owner = co.owner();
+                        leading && !owner.is_empty() && owner != journal.id()
+                    }
+                    Err(Error::NotFound(_)) => true,
+                    Err(_) => false,
+                },
+                // The state of the collection has been dropped.
+                None => true,
+            };
+            if stale {
+                // Retries in the next round if it fails.
+                let _ = self.close_replica(replica.shard).await;
+            }
+        }
+    }
+}
+
+// Follows the log of a replica as the role of this member changes. A
+// follower applies the records of the leader to keep the replica warm, and a
+// new leader catches up with the log before it accepts writes.
+async fn follow(db: Weak<Inner>, replica: Arc<Replica>, mut tailer: Tailer) {
+    let log = replica.log.clone();
+    let mut states = log.subscribe();
+    loop {
+        let state = states.borrow().clone();
+        let inner = match db.upgrade() {
+            Some(inner) => inner,
+            None => break,
+        };
+        let leading = replica.leading_epoch.load(Ordering::Acquire);
+        match state {
+            Some(state) if state.role == Role::Leader => {
+                if leading != state.epoch {
+                    if inner
+                        .catch_up(&replica, &mut tailer, state.epoch)
+                        .await
+                        .is_err()
+                    {
+                        // Retries until the epoch changes or it succeeds.
+                        drop(inner);
+                        tokio::time::sleep(RETRY_INTERVAL).await;
+                        continue;
+                    }
+                }
+                drop(inner);
+                if states.changed().await.is_err() {
+                    break;
+                }
+            }
+            _ => {
+                // Stops accepting writes, and follows the records after the
+                // ones appended by this member.
+                if leading != 0 {
+                    replica.leading_epoch.store(0, Ordering::Release);
+                    let sequence = replica.last_sequence.load(Ordering::Relaxed);
+                    tailer = log.tail(sequence + 1);
+                }
+                drop(inner);
+                tokio::select! {
+                    res = states.changed() => {
+                        if res.is_err() {
+                            break;
+                        }
+                    }
+                    res = tailer.next() => match res {
+                        Ok((sequence, record)) => {
+                            if let Some(inner) = db.upgrade() {
+                                if record.barrier == 0 {
+                                    inner.apply(&replica, sequence, record).await;
+                                }
+                            }
+                        }
+                        Err(_) => tokio::time::sleep(RETRY_INTERVAL).await,
+                    },
+                }
+            }
+        }
+    }
+}
+
+fn write_batches(record: &LogRecord) -> Vec<(u64, WriteBatch)> {
+    record
+        .collections
+        .iter()
+        .map(|colog| (colog.id, WriteBatch::from_log(record.ts, colog)))
+        .collect()
+}
+
+fn snapshot_retention(desc: &DatabaseDesc) -> Timestamp {
+    let retention = if desc.snapshot_retention_ms > 0 {
+        Duration::from_millis(desc.snapshot_retention_ms)
+    } else {
+        DEFAULT_SNAPSHOT_RETENTION
+    };
+    retention.as_micros() as Timestamp
 }
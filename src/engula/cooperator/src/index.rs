@@ -0,0 +1,129 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Secondary indexes of collections.
+//!
+//! An index maps the keys of objects to the sets of their ids. Each set is
+//! stored as an entry object in the collection that it indexes, so entries
+//! are logged, replicated and flushed together with the objects they point
+//! to. Ids of entries start with a reserved prefix and are hidden from
+//! clients.
+//!
+//! A key has an entry in each partition of a collection, which holds the
+//! objects in that partition. A write locks the partitions of its objects
+//! only, and they contain the entries it updates.
+
+use engula_apis::*;
+
+use crate::Object;
+
+const ENTRY_PREFIX: &[u8] = b"\xff\xffindex\xff";
+
+/// Returns true if `id` is reserved for index entries.
+pub fn is_entry(id: &[u8]) -> bool {
+    id.starts_with(ENTRY_PREFIX)
+}
+
+/// Returns the id of the entry of `key` in an index of a collection shard,
+/// which holds the objects in `partition`.
+///
+/// The shard is part of the id, since all shards of a collection share the
+/// same bucket in the object engine.
+pub fn entry_id(shard: u64, partition: usize, index: &str, key: &[u8]) -> Vec<u8> {
+    let mut id = ENTRY_PREFIX.to_vec();
+    id.extend_from_slice(&shard.to_be_bytes());
+    id.extend_from_slice(&(partition as u32).to_be_bytes());
+    id.extend_from_slice(&(index.len() as u32).to_be_bytes());
+    id.extend_from_slice(index.as_bytes());
+    id.extend_from_slice(key);
+    id
+}
+
+/// Returns the partition of an entry, or `None` if `id` is not an entry.
+pub fn entry_partition(id: &[u8]) -> Option<usize> {
+    let start = ENTRY_PREFIX.len() + 8;
+    let bytes = id.get(start..start + 4)?.try_into().ok()?;
+    is_entry(id).then(|| u32::from_be_bytes(bytes) as usize)
+}
+
+/// Returns the key of `object` in the index, or `None` if it is not indexed.
+///
+/// An index with a field indexes that field of map objects, and an index
+/// without one indexes objects as a whole.
+pub fn index_key(desc: &IndexDesc, object: &Object) -> Option<Vec<u8>> {
+    let value = match object {
+        Object::Value(v) if desc.field.is_empty() => v,
+        Object::Map(v) if !desc.field.is_empty() => v.get(&desc.field)?.value.as_ref()?,
+        _ => return None,
+    };
+    encode_key(value)
+}
+
+/// Encodes a value as an index key. Only blob, text and integer values are
+/// indexed, and values of different types never share a key.
+pub fn encode_key(value: &Value) -> Option<Vec<u8>> {
+    let (tag, bytes) = match value {
+        Value::BlobValue(v) => (b'b', v.clone()),
+        Value::TextValue(v) => (b't', v.as_bytes().to_vec()),
+        Value::I64Value(v) => (b'i', v.to_be_bytes().to_vec()),
+        _ => return None,
+    };
+    let mut key = vec![tag];
+    key.extend(bytes);
+    Some(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Map;
+
+    #[test]
+    fn index_keys() {
+        let blob = IndexDesc {
+            name: "blob".to_owned(),
+            ..Default::default()
+        };
+        let email = IndexDesc {
+            name: "email".to_owned(),
+            field: b"email".to_vec(),
+        };
+
+        let object = Object::Value(Value::TextValue("a@b.c".to_owned()));
+        assert_eq!(index_key(&blob, &object), Some(b"ta@b.c".to_vec()));
+        assert_eq!(index_key(&email, &object), None);
+
+        let mut map = Map::default();
+        map.insert(
+            b"email".to_vec(),
+            Value::TextValue("a@b.c".to_owned()).into(),
+        );
+        let object = Object::Map(map);
+        assert_eq!(index_key(&blob, &object), None);
+        assert_eq!(index_key(&email, &object), Some(b"ta@b.c".to_vec()));
+
+        let object = Object::Value(Value::BlobValue(b"a@b.c".to_vec()));
+        assert_ne!(index_key(&blob, &object), Some(b"ta@b.c".to_vec()));
+        let object = Object::Value(Value::F64Value(1.0));
+        assert_eq!(index_key(&blob, &object), None);
+
+        let id = entry_id(1, 3, "email", b"ta@b.c");
+        assert!(is_entry(&id));
+        assert_eq!(entry_partition(&id), Some(3));
+        assert_ne!(id, entry_id(2, 3, "email", b"ta@b.c"));
+        assert_ne!(id, entry_id(1, 4, "email", b"ta@b.c"));
+        assert!(!is_entry(b"a@b.c"));
+        assert_eq!(entry_partition(b"a@b.c"), None);
+    }
+}
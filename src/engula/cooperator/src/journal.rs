@@ -0,0 +1,592 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::StreamExt;
+use prost::Message;
+use stream_engine_client::{Engine, EpochState, Sequence, Stream, StreamReader, Tenant};
+use tokio::sync::{watch, Mutex};
+
+use crate::{apis::LogRecord, Error, Result};
+
+// How long a tailer waits for the segment of a new epoch to be created.
+const TAIL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Write-ahead logs of databases, which are kept in streams of a stream
+/// engine tenant.
+///
+/// Each shard of a collection has its own log, and the unsharded collections
+/// of a database share one. Cooperators that join the same group share the
+/// logs. For each log, the stream engine elects one of the members that have
+/// opened it as the leader, which is the only one that appends records, and
+/// the others follow the records it appends.
+#[derive(Clone)]
+pub struct Journal {
+    id: String,
+    tenant: Tenant,
+    // A stream accepts only one subscriber of its states, so each log is
+    // opened once and shared.
+    logs: Arc<Mutex<BTreeMap<String, Log>>>,
+}
+
+impl Journal {
+    /// Creates a journal in which this cooperator is the member `id`.
+    pub fn new(id: String, tenant: Tenant) -> Self {
+        Self {
+            id,
+            tenant,
+            logs: Arc::new(Mutex::new(BTreeMap::new())),
+        }
+    }
+
+    /// Joins `group` as the member `id`, whose logs are kept in the stream
+    /// engine at `url`.
+    pub async fn connect(group: &str, id: String, url: impl Into<String>) -> Result<Self> {
+        let engine = Engine::new(id.clone(), url).await.map_err(journal_error)?;
+        let tenant = match engine.create_tenant(group).await {
+            Ok(tenant) => tenant,
+            Err(stream_engine_client::Error::AlreadyExists(_)) => engine.tenant(group),
+            Err(err) => return Err(journal_error(err)),
+        };
+        Ok(Self::new(id, tenant))
+    }
+
+    /// Returns the id of this member.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Opens the log of a shard of a database, or the log of its unsharded
+    /// collections if `shard` is zero.
+    pub async fn open_log(&self, dbid: u64, shard: u64) -> Result<Log> {
+        let name = log_name(dbid, shard);
+        let mut logs = self.logs.lock().await;
+        if let Some(log) = logs.get(&name) {
+            return Ok(log.clone());
+        }
+        let stream = match self.tenant.stream(&name).await {
+            Ok(stream) => stream,
+            Err(stream_engine_client::Error::NotFound(_)) => {
+                match self.tenant.create_stream(&name).await {
+                    Ok(stream) => stream,
+                    Err(stream_engine_client::Error::AlreadyExists(_)) => {
+                        self.tenant.stream(&name).await.map_err(journal_error)?
+                    }
+                    Err(err) => return Err(journal_error(err)),
+                }
+            }
+            Err(err) => return Err(journal_error(err)),
+        };
+        let mut states = stream.subscribe_state().await.map_err(journal_error)?;
+        let (sender, receiver) = watch::channel(None);
+        tokio::spawn(async move {
+            while let Some(state) = states.next().await {
+                if sender.send(Some(state)).is_err() {
+                    break;
+                }
+            }
+        });
+        let log = Log {
+            stream,
+            state: receiver,
+        };
+        logs.insert(name, log.clone());
+        Ok(log)
+    }
+
+    /// Closes the log of a shard of a database, so that one of the other
+    /// members that have opened it is elected as the leader instead.
+    ///
+    /// The epoch states of the log end, and it must not be appended to
+    /// anymore. It can be opened again later.
+    pub async fn close_log(&self, dbid: u64, shard: u64) -> Result<()> {
+        let name = log_name(dbid, shard);
+        let mut logs = self.logs.lock().await;
+        if let Some(log) = logs.get(&name) {
+            log.stream.close().await.map_err(journal_error)?;
+            logs.remove(&name);
+        }
+        Ok(())
+    }
+}
+
+fn log_name(dbid: u64, shard: u64) -> String {
+    if shard == 0 {
+        format!("database-{}", dbid)
+    } else {
+        format!("database-{}-shard-{}", dbid, shard)
+    }
+}
+
+/// The log of a shard, or of the unsharded collections of a database.
+#[derive(Clone)]
+pub struct Log {
+    stream: Stream,
+    // The latest epoch state of the stream, or none before it is known.
+    state: watch::Receiver<Option<EpochState>>,
+}
+
+impl Log {
+    /// Appends a record and returns after it is acknowledged.
+    ///
+    /// Appending fails if this member doesn't lead the log.
+    pub async fn append(&self, record: &LogRecord) -> Result<u64> {
+        let event = record.encode_to_vec().into_boxed_slice();
+        self.stream.append(event).await.map_err(journal_error)
+    }
+
+    /// Appends a barrier, and returns its id and sequence.
+    ///
+    /// A reader can't tell whether it has reached the end of a stream, so a
+    /// leader reads until its barrier to catch up with the log.
+    pub async fn append_barrier(&self) -> Result<(u64, u64)> {
+        let barrier = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        let record = LogRecord {
+            barrier,
+            ..Default::default()
+        };
+        let sequence = self.append(&record).await?;
+        Ok((barrier, sequence))
+    }
+
+    /// Returns the latest epoch state of the log.
+    pub fn state(&self) -> Option<EpochState> {
+        self.state.borrow().clone()
+    }
+
+    /// Returns a receiver that is notified when the epoch state changes.
+    pub fn subscribe(&self) -> watch::Receiver<Option<EpochState>> {
+        self.state.clone()
+    }
+
+    /// Returns a tailer that reads records from `sequence` on.
+    pub fn tail(&self, sequence: u64) -> Tailer {
+        let sequence = Sequence::from(sequence);
+        Tailer {
+            stream: self.stream.clone(),
+            state: self.state.clone(),
+            epoch: sequence.epoch.max(1),
+            index: sequence.index,
+            reader: None,
+        }
+    }
+}
+
+/// Reads records of a log in order, and waits for new ones at the end.
+pub struct Tailer {
+    stream: Stream,
+    state: watch::Receiver<Option<EpochState>>,
+    epoch: u32,
+    index: u32,
+    reader: Option<StreamReader>,
+}
+
+impl Tailer {
+    /// Returns the next record and its sequence.
+    ///
+    /// Sequences of records that follow holes in the log may be smaller than
+    /// the actual ones.
+    pub async fn next(&mut self) -> Result<(u64, LogRecord)> {
+        loop {
+            let reader = match self.reader.as_mut() {
+                Some(reader) => reader,
+                None => {
+                    let mut reader = self.stream.new_reader().await.map_err(journal_error)?;
+                    let sequence = Sequence::new(self.epoch, self.index);
+                    match reader.seek(sequence.into()).await {
+                        Ok(()) => self.reader.insert(reader),
+                        Err(stream_engine_client::Error::NotFound(_)) => {
+                            let current = self.state.borrow().as_ref().map_or(0, |s| s.epoch);
+                            if (self.epoch as u64) < current {
+                                // The segment has been truncated.
+                                self.next_epoch();
+                            } else {
+                                // The segment has not been created yet.
+                                tokio::time::sleep(TAIL_INTERVAL).await;
+                            }
+                            continue;
+                        }
+                        Err(err) => return Err(journal_error(err)),
+                    }
+                }
+            };
+            let event = match reader.wait_next().await {
+                Ok(event) => event,
+                // Each epoch writes its own segment, and a reader stops at the
+                // end of a segment.
+                Err(stream_engine_client::Error::NotFound(_)) => {
+                    self.next_epoch();
+                    continue;
+                }
+                Err(err) => {
+                    self.reader = None;
+                    return Err(journal_error(err));
+                }
+            };
+            let sequence = Sequence::new(self.epoch, self.index);
+            self.index += 1;
+            let record = LogRecord::decode(&*event).map_err(|e| Error::corrupted(e.to_string()))?;
+            return Ok((sequence.into(), record));
+        }
+    }
+
+    fn next_epoch(&mut self) {
+        self.epoch += 1;
+        self.index = 0;
+        self.reader = None;
+    }
+}
+
+fn journal_error(err: stream_engine_client::Error) -> Error {
+    match err {
+        stream_engine_client::Error::NotFound(s) => Error::NotFound(s),
+        stream_engine_client::Error::AlreadyExists(s) => Error::AlreadyExists(s),
+        stream_engine_client::Error::InvalidArgument(s) => Error::InvalidArgument(s),
+        stream_engine_client::Error::Io(err) => Error::Io(err),
+        err => Error::internal(err.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engula_apis::*;
+    use engula_supervisor::{
+        admin_request, admin_response, shard_move, AdminRequest, ListShardMovesRequest,
+        MoveShardRequest, Supervisor,
+    };
+    use stream_engine_master::build_master;
+    use stream_engine_store::build_store;
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use super::*;
+    use crate::{Cooperator, Server};
+
+    type TestResult = std::result::Result<(), Box<dyn std::error::Error>>;
+
+    async fn create_collection(sp: &Supervisor, dbname: &str, coname: &str) -> TestResult {
+        let desc = DatabaseDesc {
+            name: dbname.to_owned(),
+            ..Default::default()
+        };
+        sp.create_database(desc).await?;
+        let desc = CollectionDesc {
+            name: coname.to_owned(),
+            ..Default::default()
+        };
+        sp.create_collection(dbname.to_owned(), desc).await?;
+        Ok(())
+    }
+
+    fn txn_request(dbname: &str, coname: &str, func: Function, args: Vec<Value>) -> TxnRequest {
+        let expr = Expr {
+            from: Some(expr::From::Id(b"id".to_vec())),
+            call: Some(CallExpr {
+                func: func as i32,
+                args: args.into_iter().map(Into::into).collect(),
+            }),
+            ..Default::default()
+        };
+        let coreq = CollectionTxnRequest {
+            name: coname.to_owned(),
+            exprs: vec![expr],
+            ..Default::default()
+        };
+        let dbreq = DatabaseTxnRequest {
+            name: dbname.to_owned(),
+            requests: vec![coreq],
+            ..Default::default()
+        };
+        TxnRequest {
+            requests: vec![dbreq],
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recover_from_journal() -> TestResult {
+        let mut replicas = Vec::new();
+        for _ in 0..3 {
+            replicas.push(build_store().await?);
+        }
+        let replicas: Vec<_> = replicas.iter().map(String::as_str).collect();
+        let url = build_master(&replicas).await?;
+        let journal = Journal::connect("engula", "1".to_owned(), url).await?;
+
+        let sp = Supervisor::new();
+        create_collection(&sp, "db", "co").await?;
+        let co = Cooperator::with_journal(sp.clone(), journal.clone());
+        let req = txn_request("db", "co", Function::Store, vec![Value::I64Value(1)]);
+        txn_as_leader(&co, req).await?;
+        let req = txn_request("db", "co", Function::Add, vec![Value::I64Value(2)]);
+        co.txn(req).await?;
+        drop(co);
+
+        // A new cooperator recovers the objects from the journal before it
+        // accepts writes as the leader.
+        let co = Cooperator::with_journal(sp, journal);
+        let req = txn_request("db", "co", Function::Add, vec![Value::I64Value(0)]);
+        txn_as_leader(&co, req).await?;
+        let req = txn_request("db", "co", Function::Load, vec![]);
+        let res = co.txn(req).await?;
+        assert_eq!(loaded_value(&res), Some(Value::I64Value(3)));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recover_changed_members() -> TestResult {
+        let mut replicas = Vec::new();
+        for _ in 0..3 {
+            replicas.push(build_store().await?);
+        }
+        let replicas: Vec<_> = replicas.iter().map(String::as_str).collect();
+        let url = build_master(&replicas).await?;
+        let journal = Journal::connect("engula", "1".to_owned(), url).await?;
+
+        let sp = Supervisor::new();
+        create_collection(&sp, "db", "co").await?;
+        let co = Cooperator::with_journal(sp.clone(), journal.clone());
+        let map = MapValue {
+            keys: vec![Value::BlobValue(b"a".to_vec()).into()],
+            values: vec![Value::I64Value(1).into()],
+        };
+        let req = txn_request("db", "co", Function::Store, vec![map.into()]);
+        txn_as_leader(&co, req).await?;
+        // Only the changed field of the map is logged.
+        let mut req = txn_request("db", "co", Function::Store, vec![Value::I64Value(2)]);
+        let object = &mut req.requests[0].requests[0].exprs[0];
+        object.subexprs.push(Expr {
+            from: Some(expr::From::Index(Value::BlobValue(b"b".to_vec()).into())),
+            call: object.call.take(),
+            ..Default::default()
+        });
+        co.txn(req).await?;
+        drop(co);
+
+        let co = Cooperator::with_journal(sp, journal);
+        let req = txn_request("db", "co", Function::Load, vec![]);
+        let res = txn_as_leader(&co, req).await?;
+        let map = MapValue {
+            keys: vec![
+                Value::BlobValue(b"a".to_vec()).into(),
+                Value::BlobValue(b"b".to_vec()).into(),
+            ],
+            values: vec![Value::I64Value(1).into(), Value::I64Value(2).into()],
+        };
+        assert_eq!(loaded_value(&res), Some(map.into()));
+        Ok(())
+    }
+
+    // Retries a request until the cooperator is elected as the leader of the
+    // log.
+    async fn txn_as_leader(co: &Cooperator, req: TxnRequest) -> Result<TxnResponse> {
+        loop {
+            match co.txn(req.clone()).await {
+                Err(Error::NotLeader(_)) => tokio::time::sleep(Duration::from_millis(100)).await,
+                res => return res,
+            }
+        }
+    }
+
+    fn loaded_value(res: &TxnResponse) -> Option<Value> {
+        res.responses[0].responses[0].results[0].values[0]
+            .value
+            .clone()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn serve_shards_on_their_owners() -> TestResult {
+        let mut replicas = Vec::new();
+        for _ in 0..3 {
+            replicas.push(build_store().await?);
+        }
+        let replicas: Vec<_> = replicas.iter().map(String::as_str).collect();
+        let url = build_master(&replicas).await?;
+
+        // The shards are assigned to the members in turn.
+        let sp = Supervisor::new();
+        let members = ["1", "2"];
+        for id in members {
+            sp.heartbeat(id.to_owned()).await?;
+        }
+        create_collection(&sp, "db", "plain").await?;
+        let desc = CollectionDesc {
+            name: "co".to_owned(),
+            sharding: Some(ShardingDesc {
+                kind: Some(sharding_desc::Kind::Hash(HashSharding { num_shards: 2 })),
+            }),
+            ..Default::default()
+        };
+        let desc = sp.create_collection("db".to_owned(), desc).await?;
+        let mut cos = Vec::new();
+        for id in members {
+            let journal = Journal::connect("engula", id.to_owned(), url.clone()).await?;
+            cos.push(Cooperator::with_journal(sp.clone(), journal));
+        }
+
+        // Each member leads the log of its own shard, and rejects the other
+        // shard.
+        for (i, shard) in desc.shards.iter().enumerate() {
+            assert_eq!(shard.cooperator, members[i]);
+            let mut req = txn_request("db", "co", Function::Store, vec![Value::I64Value(1)]);
+            req.requests[0].requests[0].shard = shard.id;
+            txn_as_leader(&cos[i], req.clone()).await?;
+            let err = cos[1 - i]
+                .txn(req)
+                .await
+                .err()
+                .ok_or("unowned shard is served")?;
+            assert!(matches!(err, Error::NotLeader(_)), "{}", err);
+        }
+        Ok(())
+    }
+
+    // Serves a cooperator whose member id is its address, and returns the
+    // address and a client of it.
+    async fn serve_member(sp: &Supervisor, master: &str) -> Result<(String, Cooperator)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let journal = Journal::connect("engula", url.clone(), master.to_owned()).await?;
+        let server = Server::with_journal(sp.clone(), journal);
+        let shard_host = server.clone().into_shard_host_service();
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(server.into_service())
+                .add_service(shard_host)
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+        sp.heartbeat(url.clone()).await?;
+        let co = Cooperator::connect(url.clone()).await?;
+        Ok((url, co))
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn move_hands_over_log() -> TestResult {
+        let mut replicas = Vec::new();
+        for _ in 0..3 {
+            replicas.push(build_store().await?);
+        }
+        let replicas: Vec<_> = replicas.iter().map(String::as_str).collect();
+        let url = build_master(&replicas).await?;
+
+        // The shard is assigned to the source, which is the only member.
+        let sp = Supervisor::new();
+        let (_, source) = serve_member(&sp, &url).await?;
+        create_collection(&sp, "db", "plain").await?;
+        let desc = CollectionDesc {
+            name: "co".to_owned(),
+            sharding: Some(ShardingDesc {
+                kind: Some(sharding_desc::Kind::Hash(HashSharding { num_shards: 1 })),
+            }),
+            ..Default::default()
+        };
+        let desc = sp.create_collection("db".to_owned(), desc).await?;
+        let shard = desc.shards[0].id;
+        let shard_req = |func, args| {
+            let mut req = txn_request("db", "co", func, args);
+            req.requests[0].requests[0].shard = shard;
+            req
+        };
+        let req = shard_req(Function::Store, vec![Value::I64Value(1)]);
+        txn_as_leader(&source, req).await?;
+
+        let (target_url, target) = serve_member(&sp, &url).await?;
+        let req = AdminRequest {
+            request: Some(admin_request::Request::MoveShard(MoveShardRequest {
+                dbname: "db".to_owned(),
+                coname: "co".to_owned(),
+                shard,
+                target: target_url,
+            })),
+        };
+        sp.admin(req).await?;
+        loop {
+            let req = AdminRequest {
+                request: Some(admin_request::Request::ListShardMoves(
+                    ListShardMovesRequest {},
+                )),
+            };
+            let moves = match sp.admin(req).await?.response {
+                Some(admin_response::Response::ListShardMoves(res)) => res.shard_moves,
+                _ => return Err("unexpected admin response".into()),
+            };
+            let m = moves.first().ok_or("missing shard move")?;
+            match m.state() {
+                shard_move::State::Done => break,
+                shard_move::State::Failed => return Err(m.error.clone().into()),
+                _ => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+
+        // The target is elected once the source closes the log, and it
+        // continues from the writes of the source.
+        let req = shard_req(Function::Add, vec![Value::I64Value(2)]);
+        txn_as_leader(&target, req).await?;
+        let res = target.txn(shard_req(Function::Load, vec![])).await?;
+        assert_eq!(loaded_value(&res), Some(Value::I64Value(3)));
+        let req = shard_req(Function::Add, vec![Value::I64Value(2)]);
+        let err = source.txn(req).await.err().ok_or("moved shard is served")?;
+        assert!(matches!(err, Error::NotLeader(_)), "{}", err);
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn follower_rejects_requests() -> TestResult {
+        let mut replicas = Vec::new();
+        for _ in 0..3 {
+            replicas.push(build_store().await?);
+        }
+        let replicas: Vec<_> = replicas.iter().map(String::as_str).collect();
+        let url = build_master(&replicas).await?;
+
+        let sp = Supervisor::new();
+        create_collection(&sp, "db", "co").await?;
+        let journal = Journal::connect("engula", "1".to_owned(), url.clone()).await?;
+        let leader = Cooperator::with_journal(sp.clone(), journal);
+        // The only member becomes the leader once the log is assigned to it.
+        let req = txn_request("db", "co", Function::Store, vec![Value::I64Value(1)]);
+        txn_as_leader(&leader, req.clone()).await?;
+
+        let journal = Journal::connect("engula", "2".to_owned(), url).await?;
+        let follower = Cooperator::with_journal(sp, journal);
+        let err = follower
+            .txn(req)
+            .await
+            .err()
+            .ok_or("follower accepts writes")?;
+        assert!(matches!(err, Error::NotLeader(_)), "{}", err);
+
+        // Reads go to the leader too, since the follower may lag behind.
+        let req = txn_request("db", "co", Function::Load, vec![]);
+        let err = follower
+            .txn(req.clone())
+            .await
+            .err()
+            .ok_or("follower accepts reads")?;
+        assert!(matches!(err, Error::NotLeader(_)), "{}", err);
+        assert_eq!(
+            loaded_value(&leader.txn(req).await?),
+            Some(Value::I64Value(1))
+        );
+        Ok(())
+    }
+}
@@ -14,14 +14,42 @@
 
 mod apis;
 mod args;
+mod clock;
 mod collection;
 mod cooperator;
 mod database;
+mod index;
+mod journal;
+mod map;
+mod numeric;
+mod object;
+mod path;
+mod read_cache;
 mod server;
+mod sorted_set;
 mod universe;
 mod write_cache;
 
 use engula_common::{Error, Result};
 
-use self::{args::Args, collection::Collection, database::Database, universe::Universe};
-pub use self::{cooperator::Cooperator, server::Server};
+use self::{
+    args::Args,
+    clock::{Clock, Timestamp},
+    collection::{Changes, Collection},
+    database::Database,
+    journal::Log,
+    map::Map,
+    object::Object,
+    read_cache::ReadCache,
+    sorted_set::SortedSet,
+    universe::Universe,
+    write_cache::{WriteBatch, WriteCache, WriteCacheOptions},
+};
+pub use self::{
+    collection::{is_read_only, source_ids},
+    cooperator::Cooperator,
+    journal::Journal,
+    read_cache::{ReadCacheOptions, ReadCacheStats},
+    server::{Server, WatchStream},
+    write_cache::{open_object_engine, ObjectEngine},
+};
@@ -0,0 +1,115 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::BTreeMap, ops::Bound};
+
+use engula_apis::*;
+use prost::Message;
+
+use crate::{Error, Result};
+
+/// A map with fields ordered by key.
+#[derive(Clone, Default)]
+pub struct Map {
+    fields: BTreeMap<Vec<u8>, ValueUnion>,
+    // The approximate encoded size of the fields, which is kept as they
+    // change.
+    size: usize,
+}
+
+impl Map {
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Returns the approximate encoded size of the map.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&ValueUnion> {
+        self.fields.get(key)
+    }
+
+    pub fn insert(&mut self, key: Vec<u8>, value: ValueUnion) {
+        let key_len = key.len();
+        self.size += key_len + value.encoded_len();
+        if let Some(old) = self.fields.insert(key, value) {
+            self.size -= key_len + old.encoded_len();
+        }
+    }
+
+    pub fn remove(&mut self, key: &[u8]) -> bool {
+        match self.fields.remove(key) {
+            Some(old) => {
+                self.size -= key.len() + old.encoded_len();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns fields with keys in [start, end). An empty end means that the
+    /// range is unbounded.
+    pub fn range(&self, start: Vec<u8>, end: Vec<u8>) -> MapValue {
+        let end = if end.is_empty() {
+            Bound::Unbounded
+        } else if end <= start {
+            return MapValue::default();
+        } else {
+            Bound::Excluded(end)
+        };
+        collect(self.fields.range((Bound::Included(start), end)))
+    }
+
+    /// Returns fields with keys that start with `prefix`.
+    pub fn prefix(&self, prefix: &[u8]) -> MapValue {
+        let fields = self
+            .fields
+            .range(prefix.to_owned()..)
+            .take_while(|(key, _)| key.starts_with(prefix));
+        collect(fields)
+    }
+
+    pub fn to_value(&self) -> MapValue {
+        collect(self.fields.iter())
+    }
+}
+
+impl TryFrom<MapValue> for Map {
+    type Error = Error;
+
+    fn try_from(v: MapValue) -> Result<Self> {
+        if v.keys.len() != v.values.len() {
+            return Err(Error::invalid_argument("unmatched keys and values"));
+        }
+        let mut map = Map::default();
+        for (key, value) in v.keys.into_iter().zip(v.values) {
+            match key.value {
+                Some(Value::BlobValue(key)) => map.insert(key, value),
+                _ => return Err(Error::invalid_argument("require blob key")),
+            }
+        }
+        Ok(map)
+    }
+}
+
+fn collect<'a>(fields: impl Iterator<Item = (&'a Vec<u8>, &'a ValueUnion)>) -> MapValue {
+    let mut value = MapValue::default();
+    for (key, field) in fields {
+        value.keys.push(Value::BlobValue(key.clone()).into());
+        value.values.push(field.clone());
+    }
+    value
+}
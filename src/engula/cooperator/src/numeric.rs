@@ -0,0 +1,75 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use engula_apis::*;
+
+use crate::{Error, Result};
+
+/// Applies a numeric function to `lhs` and `rhs`.
+///
+/// If both sides are i64, the result is an i64 and fails on overflow.
+/// Otherwise, both sides are converted to f64 and the result follows IEEE 754,
+/// which overflows to infinity, except that a NaN result is rejected.
+pub fn apply(func: Function, lhs: &Value, rhs: &Value) -> Result<Value> {
+    if let (Value::I64Value(a), Value::I64Value(b)) = (lhs, rhs) {
+        let v = match func {
+            Function::Add => a.checked_add(*b),
+            Function::Sub => a.checked_sub(*b),
+            Function::Mul => a.checked_mul(*b),
+            Function::Min => Some(*a.min(b)),
+            Function::Max => Some(*a.max(b)),
+            _ => return Err(Error::invalid_argument("invalid numeric function")),
+        };
+        return v
+            .map(Value::I64Value)
+            .ok_or_else(|| Error::invalid_argument("integer overflow"));
+    }
+    let a = as_f64(lhs)?;
+    let b = as_f64(rhs)?;
+    let v = match func {
+        Function::Add => a + b,
+        Function::Sub => a - b,
+        Function::Mul => a * b,
+        Function::Min => a.min(b),
+        Function::Max => a.max(b),
+        _ => return Err(Error::invalid_argument("invalid numeric function")),
+    };
+    if v.is_nan() {
+        Err(Error::invalid_argument("result is NaN"))
+    } else {
+        Ok(Value::F64Value(v))
+    }
+}
+
+pub fn as_f64(v: &Value) -> Result<f64> {
+    match v {
+        Value::I64Value(v) => Ok(*v as f64),
+        Value::F64Value(v) => Ok(*v),
+        _ => Err(Error::invalid_argument("require numeric object")),
+    }
+}
+
+/// Applies a numeric function to an object that may be missing.
+///
+/// A missing object starts from zero for Add and Sub, and takes the operand
+/// for the others.
+pub fn update(func: Function, value: Option<&Value>, operand: Value) -> Result<Value> {
+    match value {
+        Some(value) => apply(func, value, &operand),
+        None if matches!(func, Function::Add | Function::Sub) => {
+            apply(func, &Value::I64Value(0), &operand)
+        }
+        None => Ok(operand),
+    }
+}
@@ -0,0 +1,102 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use engula_apis::*;
+
+use crate::{apis::MemberLog, Error, Map, Result, SortedSet};
+
+/// The in-memory representation of an object.
+///
+/// Most objects are kept as their wire values, while types that need an
+/// ordered representation for efficient access are kept in their own
+/// structures.
+#[derive(Clone)]
+pub enum Object {
+    Value(Value),
+    Map(Map),
+    SortedSet(SortedSet),
+}
+
+impl Object {
+    pub fn to_value(&self) -> Value {
+        match self {
+            Object::Value(v) => v.clone(),
+            Object::Map(v) => v.to_value().into(),
+            Object::SortedSet(v) => v.to_value().into(),
+        }
+    }
+
+    /// Returns a member of a map, or the score of a member of a sorted set.
+    pub fn member(&self, key: &[u8]) -> Option<Value> {
+        match self {
+            Object::Value(_) => None,
+            Object::Map(v) => v.get(key).and_then(|v| v.value.clone()),
+            Object::SortedSet(v) => v.score(key).map(Value::F64Value),
+        }
+    }
+
+    /// Replaces a member of a map, or the score of a member of a sorted set.
+    /// The member is removed if `value` is none.
+    pub fn set_member(&mut self, key: Vec<u8>, value: Option<Value>) -> Result<()> {
+        match (self, value) {
+            (Object::Map(v), Some(value)) => v.insert(key, value.into()),
+            (Object::Map(v), None) => {
+                v.remove(&key);
+            }
+            (Object::SortedSet(v), Some(Value::F64Value(score))) => v.insert(key, score)?,
+            (Object::SortedSet(_), Some(_)) => {
+                return Err(Error::invalid_argument("require f64 score"));
+            }
+            (Object::SortedSet(v), None) => {
+                v.remove(&key);
+            }
+            (Object::Value(_), _) => {
+                return Err(Error::invalid_argument("require map or sorted set object"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the members of a log that changes only them.
+    pub fn apply_members(&mut self, members: Vec<MemberLog>) -> Result<()> {
+        for member in members {
+            self.set_member(member.key, member.value.and_then(|v| v.value))?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<Value> for Object {
+    type Error = Error;
+
+    fn try_from(v: Value) -> Result<Self> {
+        match v {
+            Value::MapValue(v) => Ok(Object::Map(v.try_into()?)),
+            Value::SortedSetValue(v) => Ok(Object::SortedSet(v.try_into()?)),
+            v => Ok(Object::Value(v)),
+        }
+    }
+}
+
+impl From<Map> for Object {
+    fn from(v: Map) -> Self {
+        Object::Map(v)
+    }
+}
+
+impl From<SortedSet> for Object {
+    fn from(v: SortedSet) -> Self {
+        Object::SortedSet(v)
+    }
+}
@@ -0,0 +1,316 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evaluation of expressions on members of objects.
+//!
+//! Members are addressed by a path of indexes, which is expressed as nested
+//! expressions with `expr::From::Index`. Blob indexes select map fields and
+//! i64 indexes select list elements, where negative indexes count from the
+//! end.
+
+use std::cmp::Ordering;
+
+use engula_apis::*;
+
+use crate::{numeric, Args, Error, Result};
+
+/// Applies `exprs` to `value` and its members, creating missing containers
+/// along the way. Returns whether `value` is mutated.
+pub fn handle_exprs(
+    value: &mut Option<Value>,
+    exprs: Vec<Expr>,
+    result: &mut ExprResult,
+) -> Result<bool> {
+    let mut mutated = false;
+    for expr in exprs {
+        if let Some(expr::From::Index(index)) = expr.from {
+            let index = index
+                .value
+                .ok_or_else(|| Error::invalid_argument("missing index"))?;
+            let mut member = match value.as_ref() {
+                Some(value) => member(value, &index)?,
+                None => None,
+            };
+            let changed = if let Some(call) = expr.call {
+                handle_call_expr(&mut member, call, result)?
+            } else {
+                handle_exprs(&mut member, expr.subexprs, result)?
+            };
+            if changed {
+                if let Some(container) = value.as_mut() {
+                    set_member(container, index, member)?;
+                } else if let Some(member) = member {
+                    // Creates the missing container as a map.
+                    if !matches!(index, Value::BlobValue(_)) {
+                        return Err(Error::invalid_argument("require blob index"));
+                    }
+                    let map = MapValue {
+                        keys: vec![index.into()],
+                        values: vec![member.into()],
+                    };
+                    *value = Some(map.into());
+                }
+                mutated = true;
+            }
+        } else {
+            let call = expr
+                .call
+                .ok_or_else(|| Error::invalid_argument("missing call expr"))?;
+            mutated |= handle_call_expr(value, call, result)?;
+        }
+    }
+    Ok(mutated)
+}
+
+fn handle_call_expr(
+    value: &mut Option<Value>,
+    call: CallExpr,
+    result: &mut ExprResult,
+) -> Result<bool> {
+    let func =
+        Function::from_i32(call.func).ok_or_else(|| Error::invalid_argument("invalid function"))?;
+    handle_call(value, func, Args::new(call.args), result)
+}
+
+/// Applies a function to a member value. Returns whether `value` is mutated.
+pub fn handle_call(
+    value: &mut Option<Value>,
+    func: Function,
+    mut args: Args,
+    result: &mut ExprResult,
+) -> Result<bool> {
+    match func {
+        Function::Nop => return Ok(false),
+        // Conditions are checked before any expression is applied.
+        _ if is_condition(func) => return Ok(false),
+        Function::Load => {
+            result.values.push(value.clone().into());
+            return Ok(false);
+        }
+        Function::Len => {
+            let len = match value {
+                Some(Value::BlobValue(v)) => v.len(),
+                Some(Value::TextValue(v)) => v.len(),
+                Some(Value::MapValue(v)) => v.keys.len(),
+                Some(Value::ListValue(v)) => v.values.len(),
+                Some(Value::SetValue(v)) => v.values.len(),
+                Some(_) => return Err(Error::invalid_argument("require container object")),
+                None => 0,
+            };
+            result.values.push(Value::I64Value(len as i64).into());
+            return Ok(false);
+        }
+        Function::Store => {
+            *value = Some(args.take()?);
+        }
+        Function::Reset => {
+            *value = None;
+        }
+        Function::Add | Function::Sub | Function::Mul | Function::Min | Function::Max => {
+            let operand = args.take_numeric()?;
+            *value = Some(numeric::update(func, value.as_ref(), operand)?);
+        }
+        Function::Append => match value {
+            Some(Value::BlobValue(v)) => v.append(&mut args.take_blob()?),
+            Some(Value::TextValue(v)) => v.push_str(&args.take_text()?),
+            Some(Value::ListValue(v)) => v.values.append(&mut args.take_list()?.values),
+            Some(_) => return Err(Error::invalid_argument("require sequence object")),
+            None => *value = Some(args.take_sequence()?),
+        },
+        Function::PushBack | Function::PushFront => {
+            let operand = args.take()?;
+            match value {
+                Some(Value::ListValue(v)) => {
+                    if func == Function::PushBack {
+                        v.values.push(operand.into());
+                    } else {
+                        v.values.insert(0, operand.into());
+                    }
+                }
+                Some(_) => return Err(Error::invalid_argument("require sequence object")),
+                None => {
+                    let list = ListValue {
+                        values: vec![operand.into()],
+                    };
+                    *value = Some(list.into());
+                }
+            }
+        }
+        _ => return Err(Error::invalid_argument("unsupported member function")),
+    }
+    Ok(true)
+}
+
+/// Checks conditions in `exprs` against `value` and its members.
+pub fn check_exprs(value: Option<&Value>, exprs: &[Expr]) -> Result<()> {
+    for expr in exprs {
+        if let Some(expr::From::Index(index)) = &expr.from {
+            let index = index
+                .value
+                .as_ref()
+                .ok_or_else(|| Error::invalid_argument("missing index"))?;
+            let member = match value {
+                Some(value) => member(value, index)?,
+                None => None,
+            };
+            if let Some(call) = &expr.call {
+                check_call(member.as_ref(), call)?;
+            }
+            check_exprs(member.as_ref(), &expr.subexprs)?;
+        } else if let Some(call) = &expr.call {
+            check_call(value, call)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn check_call(value: Option<&Value>, call: &CallExpr) -> Result<()> {
+    let func =
+        Function::from_i32(call.func).ok_or_else(|| Error::invalid_argument("invalid function"))?;
+    let ok = match func {
+        Function::IfExists => value.is_some(),
+        Function::IfNotExists => value.is_none(),
+        Function::IfEq
+        | Function::IfNe
+        | Function::IfLt
+        | Function::IfLe
+        | Function::IfGt
+        | Function::IfGe => {
+            let operand = Args::new(call.args.clone()).take()?;
+            match (func, value) {
+                (Function::IfEq, Some(value)) => value == &operand,
+                (Function::IfNe, value) => value != Some(&operand),
+                (_, Some(value)) => {
+                    let ord = compare(value, &operand)?;
+                    match func {
+                        Function::IfLt => ord == Ordering::Less,
+                        Function::IfLe => ord != Ordering::Greater,
+                        Function::IfGt => ord == Ordering::Greater,
+                        _ => ord != Ordering::Less,
+                    }
+                }
+                (_, None) => false,
+            }
+        }
+        _ => return Ok(()),
+    };
+    if ok {
+        Ok(())
+    } else {
+        Err(Error::condition_failed(format!(
+            "condition {:?} failed",
+            func
+        )))
+    }
+}
+
+pub fn has_conditions(expr: &Expr) -> bool {
+    let is_condition = expr
+        .call
+        .as_ref()
+        .and_then(|call| Function::from_i32(call.func))
+        .map_or(false, is_condition);
+    is_condition || expr.subexprs.iter().any(has_conditions)
+}
+
+pub fn is_condition(func: Function) -> bool {
+    matches!(
+        func,
+        Function::IfExists
+            | Function::IfNotExists
+            | Function::IfEq
+            | Function::IfNe
+            | Function::IfLt
+            | Function::IfLe
+            | Function::IfGt
+            | Function::IfGe
+    )
+}
+
+/// Returns the member of `value` at `index`.
+pub fn member(value: &Value, index: &Value) -> Result<Option<Value>> {
+    match (value, index) {
+        (Value::MapValue(v), _) => {
+            let pos = v.keys.iter().position(|x| x.value.as_ref() == Some(index));
+            Ok(pos.and_then(|pos| v.values[pos].value.clone()))
+        }
+        (Value::ListValue(v), Value::I64Value(index)) => {
+            let pos = list_index(*index, v.values.len())?;
+            Ok(v.values[pos].value.clone())
+        }
+        (Value::ListValue(_), _) => Err(Error::invalid_argument("require i64 index")),
+        _ => Err(Error::invalid_argument("require container object")),
+    }
+}
+
+/// Replaces the member of `value` at `index`, or removes it if `member` is
+/// none.
+pub fn set_member(value: &mut Value, index: Value, member: Option<Value>) -> Result<()> {
+    match value {
+        Value::MapValue(v) => {
+            let pos = v.keys.iter().position(|x| x.value.as_ref() == Some(&index));
+            match (pos, member) {
+                (Some(pos), Some(member)) => v.values[pos] = member.into(),
+                (Some(pos), None) => {
+                    v.keys.remove(pos);
+                    v.values.remove(pos);
+                }
+                (None, Some(member)) => {
+                    v.keys.push(index.into());
+                    v.values.push(member.into());
+                }
+                (None, None) => {}
+            }
+        }
+        Value::ListValue(v) => {
+            let pos = match index {
+                Value::I64Value(index) => list_index(index, v.values.len())?,
+                _ => return Err(Error::invalid_argument("require i64 index")),
+            };
+            match member {
+                Some(member) => v.values[pos] = member.into(),
+                None => {
+                    v.values.remove(pos);
+                }
+            }
+        }
+        _ => return Err(Error::invalid_argument("require container object")),
+    }
+    Ok(())
+}
+
+/// Converts a list index to a position. Negative indexes count from the end.
+pub fn list_index(index: i64, len: usize) -> Result<usize> {
+    let len = len as i64;
+    let pos = if index < 0 { index + len } else { index };
+    if pos >= 0 && pos < len {
+        Ok(pos as usize)
+    } else {
+        Err(Error::invalid_argument("index out of range"))
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Result<Ordering> {
+    match (a, b) {
+        (Value::I64Value(a), Value::I64Value(b)) => Ok(a.cmp(b)),
+        (Value::BlobValue(a), Value::BlobValue(b)) => Ok(a.cmp(b)),
+        (Value::TextValue(a), Value::TextValue(b)) => Ok(a.cmp(b)),
+        (Value::I64Value(_) | Value::F64Value(_), Value::I64Value(_) | Value::F64Value(_)) => {
+            numeric::as_f64(a)?
+                .partial_cmp(&numeric::as_f64(b)?)
+                .ok_or_else(|| Error::invalid_argument("incomparable values"))
+        }
+        _ => Err(Error::invalid_argument("incomparable values")),
+    }
+}
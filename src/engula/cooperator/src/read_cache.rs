@@ -0,0 +1,323 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    ops::RangeBounds,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crate::Object;
+
+pub struct ReadCacheOptions {
+    /// The approximate number of bytes of objects that are kept in memory.
+    pub capacity: usize,
+}
+
+impl Default for ReadCacheOptions {
+    fn default() -> Self {
+        Self { capacity: 1 << 30 }
+    }
+}
+
+/// The capacity and counters shared by the read caches of a cooperator.
+pub struct ReadCacheStats {
+    capacity: usize,
+    size: AtomicUsize,
+    // The number of caches that share the capacity.
+    caches: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl ReadCacheStats {
+    pub fn new(options: ReadCacheOptions) -> Self {
+        Self {
+            capacity: options.capacity,
+            size: AtomicUsize::new(0),
+            caches: AtomicUsize::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns stats of caches that never evict objects.
+    pub fn unbounded() -> Self {
+        Self::new(ReadCacheOptions {
+            capacity: usize::MAX,
+        })
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the approximate number of bytes of cached objects.
+    pub fn size(&self) -> usize {
+        self.size.load(Ordering::Relaxed)
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// The objects of a shard that are kept in memory.
+///
+/// Each object is charged with its approximate encoded size, which maps and
+/// sorted sets keep as they change, so that recharging them is cheap. Once
+/// the caches of a cooperator exceed their capacity, objects are evicted in
+/// least recently used order.
+pub struct ReadCache {
+    objects: BTreeMap<Vec<u8>, Entry>,
+    // The sum of the charges of the objects in this cache.
+    size: usize,
+    // Object ids in the order of their last access.
+    lru: BTreeMap<u64, Vec<u8>>,
+    next_tick: u64,
+    stats: Arc<ReadCacheStats>,
+}
+
+struct Entry {
+    object: Object,
+    tick: u64,
+    charge: usize,
+}
+
+impl ReadCache {
+    pub fn new(stats: Arc<ReadCacheStats>) -> Self {
+        stats.caches.fetch_add(1, Ordering::Relaxed);
+        Self {
+            objects: BTreeMap::new(),
+            size: 0,
+            lru: BTreeMap::new(),
+            next_tick: 0,
+            stats,
+        }
+    }
+
+    pub fn get(&self, id: &[u8]) -> Option<&Object> {
+        self.objects.get(id).map(|ent| &ent.object)
+    }
+
+    /// Returns a mutable reference to an object. Changes in its size are
+    /// accounted by the next `recharge`.
+    pub fn get_mut(&mut self, id: &[u8]) -> Option<&mut Object> {
+        self.objects.get_mut(id).map(|ent| &mut ent.object)
+    }
+
+    pub fn contains_key(&self, id: &[u8]) -> bool {
+        self.objects.contains_key(id)
+    }
+
+    pub fn range<R>(&self, range: R) -> impl Iterator<Item = (&Vec<u8>, &Object)>
+    where
+        R: RangeBounds<Vec<u8>>,
+    {
+        self.objects.range(range).map(|(id, ent)| (id, &ent.object))
+    }
+
+    pub fn insert(&mut self, id: Vec<u8>, object: Object) -> Option<Object> {
+        let old = self.remove(&id);
+        let tick = self.tick();
+        let charge = charge_of(&id, &object);
+        self.size += charge;
+        self.stats.size.fetch_add(charge, Ordering::Relaxed);
+        self.lru.insert(tick, id.clone());
+        let ent = Entry {
+            object,
+            tick,
+            charge,
+        };
+        self.objects.insert(id, ent);
+        old
+    }
+
+    pub fn remove(&mut self, id: &[u8]) -> Option<Object> {
+        let ent = self.objects.remove(id)?;
+        self.lru.remove(&ent.tick);
+        self.size -= ent.charge;
+        self.stats.size.fetch_sub(ent.charge, Ordering::Relaxed);
+        Some(ent.object)
+    }
+
+    /// Marks an object as recently used. Returns whether it is cached.
+    pub fn touch(&mut self, id: &[u8]) -> bool {
+        let tick = self.tick();
+        match self.objects.get_mut(id) {
+            Some(ent) => {
+                self.lru.remove(&ent.tick);
+                self.lru.insert(tick, id.to_owned());
+                ent.tick = tick;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Updates the charge of an object after it is changed in place.
+    pub fn recharge(&mut self, id: &[u8]) {
+        if let Some(ent) = self.objects.get_mut(id) {
+            let charge = charge_of(id, &ent.object);
+            self.size = self.size + charge - ent.charge;
+            self.stats.size.fetch_add(charge, Ordering::Relaxed);
+            self.stats.size.fetch_sub(ent.charge, Ordering::Relaxed);
+            ent.charge = charge;
+        }
+    }
+
+    /// Evicts the least recently used objects while the caches exceed their
+    /// capacity, and returns the ids of evicted objects.
+    ///
+    /// A cache evicts objects only down to its share of the capacity, so
+    /// that caches within their shares keep their objects when other caches
+    /// grow, and the caches above their shares evict objects instead.
+    pub fn evict(&mut self) -> Vec<Vec<u8>> {
+        let caches = self.stats.caches.load(Ordering::Relaxed).max(1);
+        let share = self.stats.capacity() / caches;
+        let mut evicted = Vec::new();
+        while self.stats.size() > self.stats.capacity() && self.size > share {
+            let id = match self.lru.values().next() {
+                Some(id) => id.clone(),
+                None => break,
+            };
+            self.remove(&id);
+            evicted.push(id);
+        }
+        evicted
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.next_tick += 1;
+        self.next_tick
+    }
+}
+
+impl Drop for ReadCache {
+    fn drop(&mut self) {
+        self.stats.size.fetch_sub(self.size, Ordering::Relaxed);
+        self.stats.caches.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+fn charge_of(id: &[u8], object: &Object) -> usize {
+    let size = match object {
+        Object::Value(v) => v.encoded_len(),
+        Object::Map(v) => v.size(),
+        Object::SortedSet(v) => v.size(),
+    };
+    id.len() + size
+}
+
+#[cfg(test)]
+mod tests {
+    use engula_apis::*;
+    use prost::Message;
+
+    use super::*;
+    use crate::Map;
+
+    fn object(len: usize) -> Object {
+        Object::Value(Value::BlobValue(vec![0; len]))
+    }
+
+    #[test]
+    fn evict_least_recently_used() {
+        let charge = charge_of(b"a", &object(10));
+        let options = ReadCacheOptions {
+            capacity: charge * 2,
+        };
+        let stats = Arc::new(ReadCacheStats::new(options));
+        let mut cache = ReadCache::new(stats.clone());
+        cache.insert(b"a".to_vec(), object(10));
+        cache.insert(b"b".to_vec(), object(10));
+        assert_eq!(stats.size(), charge * 2);
+        assert!(cache.evict().is_empty());
+
+        // Touching `a` makes `b` the least recently used one.
+        assert!(cache.touch(b"a"));
+        cache.insert(b"c".to_vec(), object(10));
+        assert_eq!(cache.evict(), vec![b"b".to_vec()]);
+        assert!(cache.contains_key(b"a"));
+        assert!(!cache.contains_key(b"b"));
+
+        // Growing an object in place is accounted after a recharge.
+        if let Some(Object::Value(Value::BlobValue(v))) = cache.get_mut(b"c") {
+            v.extend_from_slice(&[0; 10]);
+        }
+        cache.recharge(b"c");
+        assert_eq!(cache.evict(), vec![b"a".to_vec()]);
+        assert_eq!(stats.size(), charge_of(b"c", &object(20)));
+
+        drop(cache);
+        assert_eq!(stats.size(), 0);
+    }
+
+    #[test]
+    fn evict_down_to_share() {
+        let charge = charge_of(b"a", &object(10));
+        let options = ReadCacheOptions {
+            capacity: charge * 4,
+        };
+        let stats = Arc::new(ReadCacheStats::new(options));
+        let mut small = ReadCache::new(stats.clone());
+        let mut large = ReadCache::new(stats.clone());
+        small.insert(b"a".to_vec(), object(10));
+        for id in [b"b", b"c", b"d", b"e"] {
+            large.insert(id.to_vec(), object(10));
+        }
+
+        // The small cache stays within its share, so only the large one
+        // evicts objects.
+        assert!(small.evict().is_empty());
+        assert_eq!(large.evict(), vec![b"b".to_vec()]);
+        assert_eq!(stats.size(), charge * 4);
+    }
+
+    #[test]
+    fn recharge_changed_map() {
+        let stats = Arc::new(ReadCacheStats::unbounded());
+        let mut cache = ReadCache::new(stats.clone());
+        cache.insert(b"a".to_vec(), Map::default().into());
+        assert_eq!(stats.size(), 1);
+
+        let value: ValueUnion = Value::BlobValue(vec![0; 10]).into();
+        if let Some(Object::Map(v)) = cache.get_mut(b"a") {
+            v.insert(b"k".to_vec(), value.clone());
+        }
+        cache.recharge(b"a");
+        assert_eq!(stats.size(), 2 + value.encoded_len());
+
+        if let Some(Object::Map(v)) = cache.get_mut(b"a") {
+            v.remove(b"k");
+        }
+        cache.recharge(b"a");
+        assert_eq!(stats.size(), 1);
+    }
+}
@@ -12,11 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{path::Path, pin::Pin};
+
 use engula_apis::*;
-use engula_supervisor::Supervisor;
+use engula_supervisor::{shard_host_server, CatchUpRequest, CatchUpResponse, Supervisor};
+use futures::{stream, Stream};
+use tokio::sync::broadcast::error::RecvError;
 use tonic::{Request, Response, Status};
 
-use crate::{apis::*, Universe};
+use crate::{
+    apis::*, open_object_engine, Changes, Journal, ObjectEngine, ReadCacheOptions, ReadCacheStats,
+    Universe,
+};
+
+pub type WatchStream = Pin<Box<dyn Stream<Item = Result<WatchResponse, Status>> + Send>>;
 
 #[derive(Clone)]
 pub struct Server {
@@ -26,20 +35,138 @@ pub struct Server {
 impl Server {
     pub fn new(supervisor: Supervisor) -> Self {
         Self {
-            uv: Universe::new(supervisor),
+            uv: Universe::new(supervisor, None, None, ReadCacheStats::unbounded()),
+        }
+    }
+
+    /// Creates a server that logs changes to `journal` and recovers from it.
+    pub fn with_journal(supervisor: Supervisor, journal: Journal) -> Self {
+        Self {
+            uv: Universe::new(supervisor, Some(journal), None, ReadCacheStats::unbounded()),
+        }
+    }
+
+    /// Creates a server that logs changes to `journal`, and flushes them into
+    /// `engine` in the background. Objects that don't fit in the read cache
+    /// are read from `engine`.
+    pub fn with_object_engine(
+        supervisor: Supervisor,
+        journal: Journal,
+        engine: ObjectEngine,
+        cache: ReadCacheOptions,
+    ) -> Self {
+        let cache = ReadCacheStats::new(cache);
+        Self {
+            uv: Universe::new(supervisor, Some(journal), Some(engine), cache),
         }
     }
 
+    /// Opens a server that keeps its objects under `path`. If `journal` is
+    /// given, changes to objects are logged to it and flushed into an object
+    /// engine under `path`. Otherwise, objects are kept in memory only.
+    pub async fn open(
+        supervisor: Supervisor,
+        path: impl AsRef<Path>,
+        journal: Option<Journal>,
+    ) -> crate::Result<Self> {
+        let server = match journal {
+            Some(journal) => {
+                let engine = open_object_engine(path.as_ref().join("objects")).await?;
+                let cache = ReadCacheOptions::default();
+                Self::with_object_engine(supervisor, journal, engine, cache)
+            }
+            None => Self::new(supervisor),
+        };
+        Ok(server)
+    }
+
+    pub fn read_cache_stats(&self) -> &ReadCacheStats {
+        self.uv.read_cache_stats()
+    }
+
     pub fn into_service(self) -> cooperator_server::CooperatorServer<Self> {
         cooperator_server::CooperatorServer::new(self)
     }
+
+    /// Returns the service that the supervisor uses to move shards to this
+    /// server.
+    pub fn into_shard_host_service(self) -> shard_host_server::ShardHostServer<Self> {
+        shard_host_server::ShardHostServer::new(self)
+    }
 }
 
 #[tonic::async_trait]
 impl cooperator_server::Cooperator for Server {
+    type WatchStream = WatchStream;
+
     async fn txn(&self, req: Request<TxnRequest>) -> Result<Response<TxnResponse>, Status> {
         let req = req.into_inner();
         let res = self.uv.execute(req).await?;
         Ok(Response::new(res))
     }
+
+    async fn watch(
+        &self,
+        req: Request<WatchRequest>,
+    ) -> Result<Response<Self::WatchStream>, Status> {
+        let req = req.into_inner();
+        let changes = self
+            .uv
+            .watch(&req.dbname, &req.coname, req.shard, req.start_sequence)
+            .await?;
+        let stream: WatchStream = Box::pin(watch_stream(changes, req.start, req.end));
+        Ok(Response::new(stream))
+    }
+}
+
+#[tonic::async_trait]
+impl shard_host_server::ShardHost for Server {
+    async fn catch_up(
+        &self,
+        req: Request<CatchUpRequest>,
+    ) -> Result<Response<CatchUpResponse>, Status> {
+        let req = req.into_inner();
+        let sequence = self
+            .uv
+            .catch_up(&req.dbname, &req.coname, req.shard, req.fence)
+            .await?;
+        Ok(Response::new(CatchUpResponse { sequence }))
+    }
+}
+
+// Yields changes to objects in the range [start, end), where an empty end
+// means that the range is unbounded. The stream fails if it falls too far
+// behind, since the skipped changes are lost, and the error tells the
+// sequence to watch again from to resume.
+fn watch_stream(
+    changes: Changes,
+    start: Vec<u8>,
+    end: Vec<u8>,
+) -> impl Stream<Item = Result<WatchResponse, Status>> {
+    let state = (changes.retained.into_iter(), changes, start, end);
+    stream::unfold(Some(state), |state| async move {
+        let (mut retained, mut changes, start, end) = state?;
+        loop {
+            let event = match retained.next() {
+                Some(event) => event,
+                None => match changes.receiver.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        let status = Status::data_loss(format!(
+                            "watcher lagged by {} changes after sequence {}, watch from sequence {} to resume",
+                            n,
+                            changes.last_sequence,
+                            changes.last_sequence + 1
+                        ));
+                        return Some((Err(status), None));
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+            };
+            changes.last_sequence = event.sequence;
+            if event.id >= start && (end.is_empty() || event.id < end) {
+                return Some((Ok(event), Some((retained, changes, start, end))));
+            }
+        }
+    })
 }
@@ -0,0 +1,187 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    cmp::Ordering,
+    collections::{BTreeSet, HashMap},
+    mem::size_of,
+};
+
+use engula_apis::*;
+
+use crate::{Error, Result};
+
+/// A set of members ordered by score, then by member.
+#[derive(Clone, Default)]
+pub struct SortedSet {
+    scores: HashMap<Vec<u8>, Score>,
+    members: BTreeSet<(Score, Vec<u8>)>,
+    // The approximate encoded size of the members and their scores, which is
+    // kept as they change.
+    size: usize,
+}
+
+impl SortedSet {
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    /// Returns the approximate encoded size of the set.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).map(|s| s.0)
+    }
+
+    pub fn insert(&mut self, member: Vec<u8>, score: f64) -> Result<()> {
+        let score = Score::new(score)?;
+        if let Some(old) = self.scores.insert(member.clone(), score) {
+            self.members.remove(&(old, member.clone()));
+        } else {
+            self.size += member_size(&member);
+        }
+        self.members.insert((score, member));
+        Ok(())
+    }
+
+    /// Adds `delta` to the score of the member and returns the new score.
+    /// A missing member starts from zero.
+    pub fn incr(&mut self, member: Vec<u8>, delta: f64) -> Result<f64> {
+        let score = self.score(&member).unwrap_or_default() + delta;
+        self.insert(member, score)?;
+        Ok(score)
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        if let Some(score) = self.scores.remove(member) {
+            self.members.remove(&(score, member.to_owned()));
+            self.size -= member_size(member);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns members with ranks in [start, end). Negative ranks count from
+    /// the end.
+    pub fn range_by_rank(&self, start: i64, end: i64) -> SortedSetValue {
+        let len = self.len() as i64;
+        let start = normalize_rank(start, len);
+        let end = normalize_rank(end, len);
+        collect(
+            self.members
+                .iter()
+                .skip(start)
+                .take(end.saturating_sub(start)),
+        )
+    }
+
+    /// Returns members with scores in [min, max].
+    pub fn range_by_score(&self, min: f64, max: f64) -> Result<SortedSetValue> {
+        let min = Score::new(min)?;
+        let max = Score::new(max)?;
+        let members = self
+            .members
+            .range((min, Vec::new())..)
+            .take_while(|(score, _)| *score <= max);
+        Ok(collect(members))
+    }
+
+    pub fn pop_min(&mut self, count: usize) -> SortedSetValue {
+        let popped: Vec<_> = self.members.iter().take(count).cloned().collect();
+        self.pop(popped)
+    }
+
+    pub fn pop_max(&mut self, count: usize) -> SortedSetValue {
+        let popped: Vec<_> = self.members.iter().rev().take(count).cloned().collect();
+        self.pop(popped)
+    }
+
+    fn pop(&mut self, popped: Vec<(Score, Vec<u8>)>) -> SortedSetValue {
+        for (_, member) in &popped {
+            self.remove(member);
+        }
+        collect(popped.iter())
+    }
+
+    pub fn to_value(&self) -> SortedSetValue {
+        collect(self.members.iter())
+    }
+}
+
+impl TryFrom<SortedSetValue> for SortedSet {
+    type Error = Error;
+
+    fn try_from(v: SortedSetValue) -> Result<Self> {
+        if v.members.len() != v.scores.len() {
+            return Err(Error::invalid_argument("unmatched members and scores"));
+        }
+        let mut set = SortedSet::default();
+        for (member, score) in v.members.into_iter().zip(v.scores) {
+            set.insert(member, score)?;
+        }
+        Ok(set)
+    }
+}
+
+// A score that is never NaN, so that it is totally ordered.
+#[derive(Clone, Copy, PartialEq)]
+struct Score(f64);
+
+impl Score {
+    fn new(score: f64) -> Result<Self> {
+        if score.is_nan() {
+            Err(Error::invalid_argument("score is NaN"))
+        } else {
+            Ok(Self(score))
+        }
+    }
+}
+
+impl Eq for Score {}
+
+impl PartialOrd for Score {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Score {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.partial_cmp(&other.0).unwrap()
+    }
+}
+
+fn member_size(member: &[u8]) -> usize {
+    member.len() + size_of::<f64>()
+}
+
+fn normalize_rank(rank: i64, len: i64) -> usize {
+    if rank < 0 {
+        (rank + len).max(0) as usize
+    } else {
+        rank.min(len) as usize
+    }
+}
+
+fn collect<'a>(members: impl Iterator<Item = &'a (Score, Vec<u8>)>) -> SortedSetValue {
+    let mut value = SortedSetValue::default();
+    for (score, member) in members {
+        value.members.push(member.clone());
+        value.scores.push(score.0);
+    }
+    value
+}
@@ -12,59 +12,153 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    time::Duration,
+};
 
 use engula_apis::*;
 use engula_supervisor::Supervisor;
 use tokio::sync::Mutex;
 
-use crate::{Database, Result};
+use crate::{Changes, Database, Error, Journal, ObjectEngine, ReadCacheStats, Result};
+
+const DROP_INTERVAL: Duration = Duration::from_secs(10);
 
 #[derive(Clone)]
 pub struct Universe {
-    inner: Arc<Mutex<Inner>>,
+    inner: Arc<Inner>,
 }
 
 impl Universe {
-    pub fn new(supervisor: Supervisor) -> Self {
-        let inner = Inner::new(supervisor);
-        Self {
-            inner: Arc::new(Mutex::new(inner)),
-        }
+    pub fn new(
+        supervisor: Supervisor,
+        journal: Option<Journal>,
+        engine: Option<ObjectEngine>,
+        cache: ReadCacheStats,
+    ) -> Self {
+        let inner = Arc::new(Inner::new(supervisor, journal, engine, cache));
+        tokio::spawn(drop_deleted(Arc::downgrade(&inner)));
+        Self { inner }
+    }
+
+    pub fn read_cache_stats(&self) -> &ReadCacheStats {
+        &self.inner.cache
     }
 
     pub async fn execute(&self, req: TxnRequest) -> Result<TxnResponse> {
-        let mut inner = self.inner.lock().await;
         let mut res = TxnResponse::default();
         for dbreq in req.requests {
-            let db = inner.database(&dbreq.name).await?;
+            let db = self.inner.database(&dbreq.name).await?;
             let dbres = db.execute(dbreq).await?;
             res.responses.push(dbres);
         }
         Ok(res)
     }
+
+    /// Opens a shard that is moved to or away from this cooperator. See
+    /// [`Database::catch_up`].
+    pub async fn catch_up(
+        &self,
+        dbname: &str,
+        coname: &str,
+        shard: u64,
+        fence: bool,
+    ) -> Result<u64> {
+        let db = self.inner.database(dbname).await?;
+        db.catch_up(coname, shard, fence).await
+    }
+
+    pub async fn watch(
+        &self,
+        dbname: &str,
+        coname: &str,
+        shard: u64,
+        start: u64,
+    ) -> Result<Changes> {
+        let db = self.inner.database(dbname).await?;
+        db.watch(coname, shard, start).await
+    }
+}
+
+// Drops the state of deleted databases and collections, and closes the logs
+// that this member should not lead, periodically.
+async fn drop_deleted(inner: Weak<Inner>) {
+    let mut interval = tokio::time::interval(DROP_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Some(inner) = inner.upgrade() {
+            inner.drop_deleted().await;
+        } else {
+            break;
+        }
+    }
 }
 
 struct Inner {
     sp: Supervisor,
-    databases: BTreeMap<u64, Database>,
+    journal: Option<Journal>,
+    engine: Option<ObjectEngine>,
+    cache: Arc<ReadCacheStats>,
+    databases: Mutex<BTreeMap<u64, Database>>,
 }
 
 impl Inner {
-    fn new(supervisor: Supervisor) -> Self {
+    fn new(
+        supervisor: Supervisor,
+        journal: Option<Journal>,
+        engine: Option<ObjectEngine>,
+        cache: ReadCacheStats,
+    ) -> Self {
         Self {
             sp: supervisor,
-            databases: BTreeMap::new(),
+            journal,
+            engine,
+            cache: Arc::new(cache),
+            databases: Mutex::new(BTreeMap::new()),
         }
     }
 
-    async fn database(&mut self, name: &str) -> Result<Database> {
+    async fn database(&self, name: &str) -> Result<Database> {
         let desc = self.sp.describe_database(name.to_owned()).await?;
-        let db = self
-            .databases
-            .entry(desc.id)
-            .or_insert_with(|| Database::new(desc, self.sp.clone()))
-            .clone();
+        // Holds the lock while a database is opened, so that it is opened only
+        // once.
+        let mut databases = self.databases.lock().await;
+        let db = match databases.get(&desc.id) {
+            Some(db) => db.clone(),
+            None => {
+                let db = Database::open(
+                    desc.clone(),
+                    self.sp.clone(),
+                    self.journal.clone(),
+                    self.engine.clone(),
+                    self.cache.clone(),
+                )
+                .await?;
+                databases.insert(desc.id, db.clone());
+                db
+            }
+        };
+        db.update(&desc);
         Ok(db)
     }
+
+    async fn drop_deleted(&self) {
+        let databases: Vec<_> = self.databases.lock().await.values().cloned().collect();
+        for db in databases {
+            let res = self.sp.describe_database(db.name().to_owned()).await;
+            // A database with the same name but another id is a new one.
+            let deleted = match res {
+                Ok(desc) => desc.id != db.id(),
+                Err(Error::NotFound(_)) => true,
+                Err(_) => false,
+            };
+            if deleted {
+                self.databases.lock().await.remove(&db.id());
+            } else {
+                db.drop_deleted().await;
+            }
+        }
+    }
 }
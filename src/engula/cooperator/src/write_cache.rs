@@ -12,60 +12,362 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::VecDeque, mem::size_of};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    mem::size_of,
+    path::PathBuf,
+    sync::Arc,
+};
 
+use object_engine_client::{Bucket, LocalEnv, Tenant};
+use prost::Message;
 use tokio::sync::Mutex;
 
-#[allow(dead_code)]
+use crate::{
+    apis::{CollectionLog, ObjectLog},
+    Error, Object, Result,
+};
+
+/// The object engine that memtables are flushed into.
+pub type ObjectEngine = object_engine_client::Engine<LocalEnv>;
+
+/// Opens an object engine that stores files under `path`.
+pub async fn open_object_engine(path: impl Into<PathBuf>) -> Result<ObjectEngine> {
+    let env = LocalEnv::open(path).await.map_err(engine_error)?;
+    ObjectEngine::open(env).await.map_err(engine_error)
+}
+
 pub struct WriteCacheOptions {
-    memtable_size: usize,
+    pub memtable_size: usize,
+}
+
+impl Default for WriteCacheOptions {
+    fn default() -> Self {
+        Self {
+            memtable_size: 64 << 20,
+        }
+    }
 }
 
-#[allow(dead_code)]
+/// Accumulates logged changes of a database in memtables, and flushes full
+/// memtables into the object engine.
+///
+/// Flushed records are not truncated from the log. The object engine is
+/// local to each member, so other members, including a new leader or the
+/// target of a shard move, still recover the flushed changes from the log.
+/// The log can only be truncated once memtables are flushed into a store that
+/// all members can read.
+#[derive(Clone)]
 pub struct WriteCache {
-    inner: Mutex<WriteCacheInner>,
+    inner: Arc<Mutex<WriteCacheInner>>,
 }
 
 struct WriteCacheInner {
     options: WriteCacheOptions,
+    tenant: Tenant<LocalEnv>,
     mem: Memtable,
     imm_list: VecDeque<Memtable>,
+    flushing: bool,
 }
 
-#[allow(dead_code)]
 impl WriteCache {
-    pub fn new(options: WriteCacheOptions) -> Self {
+    pub fn new(options: WriteCacheOptions, tenant: Tenant<LocalEnv>) -> Self {
         let inner = WriteCacheInner {
             options,
+            tenant,
             mem: Memtable::default(),
             imm_list: VecDeque::new(),
+            flushing: false,
         };
         Self {
-            inner: Mutex::new(inner),
+            inner: Arc::new(Mutex::new(inner)),
+        }
+    }
+
+    /// Pins the current memtable for a record that is about to be appended
+    /// to the log, and returns its generation.
+    ///
+    /// A pinned memtable is not flushed until the record is added or
+    /// unpinned.
+    pub async fn pin(&self) -> u64 {
+        let mut inner = self.inner.lock().await;
+        inner.mem.pending += 1;
+        inner.mem.generation
+    }
+
+    pub async fn unpin(&self, generation: u64) {
+        let mut inner = self.inner.lock().await;
+        if let Some(mem) = inner.memtable_mut(generation) {
+            mem.pending -= 1;
+        }
+        self.maybe_flush(&mut inner);
+    }
+
+    /// Adds the batches of a record to the memtable pinned for it.
+    pub async fn add(&self, generation: u64, batches: Vec<(u64, WriteBatch)>) {
+        let mut inner = self.inner.lock().await;
+        if let Some(mem) = inner.memtable_mut(generation) {
+            for (coid, batch) in batches {
+                mem.add(coid, batch);
+            }
+            mem.pending -= 1;
         }
+        inner.maybe_freeze();
+        self.maybe_flush(&mut inner);
     }
 
-    pub async fn add(&self, batch: WriteBatch) {
+    /// Adds the batches of a record that another member has appended to the
+    /// log. The record is already logged, so it goes to the current memtable
+    /// without pinning it.
+    pub async fn add_logged(&self, batches: Vec<(u64, WriteBatch)>) {
         let mut inner = self.inner.lock().await;
-        inner.mem.add(batch);
-        if inner.mem.approximate_size() >= inner.options.memtable_size {
-            let imm = std::mem::take(&mut inner.mem);
-            inner.imm_list.push_back(imm);
-            // TODO: sorts and flushes immutable memtables.
+        for (coid, batch) in batches {
+            inner.mem.add(coid, batch);
+        }
+        inner.maybe_freeze();
+        self.maybe_flush(&mut inner);
+    }
+
+    /// Returns the latest state of an object in a collection, or none if it
+    /// does not exist.
+    ///
+    /// Objects that are not in memtables are read from the object engine.
+    pub async fn get(&self, coid: u64, id: &[u8]) -> Result<Option<ObjectLog>> {
+        // The changed members since the latest full version, newest first.
+        let mut merges = Vec::new();
+        let tenant = {
+            let inner = self.inner.lock().await;
+            // Newer memtables come first.
+            let memtables = std::iter::once(&inner.mem).chain(inner.imm_list.iter().rev());
+            for mem in memtables {
+                for version in mem.versions(coid, id).iter().rev() {
+                    if !version.merge {
+                        let object = decode_object(id, version.value.as_deref())?;
+                        return merge_object(id, object, merges);
+                    }
+                    merges.extend(version.value.clone());
+                }
+            }
+            inner.tenant.clone()
+        };
+        let bucket = match tenant.bucket(&bucket_name(coid)).await {
+            Ok(bucket) => bucket,
+            // Nothing of the collection has been flushed yet.
+            Err(object_engine_client::Error::NotFound(_)) => return Ok(None),
+            Err(err) => return Err(engine_error(err)),
+        };
+        let value = bucket.get(id).await.map_err(engine_error)?;
+        let object = decode_object(id, value.as_deref())?;
+        merge_object(id, object, merges)
+    }
+
+    fn maybe_flush(&self, inner: &mut WriteCacheInner) {
+        if !inner.flushing && inner.imm_list.front().map_or(false, |imm| imm.pending == 0) {
+            inner.flushing = true;
+            tokio::spawn(flush(self.inner.clone()));
+        }
+    }
+}
+
+impl WriteCacheInner {
+    fn memtable_mut(&mut self, generation: u64) -> Option<&mut Memtable> {
+        if self.mem.generation == generation {
+            return Some(&mut self.mem);
+        }
+        self.imm_list
+            .iter_mut()
+            .find(|imm| imm.generation == generation)
+    }
+
+    // Replaces the current memtable with a new one if it is full.
+    fn maybe_freeze(&mut self) {
+        if self.mem.approximate_size() >= self.options.memtable_size {
+            let generation = self.mem.generation + 1;
+            let mem = Memtable {
+                generation,
+                ..Default::default()
+            };
+            let imm = std::mem::replace(&mut self.mem, mem);
+            self.imm_list.push_back(imm);
+        }
+    }
+}
+
+// Flushes immutable memtables in order until one of them is pinned. A
+// memtable that fails to flush is retried when the next one is frozen.
+async fn flush(inner: Arc<Mutex<WriteCacheInner>>) {
+    loop {
+        // The memtable stays readable while it is flushed.
+        let (tenant, tables) = {
+            let mut inner = inner.lock().await;
+            match inner.imm_list.front() {
+                Some(imm) if imm.pending == 0 => (inner.tenant.clone(), imm.tables.clone()),
+                _ => {
+                    inner.flushing = false;
+                    return;
+                }
+            }
+        };
+        let res = flush_tables(&tenant, &tables).await;
+        let mut guard = inner.lock().await;
+        if res.is_err() {
+            guard.flushing = false;
+            return;
+        }
+        guard.imm_list.pop_front();
+    }
+}
+
+async fn flush_tables(tenant: &Tenant<LocalEnv>, tables: &BTreeMap<u64, Table>) -> Result<()> {
+    let mut bulkload = tenant.begin_bulkload().await.map_err(engine_error)?;
+    for (coid, table) in tables {
+        let bucket = bucket(tenant, *coid).await?;
+        let mut builder = bulkload
+            .new_sst_builder(&bucket)
+            .await
+            .map_err(engine_error)?;
+        // Only the latest version of each object is flushed, since older
+        // versions are never read from the engine. Entries are added in the
+        // order of tables.
+        for (id, versions) in table {
+            let version = match versions.last() {
+                Some(version) => version,
+                None => continue,
+            };
+            let value = if version.merge {
+                merge_versions(&bucket, id, versions).await?
+            } else {
+                version.value.clone()
+            };
+            let res = match &value {
+                Some(value) => builder.put(id, version.ts, value).await,
+                None => builder.delete(id, version.ts).await,
+            };
+            res.map_err(engine_error)?;
+        }
+        bulkload
+            .finish_sst_builder(builder)
+            .await
+            .map_err(engine_error)?;
+    }
+    bulkload.commit().await.map_err(engine_error)
+}
+
+// Applies the changed members in the latest versions of an object to its
+// latest full version, which is read from the engine if it is flushed, and
+// returns the encoded object.
+async fn merge_versions(
+    bucket: &Bucket<LocalEnv>,
+    id: &[u8],
+    versions: &[Version],
+) -> Result<Option<Vec<u8>>> {
+    let start = versions.iter().rposition(|version| !version.merge);
+    let object = match start {
+        Some(i) => decode_object(id, versions[i].value.as_deref())?,
+        None => {
+            let value = bucket.get(id).await.map_err(engine_error)?;
+            decode_object(id, value.as_deref())?
         }
+    };
+    let merges = versions[start.map_or(0, |i| i + 1)..]
+        .iter()
+        .rev()
+        .filter_map(|version| version.value.clone())
+        .collect();
+    let value = merge_object(id, object, merges)?.map(|mut object| {
+        object.id.clear();
+        object.encode_to_vec()
+    });
+    Ok(value)
+}
+
+// Applies encoded logs of changed members, newest first, to an object.
+fn merge_object(
+    id: &[u8],
+    object: Option<ObjectLog>,
+    merges: Vec<Vec<u8>>,
+) -> Result<Option<ObjectLog>> {
+    if merges.is_empty() {
+        return Ok(object);
+    }
+    let mut object = object.ok_or_else(|| Error::corrupted("merge into a missing object"))?;
+    let value = object.value.take().and_then(|v| v.value);
+    let mut value = match value {
+        Some(value) => Object::try_from(value)?,
+        None => return Err(Error::corrupted("merge into a missing object")),
+    };
+    for merge in merges.into_iter().rev() {
+        let merge =
+            ObjectLog::decode(merge.as_slice()).map_err(|e| Error::corrupted(e.to_string()))?;
+        value.apply_members(merge.members)?;
+        object.expire_at = merge.expire_at;
+    }
+    object.value = Some(value.to_value().into());
+    Ok(Some(object))
+}
+
+/// Returns the bucket of a collection, creating it if it doesn't exist.
+pub async fn bucket(tenant: &Tenant<LocalEnv>, coid: u64) -> Result<Bucket<LocalEnv>> {
+    let name = bucket_name(coid);
+    match tenant.bucket(&name).await {
+        Ok(bucket) => Ok(bucket),
+        Err(object_engine_client::Error::NotFound(_)) => match tenant.create_bucket(&name).await {
+            Ok(bucket) => Ok(bucket),
+            Err(object_engine_client::Error::AlreadyExists(_)) => {
+                tenant.bucket(&name).await.map_err(engine_error)
+            }
+            Err(err) => Err(engine_error(err)),
+        },
+        Err(err) => Err(engine_error(err)),
+    }
+}
+
+fn bucket_name(coid: u64) -> String {
+    format!("collection-{}", coid)
+}
+
+/// Returns the tenant of a database, creating it if it doesn't exist.
+pub async fn tenant(engine: &ObjectEngine, dbid: u64) -> Result<Tenant<LocalEnv>> {
+    let name = format!("database-{}", dbid);
+    match engine.tenant(&name).await {
+        Ok(tenant) => Ok(tenant),
+        Err(object_engine_client::Error::NotFound(_)) => match engine.create_tenant(&name).await {
+            Ok(tenant) => Ok(tenant),
+            Err(object_engine_client::Error::AlreadyExists(_)) => {
+                engine.tenant(&name).await.map_err(engine_error)
+            }
+            Err(err) => Err(engine_error(err)),
+        },
+        Err(err) => Err(engine_error(err)),
     }
 }
 
 #[derive(Default)]
 struct Memtable {
-    entries: Vec<Entry>,
+    generation: u64,
+    // Shared with the flush, so that a memtable is readable until it is
+    // flushed.
+    tables: Arc<BTreeMap<u64, Table>>,
     approximate_size: usize,
+    // The number of records that are pinned to the memtable but not added
+    // yet.
+    pending: usize,
 }
 
 impl Memtable {
-    fn add(&mut self, batch: WriteBatch) {
+    fn add(&mut self, coid: u64, batch: WriteBatch) {
         self.approximate_size += batch.encoded_size();
-        self.entries.extend(batch.entries);
+        let table = Arc::make_mut(&mut self.tables).entry(coid).or_default();
+        for (id, version) in batch.entries {
+            table.entry(id).or_default().push(version);
+        }
+    }
+
+    fn versions(&self, coid: u64, id: &[u8]) -> &[Version] {
+        self.tables
+            .get(&coid)
+            .and_then(|table| table.get(id))
+            .map_or(&[], Vec::as_slice)
     }
 
     fn approximate_size(&self) -> usize {
@@ -75,36 +377,112 @@ impl Memtable {
 
 type Timestamp = u64;
 
-enum Entry {
-    Put(Vec<u8>, Timestamp, Vec<u8>),
-    Delete(Vec<u8>, Timestamp),
-}
+// The versions of objects in a collection, in the order they are added.
+type Table = BTreeMap<Vec<u8>, Vec<Version>>;
 
-impl Entry {
-    fn encoded_size(&self) -> usize {
-        let len = match self {
-            Self::Put(id, _, value) => id.len() + value.len(),
-            Self::Delete(id, _) => id.len(),
-        };
-        len + size_of::<Timestamp>()
-    }
+#[derive(Clone)]
+struct Version {
+    ts: Timestamp,
+    // The encoded object, or none if it is deleted.
+    value: Option<Vec<u8>>,
+    // Set if the value holds only the changed members of the object, which
+    // are merged into its previous version when it is read or flushed.
+    merge: bool,
 }
 
+#[derive(Default)]
 pub struct WriteBatch {
-    entries: Vec<Entry>,
+    entries: Vec<(Vec<u8>, Version)>,
 }
 
-#[allow(dead_code)]
 impl WriteBatch {
+    /// Returns a batch of the objects in a log record committed at `ts`.
+    pub fn from_log(ts: Timestamp, colog: &CollectionLog) -> Self {
+        let mut batch = Self::default();
+        for object in &colog.objects {
+            if !object.members.is_empty() {
+                let value = ObjectLog {
+                    id: Vec::new(),
+                    value: None,
+                    expire_at: object.expire_at,
+                    members: object.members.clone(),
+                };
+                batch.merge(object.id.clone(), ts, value.encode_to_vec());
+            } else if object.value.is_some() {
+                // Keeps the expiration time along with the value.
+                let value = ObjectLog {
+                    id: Vec::new(),
+                    value: object.value.clone(),
+                    expire_at: object.expire_at,
+                    members: Vec::new(),
+                };
+                batch.put(object.id.clone(), ts, value.encode_to_vec());
+            } else {
+                batch.delete(object.id.clone(), ts);
+            }
+        }
+        batch
+    }
+
     pub fn put(&mut self, id: Vec<u8>, ts: Timestamp, value: Vec<u8>) {
-        self.entries.push(Entry::Put(id, ts, value))
+        let version = Version {
+            ts,
+            value: Some(value),
+            merge: false,
+        };
+        self.entries.push((id, version))
+    }
+
+    /// Adds the changed members of an object, encoded as an object log.
+    pub fn merge(&mut self, id: Vec<u8>, ts: Timestamp, value: Vec<u8>) {
+        let version = Version {
+            ts,
+            value: Some(value),
+            merge: true,
+        };
+        self.entries.push((id, version))
     }
 
     pub fn delete(&mut self, id: Vec<u8>, ts: Timestamp) {
-        self.entries.push(Entry::Delete(id, ts))
+        let version = Version {
+            ts,
+            value: None,
+            merge: false,
+        };
+        self.entries.push((id, version))
     }
 
     pub fn encoded_size(&self) -> usize {
-        self.entries.iter().map(|x| x.encoded_size()).sum()
+        self.entries
+            .iter()
+            .map(|(id, version)| {
+                let len = version.value.as_ref().map_or(0, Vec::len);
+                id.len() + len + size_of::<Timestamp>()
+            })
+            .sum()
+    }
+}
+
+fn decode_object(id: &[u8], value: Option<&[u8]>) -> Result<Option<ObjectLog>> {
+    match value {
+        Some(value) => {
+            let mut object =
+                ObjectLog::decode(value).map_err(|e| Error::corrupted(e.to_string()))?;
+            object.id = id.to_owned();
+            Ok(Some(object))
+        }
+        None => Ok(None),
+    }
+}
+
+fn engine_error(err: object_engine_client::Error) -> Error {
+    match err {
+        object_engine_client::Error::NotFound(s) => Error::NotFound(s),
+        object_engine_client::Error::AlreadyExists(s) => Error::AlreadyExists(s),
+        object_engine_client::Error::InvalidArgument(s) => Error::InvalidArgument(s),
+        object_engine_client::Error::Corrupted(s) => Error::Corrupted(s),
+        object_engine_client::Error::Internal(s) => Error::Internal(s),
+        object_engine_client::Error::Io(err) => Error::Io(err),
+        object_engine_client::Error::Unknown(err) => Error::Unknown(err),
     }
 }
@@ -12,11 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use anyhow::Result;
-use clap::Parser;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use clap::{ArgEnum, Parser};
+use engula_supervisor::Supervisor;
+use engula_transactor::Journal;
 use tokio::net::TcpListener;
 use tokio_stream::wrappers::TcpListenerStream;
-use tracing::info;
+use tracing::{info, warn};
+
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(3);
 
 #[derive(Parser)]
 pub struct Command {
@@ -44,23 +50,128 @@ impl SubCommand {
     }
 }
 
+#[derive(ArgEnum, Clone, Copy, Debug)]
+enum Role {
+    /// Runs all tiers in one process.
+    All,
+    Supervisor,
+    Cooperator,
+    Transactor,
+}
+
 #[derive(Parser)]
 struct StartCommand {
+    #[clap(long, arg_enum, default_value = "all")]
+    role: Role,
     #[clap(long, default_value = "0.0.0.0:21716")]
     addr: String,
+    /// The url that other servers use to reach this one. Defaults to the
+    /// listening address.
+    #[clap(long)]
+    advertise_url: Option<String>,
+    #[clap(long, default_value = "/tmp/engula")]
+    path: String,
+    /// The url of the stream engine to log changes to, required by
+    /// cooperators.
+    #[clap(long)]
+    journal: Option<String>,
+    #[clap(long, default_value = "engula")]
+    group: String,
+    /// The url of the supervisor, required by cooperators and transactors.
+    #[clap(long)]
+    supervisor: Option<String>,
 }
 
 impl StartCommand {
     async fn run(self) -> Result<()> {
-        let listener = TcpListener::bind(self.addr).await?;
+        let listener = TcpListener::bind(&self.addr).await?;
         let addr = listener.local_addr()?;
-        info!(message = "The server is running at", %addr);
+        info!(message = "The server is running at", %addr, role = ?self.role);
+
+        let incoming = TcpListenerStream::new(listener);
+        let mut builder = tonic::transport::Server::builder();
+        match self.role {
+            Role::All => {
+                let journal = self.connect_journal(addr.to_string()).await?;
+                let transactor = engula_transactor::Server::open(&self.path, journal)
+                    .await?
+                    .into_service();
+                builder
+                    .add_service(transactor)
+                    .serve_with_incoming(incoming)
+                    .await?;
+            }
+            Role::Supervisor => {
+                let supervisor = engula_supervisor::Server::open(&self.path)
+                    .await?
+                    .into_service();
+                builder
+                    .add_service(supervisor)
+                    .serve_with_incoming(incoming)
+                    .await?;
+            }
+            Role::Cooperator => {
+                let supervisor = Supervisor::connect(self.supervisor_url()?).await?;
+                let url = self
+                    .advertise_url
+                    .clone()
+                    .unwrap_or_else(|| format!("http://{}", addr));
+                // Databases move to other cooperators when this one is gone,
+                // so their changes must outlive it. The cooperator joins the
+                // journal with the url that shards are assigned to.
+                let journal = self
+                    .connect_journal(url.clone())
+                    .await?
+                    .ok_or_else(|| anyhow!("--journal is required by the {:?} role", self.role))?;
+                let cooperator =
+                    engula_cooperator::Server::open(supervisor.clone(), &self.path, Some(journal))
+                        .await?;
+                // The supervisor moves shards through the shard host service.
+                let shard_host = cooperator.clone().into_shard_host_service();
+                tokio::spawn(heartbeat(supervisor, url));
+                builder
+                    .add_service(cooperator.into_service())
+                    .add_service(shard_host)
+                    .serve_with_incoming(incoming)
+                    .await?;
+            }
+            Role::Transactor => {
+                let transactor = engula_transactor::Server::connect(self.supervisor_url()?)
+                    .await?
+                    .into_service();
+                builder
+                    .add_service(transactor)
+                    .serve_with_incoming(incoming)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
 
-        let transactor = engula_transactor::Server::new().into_service();
-        tonic::transport::Server::builder()
-            .add_service(transactor)
-            .serve_with_incoming(TcpListenerStream::new(listener))
-            .await
-            .map_err(|e| e.into())
+    async fn connect_journal(&self, id: String) -> Result<Option<Journal>> {
+        match &self.journal {
+            Some(url) => {
+                let journal = Journal::connect(&self.group, id, url).await?;
+                Ok(Some(journal))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn supervisor_url(&self) -> Result<String> {
+        self.supervisor
+            .clone()
+            .ok_or_else(|| anyhow!("--supervisor is required by the {:?} role", self.role))
+    }
+}
+
+// Keeps the cooperator registered in the supervisor.
+async fn heartbeat(supervisor: Supervisor, url: String) {
+    let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+    loop {
+        interval.tick().await;
+        if let Err(err) = supervisor.heartbeat(url.clone()).await {
+            warn!(cause = %err, "Failed to send heartbeat to the supervisor");
+        }
     }
 }
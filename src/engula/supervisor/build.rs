@@ -15,6 +15,12 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_build::configure()
         .extern_path(".engula.v1", "::engula_apis")
-        .compile(&["engula/supervisor/v1/supervisor.proto"], &["."])?;
+        .compile(
+            &[
+                "engula/supervisor/v1/supervisor.proto",
+                "engula/supervisor/v1/manifest.proto",
+            ],
+            &["."],
+        )?;
     Ok(())
 }
@@ -0,0 +1,160 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+/// Where a shard is placed. The unsharded collections of a database are
+/// placed as a shard with an empty collection name and id zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShardPlacement {
+    pub dbname: String,
+    pub coname: String,
+    pub shard: u64,
+    /// The cooperator that serves the shard, which is empty if the shard is
+    /// not assigned yet.
+    pub cooperator: String,
+}
+
+/// A move planned by a [`BalancePolicy`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlannedMove {
+    pub placement: ShardPlacement,
+    pub target: String,
+}
+
+/// Decides which shards to move between cooperators.
+pub trait BalancePolicy: Send + Sync {
+    /// Returns the moves that balance `shards` over `cooperators`. Shards on
+    /// other cooperators, which are dead or evacuated, must be moved to one of
+    /// `cooperators`.
+    fn plan(&self, cooperators: &[String], shards: &[ShardPlacement]) -> Vec<PlannedMove>;
+}
+
+/// Balances the number of shards on each cooperator, so that they differ by
+/// at most one.
+#[derive(Default)]
+pub struct ShardCountPolicy;
+
+impl BalancePolicy for ShardCountPolicy {
+    fn plan(&self, cooperators: &[String], shards: &[ShardPlacement]) -> Vec<PlannedMove> {
+        if cooperators.is_empty() {
+            return Vec::new();
+        }
+        let mut placed: BTreeMap<&str, Vec<&ShardPlacement>> = cooperators
+            .iter()
+            .map(|addr| (addr.as_str(), Vec::new()))
+            .collect();
+        let mut orphans = Vec::new();
+        for shard in shards {
+            match placed.get_mut(shard.cooperator.as_str()) {
+                Some(placed) => placed.push(shard),
+                None => orphans.push(shard),
+            }
+        }
+        let mut moves = Vec::new();
+        for shard in orphans {
+            let target = least_loaded(&placed);
+            placed.entry(target).or_default().push(shard);
+            moves.push(PlannedMove {
+                placement: shard.clone(),
+                target: target.to_owned(),
+            });
+        }
+        loop {
+            let source = most_loaded(&placed);
+            let target = least_loaded(&placed);
+            if placed[source].len() <= placed[target].len() + 1 {
+                break;
+            }
+            if let Some(shard) = placed.get_mut(source).and_then(|shards| shards.pop()) {
+                placed.entry(target).or_default().push(shard);
+                moves.push(PlannedMove {
+                    placement: shard.clone(),
+                    target: target.to_owned(),
+                });
+            }
+        }
+        moves
+    }
+}
+
+// Returns the first cooperator with the fewest shards.
+fn least_loaded<'a>(placed: &BTreeMap<&'a str, Vec<&ShardPlacement>>) -> &'a str {
+    placed
+        .iter()
+        .min_by_key(|(_, shards)| shards.len())
+        .map(|(addr, _)| *addr)
+        .unwrap_or_default()
+}
+
+// Returns the last cooperator with the most shards.
+fn most_loaded<'a>(placed: &BTreeMap<&'a str, Vec<&ShardPlacement>>) -> &'a str {
+    placed
+        .iter()
+        .max_by_key(|(_, shards)| shards.len())
+        .map(|(addr, _)| *addr)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn placement(shard: u64, cooperator: &str) -> ShardPlacement {
+        ShardPlacement {
+            dbname: "db".to_owned(),
+            coname: "co".to_owned(),
+            shard,
+            cooperator: cooperator.to_owned(),
+        }
+    }
+
+    #[test]
+    fn balance_shard_counts() {
+        let cooperators = vec!["a".to_owned(), "b".to_owned(), "c".to_owned()];
+        let shards: Vec<_> = (1..=6).map(|id| placement(id, "a")).collect();
+        let moves = ShardCountPolicy.plan(&cooperators, &shards);
+        assert_eq!(moves.len(), 4);
+        let mut counts = BTreeMap::new();
+        for shard in &shards {
+            let target = moves
+                .iter()
+                .find(|m| m.placement == *shard)
+                .map_or(shard.cooperator.as_str(), |m| m.target.as_str());
+            *counts.entry(target).or_insert(0) += 1;
+        }
+        assert_eq!(counts.into_values().collect::<Vec<_>>(), vec![2, 2, 2]);
+
+        // Balanced shards stay where they are.
+        let shards = vec![
+            placement(1, "a"),
+            placement(2, "b"),
+            placement(3, "a"),
+            placement(4, "c"),
+        ];
+        assert!(ShardCountPolicy.plan(&cooperators, &shards).is_empty());
+    }
+
+    #[test]
+    fn evacuate_shards() {
+        let cooperators = vec!["a".to_owned(), "b".to_owned()];
+        let shards = vec![placement(1, "a"), placement(2, "c"), placement(3, "")];
+        let moves = ShardCountPolicy.plan(&cooperators, &shards);
+        let targets: Vec<_> = moves
+            .iter()
+            .map(|m| (m.placement.shard, m.target.as_str()))
+            .collect();
+        assert_eq!(targets, vec![(2, "b"), (3, "a")]);
+    }
+}
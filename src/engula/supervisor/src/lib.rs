@@ -13,11 +13,32 @@
 // limitations under the License.
 
 mod apis;
+mod balance;
+mod manifest;
+mod mover;
+mod registry;
 mod server;
+mod shard;
 mod supervisor;
 mod universe;
 
 use engula_common::{Error, Result};
 
-use self::universe::{Database, Universe};
-pub use self::{server::Server, supervisor::Supervisor};
+pub use self::{
+    apis::{
+        admin_request, admin_response, shard_host_server, shard_move, AdminRequest, AdminResponse,
+        CatchUpRequest, CatchUpResponse, EvacuateRequest, EvacuateResponse, ListShardMovesRequest,
+        ListShardMovesResponse, MoveShardRequest, MoveShardResponse, RebalanceRequest,
+        RebalanceResponse, ShardMove,
+    },
+    balance::{BalancePolicy, PlannedMove, ShardCountPolicy, ShardPlacement},
+    server::Server,
+    shard::{shard_by_id, shard_of, shard_overlaps},
+    supervisor::Supervisor,
+};
+use self::{
+    manifest::Manifest,
+    mover::Mover,
+    registry::Registry,
+    universe::{Database, Universe},
+};
@@ -0,0 +1,189 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crc::{Crc, CRC_32_ISCSI};
+use prost::Message;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+    sync::Mutex,
+};
+
+use crate::{apis::ManifestEdit, Error, Result};
+
+/// Manifest file format:
+///
+/// The file is a sequence of records, each of which holds an encoded
+/// `ManifestEdit`.
+///
+/// +---------+-----------+--- ... ---+
+/// |CRC (4B) | Size (4B) | Payload   |
+/// +---------+-----------+--- ... ---+
+///
+/// CRC = 32bit hash computed over the payload
+/// Size = Length of the payload
+///
+/// A record torn by a crash can only be the last one, which is discarded on
+/// recovery.
+const HEADER_SIZE: usize = 8;
+
+const CRC: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// A log of edits to the supervisor metadata.
+///
+/// Edits are appended while the supervisor runs, so the log grows until it is
+/// replaced with a snapshot of the metadata by `rewrite`.
+///
+/// A default manifest is not backed by any file, and appending to it does
+/// nothing.
+#[derive(Clone, Default)]
+pub struct Manifest {
+    writer: Option<Arc<Mutex<Writer>>>,
+}
+
+struct Writer {
+    path: PathBuf,
+    file: fs::File,
+    size: u64,
+}
+
+impl Manifest {
+    /// Opens or creates the manifest at `path`. Returns the manifest and the
+    /// edits recovered from it.
+    pub async fn open(path: impl AsRef<Path>) -> Result<(Self, Vec<ManifestEdit>)> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)
+            .await?;
+        let mut buf = Vec::new();
+        file.read_to_end(&mut buf).await?;
+        let (edits, size) = decode_records(&buf)?;
+        if size < buf.len() {
+            file.set_len(size as u64).await?;
+            file.sync_all().await?;
+        }
+        let writer = Writer {
+            path: path.to_owned(),
+            file,
+            size: size as u64,
+        };
+        let manifest = Self {
+            writer: Some(Arc::new(Mutex::new(writer))),
+        };
+        Ok((manifest, edits))
+    }
+
+    /// Appends an edit and syncs it to the file.
+    pub async fn append(&self, edit: ManifestEdit) -> Result<()> {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let record = encode_record(&edit);
+        let mut writer = writer.lock().await;
+        let res = match writer.file.write_all(&record).await {
+            Ok(()) => writer.file.sync_data().await,
+            Err(err) => Err(err),
+        };
+        if let Err(err) = res {
+            // Cuts off what has been written, so that later records are not
+            // appended after a partial one.
+            let size = writer.size;
+            let _ = writer.file.set_len(size).await;
+            return Err(err.into());
+        }
+        writer.size += record.len() as u64;
+        Ok(())
+    }
+
+    /// Replaces all records with `edits`.
+    ///
+    /// The edits are written to a temporary file, which is then renamed over
+    /// the manifest, so that a crash leaves either the old or the new records.
+    pub async fn rewrite(&self, edits: &[ManifestEdit]) -> Result<()> {
+        let writer = match &self.writer {
+            Some(writer) => writer,
+            None => return Ok(()),
+        };
+        let mut buf = Vec::new();
+        for edit in edits {
+            buf.extend(encode_record(edit));
+        }
+
+        let mut writer = writer.lock().await;
+        let tmp_path = writer.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(&buf).await?;
+        file.sync_all().await?;
+        fs::rename(&tmp_path, &writer.path).await?;
+        // Syncs the directory to persist the rename.
+        let dir = match writer.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        fs::File::open(dir).await?.sync_all().await?;
+        writer.file = fs::OpenOptions::new()
+            .append(true)
+            .open(&writer.path)
+            .await?;
+        writer.size = buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn encode_record(edit: &ManifestEdit) -> Vec<u8> {
+    let payload = edit.encode_to_vec();
+    let mut record = Vec::with_capacity(HEADER_SIZE + payload.len());
+    record.extend_from_slice(&CRC.checksum(&payload).to_le_bytes());
+    record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    record.extend_from_slice(&payload);
+    record
+}
+
+// Returns the edits in `buf` and the size of the valid records.
+fn decode_records(buf: &[u8]) -> Result<(Vec<ManifestEdit>, usize)> {
+    let mut edits = Vec::new();
+    let mut offset = 0;
+    while offset + HEADER_SIZE <= buf.len() {
+        let checksum = u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap());
+        let size = u32::from_le_bytes(buf[offset + 4..offset + 8].try_into().unwrap()) as usize;
+        let start = offset + HEADER_SIZE;
+        let end = start + size;
+        if end > buf.len() {
+            break;
+        }
+        let payload = &buf[start..end];
+        if checksum != CRC.checksum(payload) {
+            if end == buf.len() {
+                break;
+            }
+            return Err(Error::corrupted("invalid manifest record"));
+        }
+        let edit = ManifestEdit::decode(payload).map_err(|e| Error::corrupted(e.to_string()))?;
+        edits.push(edit);
+        offset = end;
+    }
+    Ok((edits, offset))
+}
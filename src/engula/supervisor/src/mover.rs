@@ -0,0 +1,468 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use tokio::sync::Mutex;
+
+use crate::{
+    apis::{shard_host_client::ShardHostClient, shard_move::State, *},
+    BalancePolicy, Error, Registry, Result, ShardPlacement, Universe,
+};
+
+const BALANCE_INTERVAL: Duration = Duration::from_secs(10);
+const CATCH_UP_INTERVAL: Duration = Duration::from_millis(100);
+const MOVE_TIMEOUT: Duration = Duration::from_secs(60);
+// How many finished moves are kept for inspection.
+const MAX_FINISHED_MOVES: usize = 1024;
+
+/// Moves shards between cooperators.
+///
+/// A move is an online transfer between cooperators that share the journal of
+/// the database. The target opens the shard and catches up with the log
+/// while the source still serves it. Then the shard is assigned to the
+/// target, and the source is fenced, after which the target catches up with
+/// the records that the source has appended. The fenced source closes the
+/// log of the shard, so the target is elected as the leader of the log once
+/// the lease of the source expires.
+///
+/// The balancer doesn't retry shards whose last move failed, since it would
+/// likely fail the same way in every round, e.g. if the cooperators have no
+/// journal. Rebalancing on request retries them.
+///
+/// Moves are kept in memory only. A move interrupted by a restart either has
+/// assigned the shard to the target or not, and it can be triggered again.
+#[derive(Clone)]
+pub struct Mover {
+    inner: Arc<Mutex<Inner>>,
+    uv: Universe,
+    registry: Registry,
+    policy: Arc<dyn BalancePolicy>,
+}
+
+struct Inner {
+    next_id: u64,
+    moves: BTreeMap<u64, ShardMove>,
+    // Cooperators that shards are moved away from.
+    evacuating: BTreeSet<String>,
+}
+
+impl Mover {
+    /// Creates a mover that balances shards with `policy` periodically.
+    pub fn new(uv: Universe, registry: Registry, policy: Arc<dyn BalancePolicy>) -> Self {
+        let inner = Inner {
+            next_id: 1,
+            moves: BTreeMap::new(),
+            evacuating: BTreeSet::new(),
+        };
+        let inner = Arc::new(Mutex::new(inner));
+        tokio::spawn(balance(
+            Arc::downgrade(&inner),
+            uv.clone(),
+            registry.clone(),
+            policy.clone(),
+        ));
+        Self {
+            inner,
+            uv,
+            registry,
+            policy,
+        }
+    }
+
+    /// Starts to move a shard to `target`.
+    pub async fn move_shard(&self, req: MoveShardRequest) -> Result<ShardMove> {
+        if req.target.is_empty() {
+            return Err(Error::invalid_argument("missing target cooperator"));
+        }
+        let placement = self
+            .uv
+            .shards()
+            .await
+            .into_iter()
+            .find(|p| p.dbname == req.dbname && p.coname == req.coname && p.shard == req.shard)
+            .ok_or_else(|| {
+                Error::NotFound(format!("shard {} of collection {}", req.shard, req.coname))
+            })?;
+        self.start(placement, req.target).await
+    }
+
+    pub async fn list(&self) -> Vec<ShardMove> {
+        let inner = self.inner.lock().await;
+        inner.moves.values().cloned().collect()
+    }
+
+    /// Moves all shards away from `cooperator`, or stops evacuating it if
+    /// `cancel` is set.
+    pub async fn evacuate(&self, cooperator: String, cancel: bool) -> Result<Vec<ShardMove>> {
+        {
+            let mut inner = self.inner.lock().await;
+            if cancel {
+                inner.evacuating.remove(&cooperator);
+                return Ok(Vec::new());
+            }
+            inner.evacuating.insert(cooperator);
+        }
+        self.rebalance().await
+    }
+
+    /// Starts the moves planned by the policy, and returns them.
+    pub async fn rebalance(&self) -> Result<Vec<ShardMove>> {
+        self.plan_moves(true).await
+    }
+
+    async fn plan_moves(&self, retry_failed: bool) -> Result<Vec<ShardMove>> {
+        let evacuating = self.inner.lock().await.evacuating.clone();
+        let cooperators: Vec<_> = self
+            .registry
+            .live()
+            .await
+            .into_iter()
+            .filter(|addr| !evacuating.contains(addr))
+            .collect();
+        let last_states = self.last_states().await;
+        let shards: Vec<_> = self
+            .uv
+            .shards()
+            .await
+            .into_iter()
+            .filter(|p| {
+                let key = (p.dbname.clone(), p.coname.clone(), p.shard);
+                match last_states.get(&key) {
+                    Some(State::Done) | None => true,
+                    Some(State::Failed) => retry_failed,
+                    // The shard is being moved.
+                    Some(_) => false,
+                }
+            })
+            .collect();
+        let mut moves = Vec::new();
+        for planned in self.policy.plan(&cooperators, &shards) {
+            match self.start(planned.placement, planned.target).await {
+                Ok(m) => moves.push(m),
+                // The shard is being moved by a concurrent request.
+                Err(Error::AlreadyExists(_)) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(moves)
+    }
+
+    // Returns the state of the last move of each shard.
+    async fn last_states(&self) -> BTreeMap<(String, String, u64), State> {
+        let inner = self.inner.lock().await;
+        inner
+            .moves
+            .values()
+            .map(|m| ((m.dbname.clone(), m.coname.clone(), m.shard), m.state()))
+            .collect()
+    }
+
+    async fn start(&self, placement: ShardPlacement, target: String) -> Result<ShardMove> {
+        let mut inner = self.inner.lock().await;
+        let moving = inner.moves.values().any(|m| {
+            !is_finished(m)
+                && m.dbname == placement.dbname
+                && m.coname == placement.coname
+                && m.shard == placement.shard
+        });
+        if moving {
+            return Err(Error::AlreadyExists(format!(
+                "move of shard {}",
+                placement.shard
+            )));
+        }
+        let id = inner.next_id;
+        inner.next_id += 1;
+        let m = ShardMove {
+            id,
+            dbname: placement.dbname,
+            coname: placement.coname,
+            shard: placement.shard,
+            source: placement.cooperator,
+            target,
+            ..Default::default()
+        };
+        inner.moves.insert(id, m.clone());
+        inner.prune();
+        tokio::spawn(self.clone().run(m.clone()));
+        Ok(m)
+    }
+
+    async fn run(self, m: ShardMove) {
+        let res = tokio::time::timeout(MOVE_TIMEOUT, self.transfer(&m)).await;
+        let (state, error) = match res {
+            Ok(Ok(())) => (State::Done, String::new()),
+            Ok(Err(err)) => (State::Failed, err.to_string()),
+            Err(_) => (State::Failed, "move timed out".to_owned()),
+        };
+        let mut inner = self.inner.lock().await;
+        if let Some(m) = inner.moves.get_mut(&m.id) {
+            m.set_state(state);
+            m.error = error;
+        }
+    }
+
+    async fn transfer(&self, m: &ShardMove) -> Result<()> {
+        self.set_state(m.id, State::CatchingUp).await;
+        self.catch_up(m, false).await?;
+        self.set_state(m.id, State::CuttingOver).await;
+        let db = self.uv.database(&m.dbname).await?;
+        if m.coname.is_empty() {
+            db.assign(&m.source, &m.target).await?;
+        } else {
+            db.assign_shard(&m.coname, m.shard, &m.source, &m.target)
+                .await?;
+        }
+        // Transactions that the source has accepted before the assignment
+        // may still append records. The source is fenced so that none is
+        // left behind when the target catches up again.
+        self.catch_up(m, true).await
+    }
+
+    // Waits until the target has applied the records that the source has
+    // applied, and fences the source first if `fence` is set. The log of a
+    // dead source is followed by the target anyway, so it only needs to open
+    // the shard.
+    async fn catch_up(&self, m: &ShardMove, fence: bool) -> Result<()> {
+        let req = CatchUpRequest {
+            dbname: m.dbname.clone(),
+            coname: m.coname.clone(),
+            shard: m.shard,
+            fence: false,
+        };
+        let live = self.registry.live().await;
+        let goal = if live.contains(&m.source) {
+            let req = CatchUpRequest {
+                fence,
+                ..req.clone()
+            };
+            catch_up(&m.source, req).await?
+        } else {
+            0
+        };
+        loop {
+            if catch_up(&m.target, req.clone()).await? >= goal {
+                return Ok(());
+            }
+            tokio::time::sleep(CATCH_UP_INTERVAL).await;
+        }
+    }
+
+    async fn set_state(&self, id: u64, state: State) {
+        let mut inner = self.inner.lock().await;
+        if let Some(m) = inner.moves.get_mut(&id) {
+            m.set_state(state);
+        }
+    }
+}
+
+impl Inner {
+    // Drops the oldest finished moves beyond the limit.
+    fn prune(&mut self) {
+        let finished: Vec<_> = self
+            .moves
+            .values()
+            .filter(|m| is_finished(m))
+            .map(|m| m.id)
+            .collect();
+        for id in finished
+            .iter()
+            .take(finished.len().saturating_sub(MAX_FINISHED_MOVES))
+        {
+            self.moves.remove(id);
+        }
+    }
+}
+
+fn is_finished(m: &ShardMove) -> bool {
+    matches!(m.state(), State::Done | State::Failed)
+}
+
+async fn catch_up(addr: &str, req: CatchUpRequest) -> Result<u64> {
+    let mut client = ShardHostClient::connect(addr.to_owned()).await?;
+    let res = client.catch_up(req).await?;
+    Ok(res.into_inner().sequence)
+}
+
+// Balances shards periodically until the mover is dropped.
+async fn balance(
+    inner: Weak<Mutex<Inner>>,
+    uv: Universe,
+    registry: Registry,
+    policy: Arc<dyn BalancePolicy>,
+) {
+    // The first round waits for cooperators to send heartbeats.
+    let start = tokio::time::Instant::now() + BALANCE_INTERVAL;
+    let mut interval = tokio::time::interval_at(start, BALANCE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let inner = match inner.upgrade() {
+            Some(inner) => inner,
+            None => break,
+        };
+        let mover = Mover {
+            inner,
+            uv: uv.clone(),
+            registry: registry.clone(),
+            policy: policy.clone(),
+        };
+        let _ = mover.plan_moves(false).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use engula_apis::*;
+
+    use super::*;
+    use crate::{ShardCountPolicy, Supervisor};
+
+    type TestResult = std::result::Result<(), Box<dyn std::error::Error>>;
+
+    // Nothing listens on these endpoints, so moves between them fail.
+    const SOURCE: &str = "http://127.0.0.1:1";
+    const TARGET: &str = "http://127.0.0.1:2";
+
+    fn sharded_collection_desc() -> CollectionDesc {
+        CollectionDesc {
+            name: "co".to_owned(),
+            sharding: Some(ShardingDesc {
+                kind: Some(sharding_desc::Kind::Hash(HashSharding { num_shards: 2 })),
+            }),
+            ..Default::default()
+        }
+    }
+
+    async fn admin(
+        sp: &Supervisor,
+        req: admin_request::Request,
+    ) -> Result<admin_response::Response> {
+        let req = AdminRequest { request: Some(req) };
+        sp.admin(req)
+            .await?
+            .response
+            .ok_or_else(|| Error::internal("missing admin response"))
+    }
+
+    // Waits until all moves are finished, and returns them.
+    async fn wait_moves(sp: &Supervisor) -> Result<Vec<ShardMove>> {
+        loop {
+            let req = admin_request::Request::ListShardMoves(ListShardMovesRequest {});
+            let moves = match admin(sp, req).await? {
+                admin_response::Response::ListShardMoves(res) => res.shard_moves,
+                _ => return Err(Error::internal("unexpected admin response")),
+            };
+            if moves.iter().all(is_finished) {
+                return Ok(moves);
+            }
+            tokio::time::sleep(CATCH_UP_INTERVAL).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn move_shard() -> TestResult {
+        let sp = Supervisor::new();
+        sp.heartbeat(SOURCE.to_owned()).await?;
+        let desc = DatabaseDesc {
+            name: "db".to_owned(),
+            ..Default::default()
+        };
+        sp.create_database(desc).await?;
+        let desc = sp
+            .create_collection("db".to_owned(), sharded_collection_desc())
+            .await?;
+        let shard = desc.shards[0].clone();
+        assert_eq!(shard.cooperator, SOURCE);
+
+        let req = MoveShardRequest {
+            dbname: "db".to_owned(),
+            coname: "co".to_owned(),
+            shard: shard.id,
+            target: String::new(),
+        };
+        let err = admin(&sp, admin_request::Request::MoveShard(req.clone()))
+            .await
+            .err()
+            .ok_or("move without a target is accepted")?;
+        assert!(matches!(err, Error::InvalidArgument(_)), "{}", err);
+        let missing = MoveShardRequest {
+            shard: 0,
+            target: TARGET.to_owned(),
+            ..req.clone()
+        };
+        let err = admin(&sp, admin_request::Request::MoveShard(missing))
+            .await
+            .err()
+            .ok_or("move of a missing shard is accepted")?;
+        assert!(matches!(err, Error::NotFound(_)), "{}", err);
+
+        let req = MoveShardRequest {
+            target: TARGET.to_owned(),
+            ..req
+        };
+        match admin(&sp, admin_request::Request::MoveShard(req)).await? {
+            admin_response::Response::MoveShard(res) => {
+                let m = res.shard_move.ok_or("missing shard move")?;
+                assert_eq!((m.shard, m.source.as_str()), (shard.id, SOURCE));
+            }
+            _ => return Err("unexpected admin response".into()),
+        }
+        // The source is unreachable, so the shard stays on it.
+        let moves = wait_moves(&sp).await?;
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves[0].state(), State::Failed);
+        assert!(!moves[0].error.is_empty());
+        let desc = sp
+            .describe_collection("db".to_owned(), "co".to_owned())
+            .await?;
+        assert_eq!(desc.shards[0].cooperator, SOURCE);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_failed_moves_on_request() -> TestResult {
+        let uv = Universe::new();
+        let registry = Registry::default();
+        let mover = Mover::new(uv.clone(), registry.clone(), Arc::new(ShardCountPolicy));
+        let desc = DatabaseDesc {
+            name: "db".to_owned(),
+            ..Default::default()
+        };
+        uv.create_database(desc).await?;
+        let db = uv.database("db").await?;
+        db.create_collection(sharded_collection_desc(), &[SOURCE.to_owned()])
+            .await?;
+        registry.heartbeat(SOURCE.to_owned()).await;
+        registry.heartbeat(TARGET.to_owned()).await;
+
+        // One of the shards is moved to balance them, which fails.
+        let moves = mover.rebalance().await?;
+        assert_eq!(moves.len(), 1);
+        while !mover.list().await.iter().all(is_finished) {
+            tokio::time::sleep(CATCH_UP_INTERVAL).await;
+        }
+        assert_eq!(mover.list().await[0].state(), State::Failed);
+
+        // The balancer skips the shard, while a request retries it.
+        assert!(mover.plan_moves(false).await?.is_empty());
+        let moves = mover.rebalance().await?;
+        assert_eq!(moves.len(), 1);
+        Ok(())
+    }
+}
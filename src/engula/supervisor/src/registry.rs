@@ -0,0 +1,46 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    collections::BTreeMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+
+/// A cooperator is considered dead if it doesn't send a heartbeat in this
+/// duration.
+const COOPERATOR_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The cooperators that send heartbeats to the supervisor.
+#[derive(Clone, Default)]
+pub struct Registry {
+    // The time of the last heartbeat of each cooperator.
+    cooperators: Arc<Mutex<BTreeMap<String, Instant>>>,
+}
+
+impl Registry {
+    pub async fn heartbeat(&self, addr: String) {
+        let mut cooperators = self.cooperators.lock().await;
+        cooperators.insert(addr, Instant::now());
+    }
+
+    /// Returns the endpoints of live cooperators, in order.
+    pub async fn live(&self) -> Vec<String> {
+        let mut cooperators = self.cooperators.lock().await;
+        cooperators.retain(|_, last| last.elapsed() < COOPERATOR_TIMEOUT);
+        cooperators.keys().cloned().collect()
+    }
+}
@@ -12,14 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{path::Path, sync::Arc};
+
 use engula_apis::*;
 use tonic::{Request, Response};
 
-use crate::{apis::*, Database, Error, Result, Universe};
+use crate::{
+    apis::*, BalancePolicy, Database, Error, Mover, Registry, Result, ShardCountPolicy, Universe,
+};
+
+const MANIFEST_NAME: &str = "MANIFEST";
 
 #[derive(Clone)]
 pub struct Server {
     uv: Universe,
+    registry: Registry,
+    mover: Mover,
 }
 
 impl Default for Server {
@@ -30,11 +38,32 @@ impl Default for Server {
 
 impl Server {
     pub fn new() -> Self {
+        Self::with_universe(Universe::new())
+    }
+
+    /// Opens a server that persists its metadata under `path`.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let uv = Universe::open(path.as_ref().join(MANIFEST_NAME)).await?;
+        Ok(Self::with_universe(uv))
+    }
+
+    fn with_universe(uv: Universe) -> Self {
+        let registry = Registry::default();
+        let policy = Arc::new(ShardCountPolicy);
+        let mover = Mover::new(uv.clone(), registry.clone(), policy);
         Self {
-            uv: Universe::new(),
+            uv,
+            registry,
+            mover,
         }
     }
 
+    /// Replaces the policy that balances shards over cooperators.
+    pub fn with_balance_policy(self, policy: impl BalancePolicy + 'static) -> Self {
+        let mover = Mover::new(self.uv.clone(), self.registry.clone(), Arc::new(policy));
+        Self { mover, ..self }
+    }
+
     pub fn into_service(self) -> supervisor_server::SupervisorServer<Self> {
         supervisor_server::SupervisorServer::new(self)
     }
@@ -58,18 +87,21 @@ impl Server {
             .request
             .ok_or_else(|| Error::invalid_argument("missing database request"))?;
         let res = match req {
-            database_request_union::Request::ListDatabases(_) => {
-                todo!();
+            database_request_union::Request::ListDatabases(req) => {
+                let res = self.handle_list_databases(req).await?;
+                database_response_union::Response::ListDatabases(res)
             }
             database_request_union::Request::CreateDatabase(req) => {
                 let res = self.handle_create_database(req).await?;
                 database_response_union::Response::CreateDatabase(res)
             }
-            database_request_union::Request::UpdateDatabase(_) => {
-                todo!();
+            database_request_union::Request::UpdateDatabase(req) => {
+                let res = self.handle_update_database(req).await?;
+                database_response_union::Response::UpdateDatabase(res)
             }
-            database_request_union::Request::DeleteDatabase(_) => {
-                todo!();
+            database_request_union::Request::DeleteDatabase(req) => {
+                let res = self.handle_delete_database(req).await?;
+                database_response_union::Response::DeleteDatabase(res)
             }
             database_request_union::Request::DescribeDatabase(req) => {
                 let res = self.handle_describe_database(req).await?;
@@ -81,6 +113,23 @@ impl Server {
         })
     }
 
+    async fn handle_list_databases(
+        &self,
+        req: ListDatabasesRequest,
+    ) -> Result<ListDatabasesResponse> {
+        let limit = req.page_size as usize;
+        let dbs = self.uv.list_databases(&req.page_token, limit).await;
+        let mut descs = Vec::with_capacity(dbs.len());
+        for db in dbs {
+            descs.push(db.desc().await);
+        }
+        let next_page_token = next_page_token(&descs, limit, |desc| &desc.name);
+        Ok(ListDatabasesResponse {
+            descs,
+            next_page_token,
+        })
+    }
+
     async fn handle_create_database(
         &self,
         req: CreateDatabaseRequest,
@@ -92,6 +141,25 @@ impl Server {
         Ok(CreateDatabaseResponse { desc: Some(desc) })
     }
 
+    async fn handle_update_database(
+        &self,
+        req: UpdateDatabaseRequest,
+    ) -> Result<UpdateDatabaseResponse> {
+        let desc = req
+            .desc
+            .ok_or_else(|| Error::invalid_argument("missing database description"))?;
+        let desc = self.uv.update_database(desc).await?;
+        Ok(UpdateDatabaseResponse { desc: Some(desc) })
+    }
+
+    async fn handle_delete_database(
+        &self,
+        req: DeleteDatabaseRequest,
+    ) -> Result<DeleteDatabaseResponse> {
+        self.uv.delete_database(&req.name).await?;
+        Ok(DeleteDatabaseResponse {})
+    }
+
     async fn handle_describe_database(
         &self,
         req: DescribeDatabaseRequest,
@@ -120,18 +188,21 @@ impl Server {
             .request
             .ok_or_else(|| Error::invalid_argument("missing collection request"))?;
         let res = match req {
-            collection_request_union::Request::ListCollections(_) => {
-                todo!();
+            collection_request_union::Request::ListCollections(req) => {
+                let res = self.handle_list_collections(db, req).await?;
+                collection_response_union::Response::ListCollections(res)
             }
             collection_request_union::Request::CreateCollection(req) => {
                 let res = self.handle_create_collection(db, req).await?;
                 collection_response_union::Response::CreateCollection(res)
             }
-            collection_request_union::Request::UpdateCollection(_) => {
-                todo!();
+            collection_request_union::Request::UpdateCollection(req) => {
+                let res = self.handle_update_collection(db, req).await?;
+                collection_response_union::Response::UpdateCollection(res)
             }
-            collection_request_union::Request::DeleteCollection(_) => {
-                todo!();
+            collection_request_union::Request::DeleteCollection(req) => {
+                let res = self.handle_delete_collection(db, req).await?;
+                collection_response_union::Response::DeleteCollection(res)
             }
             collection_request_union::Request::DescribeCollection(req) => {
                 let res = self.handle_describe_collection(db, req).await?;
@@ -143,6 +214,24 @@ impl Server {
         })
     }
 
+    async fn handle_list_collections(
+        &self,
+        db: Database,
+        req: ListCollectionsRequest,
+    ) -> Result<ListCollectionsResponse> {
+        let limit = req.page_size as usize;
+        let cos = db.list_collections(&req.page_token, limit).await;
+        let mut descs = Vec::with_capacity(cos.len());
+        for co in cos {
+            descs.push(co.desc().await);
+        }
+        let next_page_token = next_page_token(&descs, limit, |desc| &desc.name);
+        Ok(ListCollectionsResponse {
+            descs,
+            next_page_token,
+        })
+    }
+
     async fn handle_create_collection(
         &self,
         db: Database,
@@ -151,10 +240,32 @@ impl Server {
         let desc = req
             .desc
             .ok_or_else(|| Error::invalid_argument("missing collection description"))?;
-        let desc = db.create_collection(desc).await?;
+        let cooperators = self.registry.live().await;
+        let desc = db.create_collection(desc, &cooperators).await?;
         Ok(CreateCollectionResponse { desc: Some(desc) })
     }
 
+    async fn handle_update_collection(
+        &self,
+        db: Database,
+        req: UpdateCollectionRequest,
+    ) -> Result<UpdateCollectionResponse> {
+        let desc = req
+            .desc
+            .ok_or_else(|| Error::invalid_argument("missing collection description"))?;
+        let desc = db.update_collection(desc).await?;
+        Ok(UpdateCollectionResponse { desc: Some(desc) })
+    }
+
+    async fn handle_delete_collection(
+        &self,
+        db: Database,
+        req: DeleteCollectionRequest,
+    ) -> Result<DeleteCollectionResponse> {
+        db.delete_collection(&req.name).await?;
+        Ok(DeleteCollectionResponse {})
+    }
+
     async fn handle_describe_collection(
         &self,
         db: Database,
@@ -166,6 +277,89 @@ impl Server {
     }
 }
 
+impl Server {
+    async fn handle_heartbeat(&self, req: HeartbeatRequest) -> Result<HeartbeatResponse> {
+        if req.addr.is_empty() {
+            return Err(Error::invalid_argument("missing cooperator address"));
+        }
+        self.registry.heartbeat(req.addr).await;
+        Ok(HeartbeatResponse {})
+    }
+
+    async fn handle_list_cooperators(
+        &self,
+        _: ListCooperatorsRequest,
+    ) -> Result<ListCooperatorsResponse> {
+        let addrs = self.registry.live().await;
+        Ok(ListCooperatorsResponse { addrs })
+    }
+
+    async fn handle_locate_database(
+        &self,
+        req: LocateDatabaseRequest,
+    ) -> Result<LocateDatabaseResponse> {
+        let db = self.uv.database(&req.dbname).await?;
+        let cooperator = db.cooperator().await;
+        if !cooperator.is_empty() {
+            return Ok(LocateDatabaseResponse { cooperator });
+        }
+        // An unassigned database is assigned without a move. Its log is
+        // shared, so the cooperator recovers the collections from it once it
+        // leads the log.
+        let live = self.registry.live().await;
+        if live.is_empty() {
+            return Ok(LocateDatabaseResponse::default());
+        }
+        let id = db.desc().await.id;
+        let target = &live[id as usize % live.len()];
+        let cooperator = match db.assign("", target).await {
+            Ok(()) => target.clone(),
+            // Assigned by a concurrent request.
+            Err(Error::ConditionFailed(_)) => db.cooperator().await,
+            Err(err) => return Err(err),
+        };
+        Ok(LocateDatabaseResponse { cooperator })
+    }
+
+    async fn handle_admin(&self, req: AdminRequest) -> Result<AdminResponse> {
+        let req = req
+            .request
+            .ok_or_else(|| Error::invalid_argument("missing admin request"))?;
+        let res = match req {
+            admin_request::Request::MoveShard(req) => {
+                let shard_move = self.mover.move_shard(req).await?;
+                admin_response::Response::MoveShard(MoveShardResponse {
+                    shard_move: Some(shard_move),
+                })
+            }
+            admin_request::Request::ListShardMoves(_) => {
+                let shard_moves = self.mover.list().await;
+                admin_response::Response::ListShardMoves(ListShardMovesResponse { shard_moves })
+            }
+            admin_request::Request::Evacuate(req) => {
+                let shard_moves = self.mover.evacuate(req.cooperator, req.cancel).await?;
+                admin_response::Response::Evacuate(EvacuateResponse { shard_moves })
+            }
+            admin_request::Request::Rebalance(_) => {
+                let shard_moves = self.mover.rebalance().await?;
+                admin_response::Response::Rebalance(RebalanceResponse { shard_moves })
+            }
+        };
+        Ok(AdminResponse {
+            response: Some(res),
+        })
+    }
+}
+
+// Returns the token of the page after `descs`, which is the name of the last
+// entry if the page is full, or empty if there are no more entries.
+fn next_page_token<T>(descs: &[T], limit: usize, name: impl Fn(&T) -> &String) -> String {
+    match descs.last() {
+        Some(last) if limit > 0 && descs.len() == limit => name(last).clone(),
+        _ => String::new(),
+    }
+}
+
 type TonicResult<T> = std::result::Result<T, tonic::Status>;
 
 #[tonic::async_trait]
@@ -187,4 +381,37 @@ impl supervisor_server::Supervisor for Server {
         let res = self.handle_collection(req).await?;
         Ok(Response::new(res))
     }
+
+    async fn heartbeat(
+        &self,
+        req: Request<HeartbeatRequest>,
+    ) -> TonicResult<Response<HeartbeatResponse>> {
+        let req = req.into_inner();
+        let res = self.handle_heartbeat(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn list_cooperators(
+        &self,
+        req: Request<ListCooperatorsRequest>,
+    ) -> TonicResult<Response<ListCooperatorsResponse>> {
+        let req = req.into_inner();
+        let res = self.handle_list_cooperators(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn locate_database(
+        &self,
+        req: Request<LocateDatabaseRequest>,
+    ) -> TonicResult<Response<LocateDatabaseResponse>> {
+        let req = req.into_inner();
+        let res = self.handle_locate_database(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn admin(&self, req: Request<AdminRequest>) -> TonicResult<Response<AdminResponse>> {
+        let req = req.into_inner();
+        let res = self.handle_admin(req).await?;
+        Ok(Response::new(res))
+    }
 }
@@ -0,0 +1,125 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crc::{Crc, CRC_32_ISCSI};
+use engula_apis::*;
+
+use crate::{Error, Result};
+
+/// The largest number of shards of a collection.
+const MAX_SHARDS: usize = 1024;
+
+// Objects must hash to the same shards in every process and release, so a
+// checksum is used instead of `std::hash`.
+const HASH: Crc<u32> = Crc::<u32>::new(&CRC_32_ISCSI);
+
+/// Returns the shard that owns the object `id`, or `None` if the collection
+/// is not sharded.
+///
+/// Hash sharding assigns an object to the shard at the position of its hash
+/// modulo the number of shards, and range sharding assigns it to the shard
+/// whose range contains its id.
+pub fn shard_of<'a>(desc: &'a CollectionDesc, id: &[u8]) -> Option<&'a ShardDesc> {
+    match desc.sharding.as_ref()?.kind.as_ref()? {
+        sharding_desc::Kind::Hash(_) => {
+            if desc.shards.is_empty() {
+                return None;
+            }
+            let index = HASH.checksum(id) as usize % desc.shards.len();
+            desc.shards.get(index)
+        }
+        sharding_desc::Kind::Range(_) => desc.shards.iter().find(|shard| {
+            shard.start.as_slice() <= id && (shard.end.is_empty() || id < shard.end.as_slice())
+        }),
+    }
+}
+
+/// Returns the shard with `id` in a collection.
+pub fn shard_by_id(desc: &CollectionDesc, id: u64) -> Option<&ShardDesc> {
+    desc.shards.iter().find(|shard| shard.id == id)
+}
+
+/// Returns whether `shard` may contain objects in the range [start, end). An
+/// empty end is unbounded.
+pub fn shard_overlaps(shard: &ShardDesc, start: &[u8], end: &[u8]) -> bool {
+    (end.is_empty() || shard.start.as_slice() < end)
+        && (shard.end.is_empty() || start < shard.end.as_slice())
+}
+
+// Creates the shards defined by `sharding` with ids allocated from `next_id`,
+// and assigns them to `cooperators` in turn.
+pub(crate) fn new_shards(
+    sharding: &ShardingDesc,
+    next_id: &mut u64,
+    cooperators: &[String],
+) -> Result<Vec<ShardDesc>> {
+    let kind = sharding
+        .kind
+        .as_ref()
+        .ok_or_else(|| Error::invalid_argument("missing sharding kind"))?;
+    let ranges = match kind {
+        sharding_desc::Kind::Hash(hash) => {
+            let num_shards = hash.num_shards as usize;
+            if num_shards == 0 || num_shards > MAX_SHARDS {
+                return Err(Error::invalid_argument(format!(
+                    "number of shards must be in [1, {}]",
+                    MAX_SHARDS
+                )));
+            }
+            vec![(Vec::new(), Vec::new()); num_shards]
+        }
+        sharding_desc::Kind::Range(range) => {
+            let keys = &range.split_keys;
+            if keys.len() >= MAX_SHARDS {
+                return Err(Error::invalid_argument(format!(
+                    "number of split keys must be less than {}",
+                    MAX_SHARDS
+                )));
+            }
+            let ascending = keys.windows(2).all(|w| w[0] < w[1]);
+            if !ascending || keys.iter().any(|key| key.is_empty()) {
+                return Err(Error::invalid_argument(
+                    "split keys must be non-empty and ascending",
+                ));
+            }
+            let mut bounds = vec![Vec::new()];
+            bounds.extend(keys.iter().cloned());
+            bounds.push(Vec::new());
+            bounds
+                .windows(2)
+                .map(|w| (w[0].clone(), w[1].clone()))
+                .collect()
+        }
+    };
+    let shards = ranges
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start, end))| {
+            let id = *next_id;
+            *next_id += 1;
+            let cooperator = if cooperators.is_empty() {
+                String::new()
+            } else {
+                cooperators[i % cooperators.len()].clone()
+            };
+            ShardDesc {
+                id,
+                start,
+                end,
+                cooperator,
+            }
+        })
+        .collect();
+    Ok(shards)
+}
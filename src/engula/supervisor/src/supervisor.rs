@@ -12,14 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::path::Path;
+
 use engula_apis::*;
-use tonic::Request;
+use tonic::{transport::Channel, Request};
 
-use crate::{apis::supervisor_server::Supervisor as _, Error, Result, Server};
+use crate::{
+    apis::{
+        supervisor_client::SupervisorClient, supervisor_server::Supervisor as _, AdminRequest,
+        AdminResponse, HeartbeatRequest, ListCooperatorsRequest, LocateDatabaseRequest,
+    },
+    Error, Result, Server,
+};
 
 #[derive(Clone)]
 pub struct Supervisor {
-    server: Server,
+    inner: Inner,
+}
+
+#[derive(Clone)]
+enum Inner {
+    Local(Server),
+    Remote(SupervisorClient<Channel>),
 }
 
 impl Default for Supervisor {
@@ -31,13 +45,32 @@ impl Default for Supervisor {
 impl Supervisor {
     pub fn new() -> Self {
         Self {
-            server: Server::new(),
+            inner: Inner::Local(Server::new()),
         }
     }
 
+    /// Opens a supervisor that persists its metadata under `path`.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let server = Server::open(path).await?;
+        Ok(Self {
+            inner: Inner::Local(server),
+        })
+    }
+
+    /// Connects to a supervisor served at `url`.
+    pub async fn connect(url: impl Into<String>) -> Result<Self> {
+        let client = SupervisorClient::connect(url.into()).await?;
+        Ok(Self {
+            inner: Inner::Remote(client),
+        })
+    }
+
     pub async fn database(&self, req: DatabaseRequest) -> Result<DatabaseResponse> {
         let req = Request::new(req);
-        let res = self.server.database(req).await?;
+        let res = match &self.inner {
+            Inner::Local(server) => server.database(req).await?,
+            Inner::Remote(client) => client.clone().database(req).await?,
+        };
         Ok(res.into_inner())
     }
 
@@ -55,6 +88,18 @@ impl Supervisor {
             .ok_or_else(|| Error::internal("missing database response"))
     }
 
+    pub async fn create_database(&self, desc: DatabaseDesc) -> Result<DatabaseDesc> {
+        let req = CreateDatabaseRequest { desc: Some(desc) };
+        let req = database_request_union::Request::CreateDatabase(req);
+        let res = self.database_union(req).await?;
+        let desc = if let database_response_union::Response::CreateDatabase(res) = res {
+            res.desc
+        } else {
+            None
+        };
+        desc.ok_or_else(|| Error::internal("missing database description"))
+    }
+
     pub async fn describe_database(&self, name: String) -> Result<DatabaseDesc> {
         let req = DescribeDatabaseRequest { name };
         let req = database_request_union::Request::DescribeDatabase(req);
@@ -69,7 +114,10 @@ impl Supervisor {
 
     pub async fn collection(&self, req: CollectionRequest) -> Result<CollectionResponse> {
         let req = Request::new(req);
-        let res = self.server.collection(req).await?;
+        let res = match &self.inner {
+            Inner::Local(server) => server.collection(req).await?,
+            Inner::Remote(client) => client.clone().collection(req).await?,
+        };
         Ok(res.into_inner())
     }
 
@@ -89,6 +137,22 @@ impl Supervisor {
             .ok_or_else(|| Error::internal("missing collection response"))
     }
 
+    pub async fn create_collection(
+        &self,
+        dbname: String,
+        desc: CollectionDesc,
+    ) -> Result<CollectionDesc> {
+        let req = CreateCollectionRequest { desc: Some(desc) };
+        let req = collection_request_union::Request::CreateCollection(req);
+        let res = self.collection_union(dbname, req).await?;
+        let desc = if let collection_response_union::Response::CreateCollection(res) = res {
+            res.desc
+        } else {
+            None
+        };
+        desc.ok_or_else(|| Error::internal("missing collection description"))
+    }
+
     pub async fn describe_collection(
         &self,
         dbname: String,
@@ -105,3 +169,48 @@ impl Supervisor {
         desc.ok_or_else(|| Error::internal("missing collection description"))
     }
 }
+
+impl Supervisor {
+    /// Registers a cooperator that serves at `addr`. A cooperator must keep
+    /// sending heartbeats to stay registered.
+    pub async fn heartbeat(&self, addr: String) -> Result<()> {
+        let req = Request::new(HeartbeatRequest { addr });
+        match &self.inner {
+            Inner::Local(server) => server.heartbeat(req).await?,
+            Inner::Remote(client) => client.clone().heartbeat(req).await?,
+        };
+        Ok(())
+    }
+
+    /// Returns the endpoints of live cooperators.
+    pub async fn list_cooperators(&self) -> Result<Vec<String>> {
+        let req = Request::new(ListCooperatorsRequest {});
+        let res = match &self.inner {
+            Inner::Local(server) => server.list_cooperators(req).await?,
+            Inner::Remote(client) => client.clone().list_cooperators(req).await?,
+        };
+        Ok(res.into_inner().addrs)
+    }
+
+    /// Returns the endpoint of the cooperator that serves the unsharded
+    /// collections of a database, or an empty string if no cooperator is
+    /// live. An unassigned database is assigned to a live cooperator.
+    pub async fn locate_database(&self, dbname: String) -> Result<String> {
+        let req = Request::new(LocateDatabaseRequest { dbname });
+        let res = match &self.inner {
+            Inner::Local(server) => server.locate_database(req).await?,
+            Inner::Remote(client) => client.clone().locate_database(req).await?,
+        };
+        Ok(res.into_inner().cooperator)
+    }
+
+    /// Triggers or inspects shard moves.
+    pub async fn admin(&self, req: AdminRequest) -> Result<AdminResponse> {
+        let req = Request::new(req);
+        let res = match &self.inner {
+            Inner::Local(server) => server.admin(req).await?,
+            Inner::Remote(client) => client.clone().admin(req).await?,
+        };
+        Ok(res.into_inner())
+    }
+}
@@ -12,12 +12,21 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ops::Bound,
+    path::Path,
+    sync::Arc,
+};
 
 use engula_apis::*;
 use tokio::sync::Mutex;
 
-use crate::{Error, Result};
+use crate::{
+    apis::{manifest_edit::Edit, AssignDatabase, ManifestEdit, NextIds},
+    shard::new_shards,
+    Error, Manifest, Result, ShardPlacement,
+};
 
 #[derive(Clone)]
 pub struct Universe {
@@ -26,20 +35,84 @@ pub struct Universe {
 
 struct UniverseInner {
     next_id: u64,
-    databases: HashMap<String, Database>,
+    databases: BTreeMap<String, Database>,
+    manifest: Manifest,
 }
 
 impl Universe {
+    /// Creates a universe that keeps its metadata in memory only.
     pub fn new() -> Self {
+        Self::with_manifest(Manifest::default())
+    }
+
+    /// Opens a universe that persists its metadata in the manifest at
+    /// `path`, and recovers the metadata from it.
+    ///
+    /// The recovered manifest is compacted, so it holds no more than the
+    /// metadata plus the edits since the last open.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let (manifest, edits) = Manifest::open(path).await?;
+        let num_edits = edits.len();
+        let uv = Self::with_manifest(manifest);
+        uv.recover(edits).await?;
+        uv.compact(num_edits).await?;
+        Ok(uv)
+    }
+
+    fn with_manifest(manifest: Manifest) -> Self {
         let inner = UniverseInner {
             next_id: 1,
-            databases: HashMap::new(),
+            databases: BTreeMap::new(),
+            manifest,
         };
         Self {
             inner: Arc::new(Mutex::new(inner)),
         }
     }
 
+    async fn recover(&self, edits: Vec<ManifestEdit>) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        for edit in edits {
+            let edit = edit
+                .edit
+                .ok_or_else(|| Error::corrupted("missing manifest edit"))?;
+            inner.apply(edit).await;
+        }
+        Ok(())
+    }
+
+    // Rewrites the manifest with a snapshot of the metadata if that takes
+    // fewer edits than `num_edits`.
+    async fn compact(&self, num_edits: usize) -> Result<()> {
+        let inner = self.inner.lock().await;
+        let mut edits = vec![Edit::NextIds(NextIds {
+            database_id: 0,
+            next_id: inner.next_id,
+        })];
+        for db in inner.databases.values() {
+            let dbinner = db.inner.lock().await;
+            edits.push(Edit::PutDatabase(dbinner.desc.clone()));
+            edits.push(Edit::NextIds(NextIds {
+                database_id: dbinner.desc.id,
+                next_id: dbinner.next_id,
+            }));
+            if !dbinner.cooperator.is_empty() {
+                edits.push(Edit::AssignDatabase(AssignDatabase {
+                    database_id: dbinner.desc.id,
+                    cooperator: dbinner.cooperator.clone(),
+                }));
+            }
+            for co in dbinner.collections.values() {
+                edits.push(Edit::PutCollection(co.desc().await));
+            }
+        }
+        if edits.len() < num_edits {
+            let edits: Vec<ManifestEdit> = edits.into_iter().map(Into::into).collect();
+            inner.manifest.rewrite(&edits).await?;
+        }
+        Ok(())
+    }
+
     pub async fn database(&self, name: &str) -> Result<Database> {
         let inner = self.inner.lock().await;
         inner
@@ -56,10 +129,157 @@ impl Universe {
         }
         desc.id = inner.next_id;
         inner.next_id += 1;
-        let db = Database::new(desc.clone());
+        let edit = Edit::PutDatabase(desc.clone());
+        inner.manifest.append(edit.into()).await?;
+        let db = Database::new(desc.clone(), inner.manifest.clone());
         inner.databases.insert(desc.name.clone(), db);
         Ok(desc)
     }
+
+    /// Returns up to `limit` databases with names after `start`, in name
+    /// order. An empty start begins from the first database, and a limit of
+    /// zero returns all of them.
+    pub async fn list_databases(&self, start: &str, limit: usize) -> Vec<Database> {
+        let inner = self.inner.lock().await;
+        page(&inner.databases, start, limit)
+    }
+
+    /// Replaces the options of a database. Its id and name are unchanged.
+    pub async fn update_database(&self, desc: DatabaseDesc) -> Result<DatabaseDesc> {
+        // Holds the universe lock so that the edit is not logged after the
+        // database is deleted.
+        let inner = self.inner.lock().await;
+        let db = inner
+            .databases
+            .get(&desc.name)
+            .ok_or_else(|| Error::NotFound(format!("database {}", desc.name)))?;
+        let mut dbinner = db.inner.lock().await;
+        let desc = DatabaseDesc {
+            id: dbinner.desc.id,
+            ..desc
+        };
+        let edit = Edit::PutDatabase(desc.clone());
+        inner.manifest.append(edit.into()).await?;
+        dbinner.desc = desc.clone();
+        Ok(desc)
+    }
+
+    /// Returns the placements of all shards.
+    ///
+    /// The unsharded collections of a database are placed together as a
+    /// shard with an empty collection name and id zero, once the database is
+    /// assigned to a cooperator.
+    pub async fn shards(&self) -> Vec<ShardPlacement> {
+        let dbs: Vec<_> = self
+            .inner
+            .lock()
+            .await
+            .databases
+            .values()
+            .cloned()
+            .collect();
+        let mut shards = Vec::new();
+        for db in dbs {
+            let dbname = db.desc().await.name;
+            let cooperator = db.cooperator().await;
+            if !cooperator.is_empty() {
+                shards.push(ShardPlacement {
+                    dbname: dbname.clone(),
+                    coname: String::new(),
+                    shard: 0,
+                    cooperator,
+                });
+            }
+            let cos: Vec<_> = db
+                .inner
+                .lock()
+                .await
+                .collections
+                .values()
+                .cloned()
+                .collect();
+            for co in cos {
+                let desc = co.desc().await;
+                for shard in desc.shards {
+                    shards.push(ShardPlacement {
+                        dbname: dbname.clone(),
+                        coname: desc.name.clone(),
+                        shard: shard.id,
+                        cooperator: shard.cooperator,
+                    });
+                }
+            }
+        }
+        shards
+    }
+
+    /// Deletes a database and all its collections.
+    pub async fn delete_database(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        let db = inner
+            .databases
+            .get(name)
+            .ok_or_else(|| Error::NotFound(format!("database {}", name)))?;
+        let edit = Edit::DeleteDatabase(db.desc().await);
+        inner.manifest.append(edit.into()).await?;
+        inner.databases.remove(name);
+        Ok(())
+    }
+}
+
+impl UniverseInner {
+    // Applies a recovered edit. Ids are allocated after the largest one ever
+    // logged, so that the ids of deleted entries are not reused.
+    async fn apply(&mut self, edit: Edit) {
+        match edit {
+            Edit::PutDatabase(desc) => {
+                self.next_id = self.next_id.max(desc.id + 1);
+                if let Some(db) = self.databases.get(&desc.name) {
+                    db.inner.lock().await.desc = desc;
+                } else {
+                    let db = Database::new(desc.clone(), self.manifest.clone());
+                    self.databases.insert(desc.name, db);
+                }
+            }
+            Edit::DeleteDatabase(desc) => {
+                self.databases.remove(&desc.name);
+            }
+            Edit::PutCollection(desc) => {
+                // The database may be deleted before a concurrent edit is logged.
+                if let Some(db) = self.database_by_id(desc.parent_id).await {
+                    db.inner.lock().await.apply_put_collection(desc).await;
+                }
+            }
+            Edit::DeleteCollection(desc) => {
+                if let Some(db) = self.database_by_id(desc.parent_id).await {
+                    db.inner.lock().await.collections.remove(&desc.name);
+                }
+            }
+            Edit::AssignDatabase(assign) => {
+                if let Some(db) = self.database_by_id(assign.database_id).await {
+                    db.inner.lock().await.cooperator = assign.cooperator;
+                }
+            }
+            Edit::NextIds(ids) if ids.database_id == 0 => {
+                self.next_id = self.next_id.max(ids.next_id);
+            }
+            Edit::NextIds(ids) => {
+                if let Some(db) = self.database_by_id(ids.database_id).await {
+                    let mut dbinner = db.inner.lock().await;
+                    dbinner.next_id = dbinner.next_id.max(ids.next_id);
+                }
+            }
+        }
+    }
+
+    async fn database_by_id(&self, id: u64) -> Option<Database> {
+        for db in self.databases.values() {
+            if db.desc().await.id == id {
+                return Some(db.clone());
+            }
+        }
+        None
+    }
 }
 
 #[derive(Clone)]
@@ -69,16 +289,22 @@ pub struct Database {
 
 struct DatabaseInner {
     desc: DatabaseDesc,
+    // The cooperator that serves the unsharded collections, or empty if the
+    // database is not assigned yet.
+    cooperator: String,
     next_id: u64,
-    collections: HashMap<String, Collection>,
+    collections: BTreeMap<String, Collection>,
+    manifest: Manifest,
 }
 
 impl Database {
-    fn new(desc: DatabaseDesc) -> Self {
+    fn new(desc: DatabaseDesc, manifest: Manifest) -> Self {
         let inner = DatabaseInner {
             desc,
+            cooperator: String::new(),
             next_id: 1,
-            collections: HashMap::new(),
+            collections: BTreeMap::new(),
+            manifest,
         };
         Self {
             inner: Arc::new(Mutex::new(inner)),
@@ -89,6 +315,31 @@ impl Database {
         self.inner.lock().await.desc.clone()
     }
 
+    /// Returns the cooperator that serves the unsharded collections, or an
+    /// empty string if the database is not assigned yet.
+    pub async fn cooperator(&self) -> String {
+        self.inner.lock().await.cooperator.clone()
+    }
+
+    /// Assigns the unsharded collections to `target` if they are still
+    /// assigned to `source`.
+    pub async fn assign(&self, source: &str, target: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        if inner.cooperator != source {
+            return Err(Error::condition_failed(format!(
+                "database {} has moved to {}",
+                inner.desc.name, inner.cooperator
+            )));
+        }
+        let edit = Edit::AssignDatabase(AssignDatabase {
+            database_id: inner.desc.id,
+            cooperator: target.to_owned(),
+        });
+        inner.manifest.append(edit.into()).await?;
+        inner.cooperator = target.to_owned();
+        Ok(())
+    }
+
     pub async fn collection(&self, name: &str) -> Result<Collection> {
         let inner = self.inner.lock().await;
         inner
@@ -98,18 +349,135 @@ impl Database {
             .ok_or_else(|| Error::NotFound(format!("collection {}", name)))
     }
 
-    pub async fn create_collection(&self, mut desc: CollectionDesc) -> Result<CollectionDesc> {
+    /// Creates a collection. If it is sharded, its shards are assigned to
+    /// `cooperators` in turn.
+    pub async fn create_collection(
+        &self,
+        mut desc: CollectionDesc,
+        cooperators: &[String],
+    ) -> Result<CollectionDesc> {
+        check_indexes(&desc.indexes)?;
         let mut inner = self.inner.lock().await;
         if inner.collections.contains_key(&desc.name) {
             return Err(Error::AlreadyExists(format!("collection {}", desc.name)));
         }
-        desc.id = inner.next_id;
-        inner.next_id += 1;
+        let mut next_id = inner.next_id;
+        desc.id = next_id;
+        next_id += 1;
+        desc.shards = match &desc.sharding {
+            Some(sharding) => new_shards(sharding, &mut next_id, cooperators)?,
+            None => Vec::new(),
+        };
+        inner.next_id = next_id;
         desc.parent_id = inner.desc.id;
+        let edit = Edit::PutCollection(desc.clone());
+        inner.manifest.append(edit.into()).await?;
         let co = Collection::new(desc.clone());
         inner.collections.insert(desc.name.clone(), co);
         Ok(desc)
     }
+
+    /// Returns up to `limit` collections with names after `start`, in name
+    /// order. An empty start begins from the first collection, and a limit
+    /// of zero returns all of them.
+    pub async fn list_collections(&self, start: &str, limit: usize) -> Vec<Collection> {
+        let inner = self.inner.lock().await;
+        page(&inner.collections, start, limit)
+    }
+
+    /// Replaces the options of a collection. Its id, name, parent and shards
+    /// are unchanged, and its indexes must be the same.
+    ///
+    /// Indexes can't be changed, since the objects written before an index
+    /// is declared would not be indexed. The object engine can't iterate over
+    /// the objects to backfill the index yet.
+    pub async fn update_collection(&self, desc: CollectionDesc) -> Result<CollectionDesc> {
+        // Holds the database lock so that the edit is not logged after the
+        // collection is deleted.
+        let inner = self.inner.lock().await;
+        let co = inner
+            .collections
+            .get(&desc.name)
+            .ok_or_else(|| Error::NotFound(format!("collection {}", desc.name)))?;
+        let mut coinner = co.inner.lock().await;
+        if desc.indexes != coinner.desc.indexes {
+            return Err(Error::invalid_argument(
+                "indexes of a collection can't be changed",
+            ));
+        }
+        let desc = CollectionDesc {
+            id: coinner.desc.id,
+            parent_id: coinner.desc.parent_id,
+            sharding: coinner.desc.sharding.clone(),
+            shards: coinner.desc.shards.clone(),
+            ..desc
+        };
+        let edit = Edit::PutCollection(desc.clone());
+        inner.manifest.append(edit.into()).await?;
+        coinner.desc = desc.clone();
+        Ok(desc)
+    }
+
+    /// Assigns a shard of a collection to `target` if it is still assigned to
+    /// `source`.
+    pub async fn assign_shard(
+        &self,
+        coname: &str,
+        shard: u64,
+        source: &str,
+        target: &str,
+    ) -> Result<CollectionDesc> {
+        let inner = self.inner.lock().await;
+        let co = inner
+            .collections
+            .get(coname)
+            .ok_or_else(|| Error::NotFound(format!("collection {}", coname)))?;
+        let mut coinner = co.inner.lock().await;
+        let mut desc = coinner.desc.clone();
+        let shard = desc
+            .shards
+            .iter_mut()
+            .find(|s| s.id == shard)
+            .ok_or_else(|| Error::NotFound(format!("shard {} of collection {}", shard, coname)))?;
+        if shard.cooperator != source {
+            return Err(Error::condition_failed(format!(
+                "shard {} has moved to {}",
+                shard.id, shard.cooperator
+            )));
+        }
+        shard.cooperator = target.to_owned();
+        let edit = Edit::PutCollection(desc.clone());
+        inner.manifest.append(edit.into()).await?;
+        coinner.desc = desc.clone();
+        Ok(desc)
+    }
+
+    pub async fn delete_collection(&self, name: &str) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        let co = inner
+            .collections
+            .get(name)
+            .ok_or_else(|| Error::NotFound(format!("collection {}", name)))?;
+        let edit = Edit::DeleteCollection(co.desc().await);
+        inner.manifest.append(edit.into()).await?;
+        inner.collections.remove(name);
+        Ok(())
+    }
+}
+
+impl DatabaseInner {
+    async fn apply_put_collection(&mut self, desc: CollectionDesc) {
+        self.next_id = self.next_id.max(desc.id + 1);
+        for shard in &desc.shards {
+            self.next_id = self.next_id.max(shard.id + 1);
+        }
+        if let Some(co) = self.collections.get(&desc.name) {
+            co.inner.lock().await.desc = desc;
+        } else {
+            let co = Collection::new(desc.clone());
+            self.collections.insert(desc.name, co);
+        }
+    }
 }
 
 #[derive(Clone)]
@@ -133,3 +501,142 @@ impl Collection {
         self.inner.lock().await.desc.clone()
     }
 }
+
+impl From<Edit> for ManifestEdit {
+    fn from(edit: Edit) -> Self {
+        Self { edit: Some(edit) }
+    }
+}
+
+// Checks that indexes have unique, nonempty names.
+fn check_indexes(indexes: &[IndexDesc]) -> Result<()> {
+    let mut names = BTreeSet::new();
+    for index in indexes {
+        if index.name.is_empty() {
+            return Err(Error::invalid_argument("missing index name"));
+        }
+        if !names.insert(index.name.as_str()) {
+            return Err(Error::AlreadyExists(format!("index {}", index.name)));
+        }
+    }
+    Ok(())
+}
+
+fn page<T: Clone>(entries: &BTreeMap<String, T>, start: &str, limit: usize) -> Vec<T> {
+    let start = if start.is_empty() {
+        Bound::Unbounded
+    } else {
+        Bound::Excluded(start)
+    };
+    let limit = if limit > 0 { limit } else { usize::MAX };
+    entries
+        .range::<str, _>((start, Bound::Unbounded))
+        .take(limit)
+        .map(|(_, v)| v.clone())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{fs, io::AsyncWriteExt};
+
+    use super::*;
+
+    fn database_desc(name: &str) -> DatabaseDesc {
+        DatabaseDesc {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    fn collection_desc(name: &str) -> CollectionDesc {
+        CollectionDesc {
+            name: name.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn recover_from_manifest() -> Result<()> {
+        let tmp = tempdir::TempDir::new("manifest")?;
+        let path = tmp.path().join("MANIFEST");
+
+        let uv = Universe::open(&path).await?;
+        let desc = uv.create_database(database_desc("db")).await?;
+        uv.create_database(database_desc("deleted")).await?;
+        uv.delete_database("deleted").await?;
+        let db = uv.database("db").await?;
+        let mut co = collection_desc("co");
+        co.sharding = Some(ShardingDesc {
+            kind: Some(sharding_desc::Kind::Hash(HashSharding { num_shards: 2 })),
+        });
+        let co = db.create_collection(co, &["a".to_owned()]).await?;
+        db.create_collection(collection_desc("deleted"), &[])
+            .await?;
+        db.delete_collection("deleted").await?;
+        db.assign("", "a").await?;
+        let co = db
+            .update_collection(CollectionDesc { ttl_ms: 1000, ..co })
+            .await?;
+        drop((uv, db));
+
+        // Tears the last record, as if the supervisor crashed while
+        // appending it.
+        let mut file = fs::OpenOptions::new().append(true).open(&path).await?;
+        file.write_all(&[1, 2, 3, 4, 100, 0, 0, 0, 1, 2]).await?;
+        drop(file);
+
+        // Reopens twice, since the first open compacts the manifest.
+        for _ in 0..2 {
+            let uv = Universe::open(&path).await?;
+            assert_eq!(uv.database("db").await?.desc().await, desc);
+            assert!(uv.database("deleted").await.is_err());
+            let db = uv.database("db").await?;
+            assert_eq!(db.cooperator().await, "a");
+            assert_eq!(db.collection("co").await?.desc().await, co);
+            assert!(db.collection("deleted").await.is_err());
+            drop((uv, db));
+        }
+
+        // Ids of deleted entries are not reused.
+        let uv = Universe::open(&path).await?;
+        let new_db = uv.create_database(database_desc("new")).await?;
+        assert_eq!(new_db.id, desc.id + 2);
+        let db = uv.database("db").await?;
+        let new_co = db.create_collection(collection_desc("new"), &[]).await?;
+        let deleted_id = co.shards.iter().map(|s| s.id).max().unwrap() + 1;
+        assert_eq!(new_co.id, deleted_id + 1);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_index_changes() -> Result<()> {
+        let uv = Universe::new();
+        uv.create_database(database_desc("db")).await?;
+        let db = uv.database("db").await?;
+        let index = IndexDesc {
+            name: "value".to_owned(),
+            ..Default::default()
+        };
+        let co = CollectionDesc {
+            indexes: vec![index],
+            ..collection_desc("co")
+        };
+        let co = db.create_collection(co, &[]).await?;
+
+        let err = db
+            .update_collection(CollectionDesc {
+                indexes: Vec::new(),
+                ..co.clone()
+            })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidArgument(_)), "{}", err);
+        let updated = db
+            .update_collection(CollectionDesc { ttl_ms: 1000, ..co })
+            .await?;
+        assert_eq!(updated.ttl_ms, 1000);
+        Ok(())
+    }
+}
@@ -12,6 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod router;
 mod server;
+mod shard;
+
+pub use engula_cooperator::Journal;
+
 // server
-pub use self::server::Server;
+pub use self::{
+    router::Router,
+    server::Server,
+    shard::{split_txn, MergePlan},
+};
@@ -0,0 +1,289 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
+
+use engula_apis::*;
+use engula_common::{Error, Result};
+use engula_cooperator::{Cooperator, WatchStream};
+use engula_supervisor::Supervisor;
+use tokio::sync::Mutex;
+
+// Covers the lease of a log, after which a new owner is elected to lead it.
+const MAX_ATTEMPTS: usize = 30;
+const RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Forwards requests to the cooperators registered in the supervisor.
+///
+/// A shard is served by the cooperator that the supervisor assigns it to, and
+/// the unsharded collections of a database, as well as shards that are not
+/// assigned yet, by the cooperator that the supervisor assigns the database
+/// to. A cooperator rejects requests until it leads the log of a shard,
+/// e.g. right after a move, so the router retries them for a while and looks
+/// up the assignment of the database again. Requests that fail after they are
+/// sent are not retried, since they may have been applied.
+#[derive(Clone)]
+pub struct Router {
+    supervisor: Supervisor,
+    inner: Arc<Mutex<Inner>>,
+}
+
+#[derive(Default)]
+struct Inner {
+    cooperators: HashMap<String, Cooperator>,
+    // The cooperator that each database is assigned to.
+    owners: HashMap<String, String>,
+}
+
+impl Router {
+    pub fn new(supervisor: Supervisor) -> Self {
+        Self {
+            supervisor,
+            inner: Arc::new(Mutex::new(Inner::default())),
+        }
+    }
+
+    /// Executes a database transaction on the cooperator at `addr`, or on the
+    /// cooperator of the database if `addr` is empty.
+    pub async fn database_txn(
+        &self,
+        addr: &str,
+        req: DatabaseTxnRequest,
+    ) -> Result<DatabaseTxnResponse> {
+        let dbname = req.name.clone();
+        let mut res = self
+            .retry(&dbname, addr, |co| {
+                let req = TxnRequest {
+                    requests: vec![req.clone()],
+                };
+                async move { co.txn(req).await }
+            })
+            .await?;
+        res.responses
+            .pop()
+            .ok_or_else(|| Error::internal("missing database response"))
+    }
+
+    /// Watches a collection on the cooperator at `addr`, or on the cooperator
+    /// of the database if `addr` is empty.
+    pub async fn watch(&self, addr: &str, req: WatchRequest) -> Result<WatchStream> {
+        let dbname = req.dbname.clone();
+        self.retry(&dbname, addr, |co| {
+            let req = req.clone();
+            async move { co.watch(req).await }
+        })
+        .await
+    }
+
+    // Retries `f` while the cooperator doesn't serve the request yet or can't
+    // be connected to.
+    async fn retry<F, Fut, T>(&self, dbname: &str, addr: &str, f: F) -> Result<T>
+    where
+        F: Fn(Cooperator) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let err = match self.route(dbname, addr, attempt > 0).await {
+                Ok((owner, co)) => match f(co).await {
+                    Ok(res) => return Ok(res),
+                    // The connection failed, probably because the cooperator
+                    // is down. Like a timeout, this doesn't tell whether a
+                    // write was applied, so the error is returned as is. The
+                    // cooperator is reconnected next time.
+                    Err(Error::Unknown(err)) => {
+                        let mut inner = self.inner.lock().await;
+                        inner.cooperators.remove(&owner);
+                        return Err(Error::Unknown(err));
+                    }
+                    Err(err) => err,
+                },
+                Err(err) => err,
+            };
+            match err {
+                // The cooperator is unavailable, or it doesn't lead the log
+                // yet or anymore.
+                Error::NotLeader(_) if attempt + 1 < MAX_ATTEMPTS => {
+                    attempt += 1;
+                    tokio::time::sleep(RETRY_INTERVAL).await;
+                }
+                err => return Err(err),
+            }
+        }
+    }
+
+    // Returns the cooperator at `addr`, or the cooperator of the database if
+    // `addr` is empty, whose assignment is looked up again if `refresh` is
+    // set.
+    async fn route(&self, dbname: &str, addr: &str, refresh: bool) -> Result<(String, Cooperator)> {
+        let addr = if addr.is_empty() {
+            self.locate(dbname, refresh).await?
+        } else {
+            addr.to_owned()
+        };
+        // The lock is not held across requests to the supervisor or the
+        // cooperators, which would block the requests to other databases.
+        if let Some(co) = self.inner.lock().await.cooperators.get(&addr) {
+            return Ok((addr, co.clone()));
+        }
+        let co = Cooperator::connect(addr.clone()).await.map_err(|err| {
+            Error::not_leader(format!("cooperator {} is unavailable: {}", addr, err))
+        })?;
+        // Another request may have connected to the same cooperator meanwhile.
+        let mut inner = self.inner.lock().await;
+        let co = inner.cooperators.entry(addr.clone()).or_insert(co).clone();
+        Ok((addr, co))
+    }
+
+    // Returns the cooperator that a database is assigned to.
+    async fn locate(&self, dbname: &str, refresh: bool) -> Result<String> {
+        if !refresh {
+            if let Some(addr) = self.inner.lock().await.owners.get(dbname) {
+                return Ok(addr.clone());
+            }
+        }
+        let addr = self.supervisor.locate_database(dbname.to_owned()).await?;
+        if addr.is_empty() {
+            return Err(Error::not_leader(format!(
+                "database {} is not assigned to any cooperator",
+                dbname
+            )));
+        }
+        let mut inner = self.inner.lock().await;
+        inner.owners.insert(dbname.to_owned(), addr.clone());
+        Ok(addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::TcpListener, sync::oneshot, task::JoinHandle};
+    use tokio_stream::wrappers::TcpListenerStream;
+
+    use super::*;
+
+    type TestResult = std::result::Result<(), Box<dyn std::error::Error>>;
+
+    // Serves a supervisor in another tier, and returns a client of it.
+    async fn serve_supervisor() -> Result<Supervisor> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let server = engula_supervisor::Server::new();
+        tokio::spawn(async move {
+            tonic::transport::Server::builder()
+                .add_service(server.into_service())
+                .serve_with_incoming(TcpListenerStream::new(listener))
+                .await
+                .unwrap();
+        });
+        Supervisor::connect(url).await
+    }
+
+    // Serves a cooperator registered in the supervisor until the returned
+    // sender fires.
+    async fn serve_cooperator(
+        sp: &Supervisor,
+    ) -> Result<(String, oneshot::Sender<()>, JoinHandle<()>)> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        let server = engula_cooperator::Server::new(sp.clone());
+        let (tx, rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            let shutdown = async {
+                rx.await.ok();
+            };
+            tonic::transport::Server::builder()
+                .add_service(server.into_service())
+                .serve_with_incoming_shutdown(TcpListenerStream::new(listener), shutdown)
+                .await
+                .unwrap();
+        });
+        sp.heartbeat(url.clone()).await?;
+        Ok((url, tx, handle))
+    }
+
+    fn txn_request(func: Function, args: Vec<Value>) -> DatabaseTxnRequest {
+        let expr = Expr {
+            from: Some(expr::From::Id(b"id".to_vec())),
+            call: Some(CallExpr {
+                func: func as i32,
+                args: args.into_iter().map(Into::into).collect(),
+            }),
+            ..Default::default()
+        };
+        let coreq = CollectionTxnRequest {
+            name: "co".to_owned(),
+            exprs: vec![expr],
+            ..Default::default()
+        };
+        DatabaseTxnRequest {
+            name: "db".to_owned(),
+            requests: vec![coreq],
+            ..Default::default()
+        }
+    }
+
+    // Loads the object that the requests write from the cooperator at `url`.
+    async fn load(url: &str) -> Result<Option<Value>> {
+        let co = Cooperator::connect(url.to_owned()).await?;
+        let req = TxnRequest {
+            requests: vec![txn_request(Function::Load, vec![])],
+        };
+        let res = co.txn(req).await?;
+        Ok(res.responses[0].responses[0].results[0].values[0]
+            .value
+            .clone())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn route_to_assigned_cooperator() -> TestResult {
+        let sp = serve_supervisor().await?;
+        let desc = DatabaseDesc {
+            name: "db".to_owned(),
+            ..Default::default()
+        };
+        sp.create_database(desc).await?;
+        let desc = CollectionDesc {
+            name: "co".to_owned(),
+            ..Default::default()
+        };
+        sp.create_collection("db".to_owned(), desc).await?;
+
+        // The database is assigned to the only live cooperator on first use.
+        let (owner, shutdown, handle) = serve_cooperator(&sp).await?;
+        let router = Router::new(sp.clone());
+        let req = txn_request(Function::Store, vec![Value::I64Value(1)]);
+        router.database_txn("", req).await?;
+        assert_eq!(sp.locate_database("db".to_owned()).await?, owner);
+        assert_eq!(load(&owner).await?, Some(Value::I64Value(1)));
+
+        // The owner is still assigned after it is down. The write fails, since
+        // the router can't tell whether the owner has applied it, and so do
+        // the retries, instead of going to another cooperator that doesn't
+        // have the objects of the database.
+        let (other, _shutdown, _) = serve_cooperator(&sp).await?;
+        shutdown.send(()).ok();
+        handle.await?;
+        let req = txn_request(Function::Store, vec![Value::I64Value(2)]);
+        assert!(router.database_txn("", req.clone()).await.is_err());
+        let err = router
+            .database_txn("", req)
+            .await
+            .err()
+            .ok_or("write to a dead owner succeeds")?;
+        assert!(matches!(err, Error::NotLeader(_)), "{}", err);
+        assert_eq!(load(&other).await?, None);
+        Ok(())
+    }
+}
@@ -12,14 +12,64 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::{collections::BTreeMap, path::Path};
+
 use engula_apis::*;
-use engula_cooperator::Cooperator;
-use engula_supervisor::Supervisor;
+use engula_common::{Error, Result};
+use engula_cooperator::{Cooperator, Journal, WatchStream};
+use engula_supervisor::{shard_overlaps, Supervisor};
+use futures::stream;
 use tonic::{Request, Response};
 
+use crate::{split_txn, Router};
+
 pub struct Server {
     supervisor: Supervisor,
-    cooperator: Cooperator,
+    cooperator: Cooperators,
+}
+
+enum Cooperators {
+    // A cooperator in the same process.
+    Local(Cooperator),
+    // Cooperators discovered from the supervisor.
+    Remote(Router),
+}
+
+impl Cooperators {
+    // Returns the group of the requests to a shard. The local cooperator
+    // serves all shards, so that a split transaction is still atomic.
+    fn group_of(&self, shard: &ShardDesc) -> String {
+        match self {
+            Cooperators::Local(_) => String::new(),
+            Cooperators::Remote(_) => shard.cooperator.clone(),
+        }
+    }
+
+    async fn database_txn(
+        &self,
+        group: &str,
+        req: DatabaseTxnRequest,
+    ) -> Result<DatabaseTxnResponse> {
+        match self {
+            Cooperators::Local(co) => {
+                let req = TxnRequest {
+                    requests: vec![req],
+                };
+                let mut res = co.txn(req).await?;
+                res.responses
+                    .pop()
+                    .ok_or_else(|| Error::internal("missing database response"))
+            }
+            Cooperators::Remote(router) => router.database_txn(group, req).await,
+        }
+    }
+
+    async fn watch(&self, group: &str, req: WatchRequest) -> Result<WatchStream> {
+        match self {
+            Cooperators::Local(co) => co.watch(req).await,
+            Cooperators::Remote(router) => router.watch(group, req).await,
+        }
+    }
 }
 
 impl Default for Server {
@@ -34,22 +84,117 @@ impl Server {
         let cooperator = Cooperator::new(supervisor.clone());
         Self {
             supervisor,
-            cooperator,
+            cooperator: Cooperators::Local(cooperator),
         }
     }
 
+    /// Opens a server that persists the supervisor metadata under `path`.
+    /// If `journal` is given, changes to objects are logged to it and flushed
+    /// into an object engine under `path`.
+    pub async fn open(path: impl AsRef<Path>, journal: Option<Journal>) -> Result<Self> {
+        let path = path.as_ref();
+        let supervisor = Supervisor::open(path).await?;
+        let cooperator = Cooperator::open(supervisor.clone(), path, journal).await?;
+        Ok(Self {
+            supervisor,
+            cooperator: Cooperators::Local(cooperator),
+        })
+    }
+
+    /// Connects to the supervisor served at `url`, and forwards transactions
+    /// to the cooperators registered in it.
+    pub async fn connect(url: impl Into<String>) -> Result<Self> {
+        let supervisor = Supervisor::connect(url).await?;
+        let router = Router::new(supervisor.clone());
+        Ok(Self {
+            supervisor,
+            cooperator: Cooperators::Remote(router),
+        })
+    }
+
     pub fn into_service(self) -> engula_server::EngulaServer<Self> {
         engula_server::EngulaServer::new(self)
     }
 }
 
+impl Server {
+    async fn handle_txn(&self, req: TxnRequest) -> Result<TxnResponse> {
+        let mut res = TxnResponse::default();
+        for dbreq in req.requests {
+            let dbres = self.handle_database_txn(dbreq).await?;
+            res.responses.push(dbres);
+        }
+        Ok(res)
+    }
+
+    // Splits a transaction by the shards of its collections. Parts on
+    // different cooperators are committed independently, so only read-only
+    // transactions can span cooperators.
+    async fn handle_database_txn(&self, req: DatabaseTxnRequest) -> Result<DatabaseTxnResponse> {
+        let (groups, plan) = split_txn(&self.supervisor, req, |shard| {
+            self.cooperator.group_of(shard)
+        })
+        .await?;
+        let mut responses = BTreeMap::new();
+        for (group, req) in groups {
+            let res = self.cooperator.database_txn(&group, req).await?;
+            responses.insert(group, res);
+        }
+        plan.merge(responses)
+    }
+
+    // Watches every shard of a collection that overlaps the requested range.
+    // Events of different shards are interleaved as they arrive, since their
+    // sequences are not comparable, so only events of the same shard are in
+    // commit order.
+    async fn handle_watch(&self, req: WatchRequest) -> Result<WatchStream> {
+        let desc = self
+            .supervisor
+            .describe_collection(req.dbname.clone(), req.coname.clone())
+            .await?;
+        if desc.shards.is_empty() {
+            return self.cooperator.watch("", req).await;
+        }
+        let shards: Vec<_> = desc
+            .shards
+            .iter()
+            .filter(|shard| shard_overlaps(shard, &req.start, &req.end))
+            .collect();
+        // Sequences are assigned by each shard, so a watch resumes from a
+        // sequence only within a single shard.
+        if req.start_sequence != 0 && shards.len() > 1 {
+            return Err(Error::invalid_argument(
+                "resuming a watch across shards is not supported",
+            ));
+        }
+        let mut streams = Vec::new();
+        for shard in shards {
+            let group = self.cooperator.group_of(shard);
+            let req = WatchRequest {
+                shard: shard.id,
+                ..req.clone()
+            };
+            streams.push(self.cooperator.watch(&group, req).await?);
+        }
+        Ok(Box::pin(stream::select_all(streams)))
+    }
+}
+
 type TonicResult<T> = std::result::Result<T, tonic::Status>;
 
 #[tonic::async_trait]
 impl engula_server::Engula for Server {
+    type WatchStream = WatchStream;
+
     async fn txn(&self, req: Request<TxnRequest>) -> TonicResult<Response<TxnResponse>> {
         let req = req.into_inner();
-        let res = self.cooperator.txn(req).await?;
+        let res = self.handle_txn(req).await?;
+        Ok(Response::new(res))
+    }
+
+    async fn watch(&self, req: Request<WatchRequest>) -> TonicResult<Response<Self::WatchStream>> {
+        let req = req.into_inner();
+        let res = self.handle_watch(req).await?;
         Ok(Response::new(res))
     }
 
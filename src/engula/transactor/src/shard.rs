@@ -0,0 +1,513 @@
+// Copyright 2022 The Engula Authors.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use engula_apis::*;
+use engula_common::{Error, Result};
+use engula_cooperator::{is_read_only, source_ids};
+use engula_supervisor::{shard_by_id, shard_of, shard_overlaps, Supervisor};
+
+/// Splits the requests of a database transaction by the shards of their
+/// collections.
+///
+/// Returns the requests grouped by `group_of` the shards they go to, and a
+/// plan to merge the responses of the groups. Requests of an unsharded
+/// collection go to the group of an empty name.
+///
+/// An expression must not read objects in other shards than its own one.
+/// Scans go to the shards that overlap their ranges, and lookups go to all
+/// shards, since each shard indexes its own objects.
+///
+/// The groups are committed independently, so a transaction that changes
+/// objects must not span multiple groups.
+pub async fn split_txn(
+    supervisor: &Supervisor,
+    req: DatabaseTxnRequest,
+    group_of: impl Fn(&ShardDesc) -> String,
+) -> Result<(BTreeMap<String, DatabaseTxnRequest>, MergePlan)> {
+    let mut groups = BTreeMap::new();
+    let mut plans = Vec::new();
+    let new_group = || DatabaseTxnRequest {
+        name: req.name.clone(),
+        ts: req.ts,
+        ..Default::default()
+    };
+    for coreq in req.requests.iter().cloned() {
+        let desc = supervisor
+            .describe_collection(req.name.clone(), coreq.name.clone())
+            .await?;
+        let mut plan = CollectionPlan {
+            num_exprs: coreq.exprs.len(),
+            scans: coreq.scans.clone(),
+            num_lookups: coreq.lookups.len(),
+            parts: Vec::new(),
+        };
+        for (part, coreq) in split_collection_txn(&desc, coreq)? {
+            let group = match shard_by_id(&desc, coreq.shard) {
+                Some(shard) => group_of(shard),
+                None => String::new(),
+            };
+            let dbreq = groups.entry(group.clone()).or_insert_with(new_group);
+            plan.parts.push(Part {
+                group,
+                index: dbreq.requests.len(),
+                ..part
+            });
+            dbreq.requests.push(coreq);
+        }
+        plans.push(plan);
+    }
+    if groups.len() > 1 {
+        let mut exprs = req.requests.iter().flat_map(|coreq| &coreq.exprs);
+        if !exprs.all(is_read_only) {
+            return Err(Error::invalid_argument(
+                "transaction changes objects on multiple cooperators",
+            ));
+        }
+    }
+    // Executes an empty transaction anyway, which checks the database and
+    // returns a timestamp.
+    if groups.is_empty() {
+        groups.insert(String::new(), new_group());
+    }
+    Ok((groups, MergePlan { plans }))
+}
+
+// Splits the requests of a collection into requests of its shards.
+fn split_collection_txn(
+    desc: &CollectionDesc,
+    req: CollectionTxnRequest,
+) -> Result<Vec<(Part, CollectionTxnRequest)>> {
+    if desc.shards.is_empty() {
+        let part = Part {
+            exprs: (0..req.exprs.len()).collect(),
+            scans: (0..req.scans.len()).collect(),
+            lookups: (0..req.lookups.len()).collect(),
+            ..Default::default()
+        };
+        return Ok(vec![(part, req)]);
+    }
+    let mut parts = BTreeMap::new();
+    for (i, expr) in req.exprs.iter().enumerate() {
+        let id = match &expr.from {
+            Some(expr::From::Id(id)) => id,
+            _ => return Err(Error::invalid_argument("missing object id")),
+        };
+        let shard = shard_of(desc, id).ok_or_else(|| Error::internal("missing object shard"))?;
+        for source in source_ids(expr) {
+            if shard_of(desc, source).map(|s| s.id) != Some(shard.id) {
+                return Err(Error::invalid_argument(
+                    "expression reads objects in other shards",
+                ));
+            }
+        }
+        let (part, coreq) = part_of(&mut parts, &req.name, shard.id);
+        part.exprs.push(i);
+        coreq.exprs.push(expr.clone());
+    }
+    for (i, scan) in req.scans.iter().enumerate() {
+        for shard in &desc.shards {
+            if shard_overlaps(shard, &scan.start, &scan.end) {
+                let (part, coreq) = part_of(&mut parts, &req.name, shard.id);
+                part.scans.push(i);
+                coreq.scans.push(scan.clone());
+            }
+        }
+    }
+    for (i, lookup) in req.lookups.iter().enumerate() {
+        for shard in &desc.shards {
+            let (part, coreq) = part_of(&mut parts, &req.name, shard.id);
+            part.lookups.push(i);
+            coreq.lookups.push(lookup.clone());
+        }
+    }
+    Ok(parts.into_values().collect())
+}
+
+fn part_of<'a>(
+    parts: &'a mut BTreeMap<u64, (Part, CollectionTxnRequest)>,
+    name: &str,
+    shard: u64,
+) -> &'a mut (Part, CollectionTxnRequest) {
+    parts.entry(shard).or_insert_with(|| {
+        let req = CollectionTxnRequest {
+            name: name.to_owned(),
+            shard,
+            ..Default::default()
+        };
+        (Part::default(), req)
+    })
+}
+
+/// A plan to merge the responses of a split transaction.
+pub struct MergePlan {
+    plans: Vec<CollectionPlan>,
+}
+
+struct CollectionPlan {
+    num_exprs: usize,
+    scans: Vec<ScanExpr>,
+    num_lookups: usize,
+    parts: Vec<Part>,
+}
+
+// A part of the requests of a collection, which is the request at `index` in
+// the transaction of `group`.
+#[derive(Default)]
+struct Part {
+    group: String,
+    index: usize,
+    // The positions of the expressions, scans and lookups in the original
+    // request.
+    exprs: Vec<usize>,
+    scans: Vec<usize>,
+    lookups: Vec<usize>,
+}
+
+impl MergePlan {
+    /// Merges the responses of the groups into the response of the original
+    /// transaction, as if it was not split.
+    pub fn merge(
+        self,
+        responses: BTreeMap<String, DatabaseTxnResponse>,
+    ) -> Result<DatabaseTxnResponse> {
+        let mut res = DatabaseTxnResponse::default();
+        let mut groups = BTreeMap::new();
+        for (group, dbres) in responses {
+            res.ts = res.ts.max(dbres.ts);
+            let coreses: Vec<_> = dbres.responses.into_iter().map(Some).collect();
+            groups.insert(group, coreses);
+        }
+        for plan in self.plans {
+            let mut results = vec![ExprResult::default(); plan.num_exprs];
+            let mut scans = vec![Vec::new(); plan.scans.len()];
+            let mut lookups = vec![Vec::new(); plan.num_lookups];
+            for part in plan.parts {
+                let cores = groups
+                    .get_mut(&part.group)
+                    .and_then(|coreses| coreses.get_mut(part.index))
+                    .and_then(|cores| cores.take())
+                    .ok_or_else(|| Error::internal("missing collection response"))?;
+                for (i, result) in part.exprs.into_iter().zip(cores.results) {
+                    results[i] = result;
+                }
+                for (i, result) in part.scans.into_iter().zip(cores.scans) {
+                    scans[i].push(result);
+                }
+                for (i, result) in part.lookups.into_iter().zip(cores.lookups) {
+                    lookups[i].push(result);
+                }
+            }
+            let scans = plan
+                .scans
+                .iter()
+                .zip(scans)
+                .map(|(scan, results)| merge_scans(scan, results))
+                .collect();
+            let lookups = lookups.into_iter().map(merge_lookups).collect();
+            res.responses.push(CollectionTxnResponse {
+                results,
+                scans,
+                lookups,
+            });
+        }
+        Ok(res)
+    }
+}
+
+// Merges the results of a scan on multiple shards in the order of ids, up to
+// the limit of the scan.
+fn merge_scans(scan: &ScanExpr, mut results: Vec<ScanResult>) -> ScanResult {
+    if results.len() == 1 {
+        return results.pop().unwrap_or_default();
+    }
+    let mut objects = Vec::new();
+    for result in results {
+        let mut values = result.values.into_iter();
+        for id in result.ids {
+            objects.push((id, values.next()));
+        }
+    }
+    objects.sort_by(|a, b| a.0.cmp(&b.0));
+    if scan.limit > 0 {
+        objects.truncate(scan.limit as usize);
+    }
+    let mut result = ScanResult::default();
+    for (id, value) in objects {
+        result.ids.push(id);
+        result.values.extend(value);
+    }
+    result
+}
+
+// Merges the results of a lookup on multiple shards in the order of ids.
+fn merge_lookups(mut results: Vec<LookupResult>) -> LookupResult {
+    if results.len() == 1 {
+        return results.pop().unwrap_or_default();
+    }
+    let mut objects = Vec::new();
+    for result in results {
+        objects.extend(result.ids.into_iter().zip(result.values));
+    }
+    objects.sort_by(|a, b| a.0.cmp(&b.0));
+    let (ids, values) = objects.into_iter().unzip();
+    LookupResult { ids, values }
+}
+
+#[cfg(test)]
+mod tests {
+    use engula_cooperator::Cooperator;
+
+    use super::*;
+
+    type TestResult = std::result::Result<(), Box<dyn std::error::Error>>;
+
+    async fn create_collection(
+        sp: &Supervisor,
+        dbname: &str,
+        coname: &str,
+        sharding: Option<ShardingDesc>,
+    ) -> TestResult {
+        let desc = DatabaseDesc {
+            name: dbname.to_owned(),
+            ..Default::default()
+        };
+        match sp.create_database(desc).await {
+            Ok(_) | Err(Error::AlreadyExists(_)) => {}
+            Err(err) => return Err(err.into()),
+        }
+        // Objects are indexed by their values.
+        let index = IndexDesc {
+            name: "value".to_owned(),
+            ..Default::default()
+        };
+        let desc = CollectionDesc {
+            name: coname.to_owned(),
+            sharding,
+            indexes: vec![index],
+            ..Default::default()
+        };
+        sp.create_collection(dbname.to_owned(), desc).await?;
+        Ok(())
+    }
+
+    fn call(id: &[u8], func: Function, args: Vec<Value>) -> Expr {
+        Expr {
+            from: Some(expr::From::Id(id.to_vec())),
+            call: Some(CallExpr {
+                func: func as i32,
+                args: args.into_iter().map(Into::into).collect(),
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn txn_request(coname: &str, exprs: Vec<Expr>, scans: Vec<ScanExpr>) -> DatabaseTxnRequest {
+        let coreq = CollectionTxnRequest {
+            name: coname.to_owned(),
+            exprs,
+            scans,
+            ..Default::default()
+        };
+        DatabaseTxnRequest {
+            name: "db".to_owned(),
+            requests: vec![coreq],
+            ..Default::default()
+        }
+    }
+
+    // Executes a transaction the way the transactor does. Shards are assigned
+    // to the cooperators in turn by their ids, and a missing cooperator fails
+    // the requests to it.
+    async fn execute(
+        sp: &Supervisor,
+        cos: &[Option<Cooperator>],
+        req: DatabaseTxnRequest,
+    ) -> Result<DatabaseTxnResponse> {
+        let group_of = |shard: &ShardDesc| (shard.id as usize % cos.len()).to_string();
+        let (groups, plan) = split_txn(sp, req, group_of).await?;
+        let mut responses = BTreeMap::new();
+        for (group, req) in groups {
+            let req = TxnRequest {
+                requests: vec![req],
+            };
+            let index: usize = group.parse().unwrap_or_default();
+            let co = cos[index]
+                .as_ref()
+                .ok_or_else(|| Error::not_leader("cooperator is unavailable"))?;
+            let mut res = co.txn(req).await?;
+            let res = res
+                .responses
+                .pop()
+                .ok_or_else(|| Error::internal("missing database response"))?;
+            responses.insert(group, res);
+        }
+        plan.merge(responses)
+    }
+
+    // Runs the same requests on a collection, and returns the responses
+    // without timestamps or the errors.
+    async fn run(sp: &Supervisor, cos: &[Option<Cooperator>], coname: &str) -> Vec<String> {
+        const IDS: [&[u8]; 6] = [b"a", b"b", b"c", b"d", b"e", b"f"];
+        let load_all = || {
+            let exprs = IDS
+                .iter()
+                .map(|id| call(id, Function::Load, vec![]))
+                .collect();
+            txn_request(coname, exprs, vec![])
+        };
+        let stores = IDS
+            .iter()
+            .enumerate()
+            .map(|(i, id)| call(id, Function::Store, vec![Value::I64Value(i as i64)]))
+            .collect();
+        let mut updates = vec![
+            call(b"b", Function::Add, vec![Value::I64Value(10)]),
+            call(b"e", Function::Add, vec![Value::I64Value(10)]),
+        ];
+        updates.extend(load_all().requests[0].exprs.clone());
+        // The condition on `d` fails, so the store on `a` must not apply.
+        let aborted = vec![
+            call(b"a", Function::Store, vec![Value::I64Value(100)]),
+            call(b"d", Function::IfNotExists, vec![]),
+        ];
+        let scans = vec![
+            ScanExpr {
+                start: b"b".to_vec(),
+                end: b"e".to_vec(),
+                ..Default::default()
+            },
+            ScanExpr {
+                limit: 4,
+                ids_only: true,
+                ..Default::default()
+            },
+        ];
+        let mut lookups = txn_request(coname, vec![], vec![]);
+        lookups.requests[0].lookups = [14, 2]
+            .into_iter()
+            .map(|key| LookupExpr {
+                index: "value".to_owned(),
+                key: Some(Value::I64Value(key).into()),
+            })
+            .collect();
+        let requests = vec![
+            txn_request(coname, stores, vec![]),
+            txn_request(coname, updates, vec![]),
+            txn_request(coname, aborted, vec![]),
+            load_all(),
+            txn_request(coname, vec![call(b"c", Function::Reset, vec![])], scans),
+            load_all(),
+            lookups,
+        ];
+        let mut outputs = Vec::new();
+        for req in requests {
+            let output = match execute(sp, cos, req).await {
+                Ok(res) => format!("{:?}", res.responses),
+                Err(err) => err.to_string(),
+            };
+            outputs.push(output);
+        }
+        outputs
+    }
+
+    #[tokio::test]
+    async fn sharded_like_unsharded() -> TestResult {
+        let sp = Supervisor::new();
+        let cos = [Some(Cooperator::new(sp.clone()))];
+        let hash = ShardingDesc {
+            kind: Some(sharding_desc::Kind::Hash(HashSharding { num_shards: 4 })),
+        };
+        let range = ShardingDesc {
+            kind: Some(sharding_desc::Kind::Range(RangeSharding {
+                split_keys: vec![b"c".to_vec(), b"e".to_vec()],
+            })),
+        };
+        create_collection(&sp, "db", "plain", None).await?;
+        create_collection(&sp, "db", "hash", Some(hash)).await?;
+        create_collection(&sp, "db", "range", Some(range)).await?;
+
+        let expected = run(&sp, &cos, "plain").await;
+        assert_eq!(run(&sp, &cos, "hash").await, expected);
+        assert_eq!(run(&sp, &cos, "range").await, expected);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_cross_shard_reads() -> TestResult {
+        let sp = Supervisor::new();
+        let cos = [Some(Cooperator::new(sp.clone()))];
+        let range = ShardingDesc {
+            kind: Some(sharding_desc::Kind::Range(RangeSharding {
+                split_keys: vec![b"m".to_vec()],
+            })),
+        };
+        create_collection(&sp, "db", "co", Some(range)).await?;
+        let union = call(b"a", Function::Union, vec![Value::BlobValue(b"z".to_vec())]);
+        let req = txn_request("co", vec![union], vec![]);
+        let err = execute(&sp, &cos, req)
+            .await
+            .err()
+            .ok_or("cross-shard read is accepted")?;
+        assert!(matches!(err, Error::InvalidArgument(_)), "{}", err);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_cross_cooperator_writes() -> TestResult {
+        let sp = Supervisor::new();
+        let mut cos = [
+            Some(Cooperator::new(sp.clone())),
+            Some(Cooperator::new(sp.clone())),
+        ];
+        let range = ShardingDesc {
+            kind: Some(sharding_desc::Kind::Range(RangeSharding {
+                split_keys: vec![b"m".to_vec()],
+            })),
+        };
+        create_collection(&sp, "db", "co", Some(range)).await?;
+        let store = |id: &[u8], v| call(id, Function::Store, vec![Value::I64Value(v)]);
+        let load = |id: &[u8]| call(id, Function::Load, vec![]);
+        for id in [b"a", b"z"] {
+            execute(&sp, &cos, txn_request("co", vec![store(id, 1)], vec![])).await?;
+        }
+
+        // The shards of `a` and `z` are on different cooperators, so the
+        // writes to them would not commit atomically.
+        let req = txn_request("co", vec![store(b"a", 2), store(b"z", 2)], vec![]);
+        let err = execute(&sp, &cos, req)
+            .await
+            .err()
+            .ok_or("cross-cooperator write is accepted")?;
+        assert!(matches!(err, Error::InvalidArgument(_)), "{}", err);
+        let req = txn_request("co", vec![load(b"a"), load(b"z")], vec![]);
+        let res = execute(&sp, &cos, req.clone()).await?;
+        let values: Vec<_> = res.responses[0]
+            .results
+            .iter()
+            .map(|result| result.values[0].value.clone())
+            .collect();
+        assert_eq!(values, vec![Some(Value::I64Value(1)); 2]);
+
+        // Reads fail as a whole if one of the cooperators fails.
+        cos[1] = None;
+        let err = execute(&sp, &cos, req)
+            .await
+            .err()
+            .ok_or("partial read is accepted")?;
+        assert!(matches!(err, Error::NotLeader(_)), "{}", err);
+        Ok(())
+    }
+}
@@ -97,6 +97,14 @@ impl Engine {
         Ok(receiver)
     }
 
+    pub(crate) async fn close_stream(&self, stream_id: u64) -> Result<()> {
+        let mut inner = self.inner.lock().await;
+        if inner.streams.remove(&stream_id).is_some() {
+            inner.active_channel.remove(stream_id).await??;
+        }
+        Ok(())
+    }
+
     pub async fn close(&self) {
         let mut inner = self.inner.lock().await;
         if let Some((join_handle, flag)) = inner.worker_handle.take() {
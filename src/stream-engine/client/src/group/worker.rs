@@ -143,7 +143,6 @@ impl ActionChannel {
         receiver
     }
 
-    #[allow(dead_code)]
     #[inline(always)]
     pub(crate) fn remove(&self, stream_id: u64) -> oneshot::Receiver<Result<()>> {
         let (sender, receiver) = oneshot::channel();
@@ -34,6 +34,7 @@ pub use stream_engine_common::{
 
 pub use self::{
     engine::Engine,
+    reader::StreamReader,
     stream::{EpochState, Role, Stream},
     tenant::Tenant,
 };
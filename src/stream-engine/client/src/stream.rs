@@ -124,6 +124,15 @@ impl Stream {
     pub async fn truncate(&self, sequence: u64) -> Result<()> {
         self.inner.channel.on_truncate(sequence.into()).await?
     }
+
+    /// Stops observing the stream, so that the master elects one of the
+    /// other observers as the leader once this one times out. The
+    /// subscribed epoch states end, and the stream must not be appended to
+    /// afterwards.
+    pub async fn close(&self) -> Result<()> {
+        let stream_id = self.inner.stream_client.stream_id();
+        self.inner.engine.close_stream(stream_id).await
+    }
 }
 
 struct StreamInner {